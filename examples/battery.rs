@@ -42,7 +42,7 @@ fn main() -> ! {
     loop {
         display.clear().unwrap();
         FONT.render_aligned(
-            format_args!("Voltage: {}V", battery.read()),
+            format_args!("Voltage: {:.2}V", battery.read_oversampled::<16>().unwrap()),
             Point::new(
                 display.bounding_box().center().x,
                 display.bounding_box().center().y,
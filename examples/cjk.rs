@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+// Chinese and Japanese text with a font read from a flash partition.
+//
+// Flash the example with the partition table in assets/partitions-font.csv,
+// then convert a CJK font, e.g. Noto Sans CJK, with `epd-font` and write it to
+// the `font` partition:
+//
+//   epd-font NotoSansCJKsc-Regular.otf -o cjk-24.f4 --size 24 \
+//       --ranges 0x20-0x7e,0xb0,0x3000-0x30ff,0x4e00-0x9fff,0xff00-0xffef
+//   espflash write-bin 0x410000 cjk-24.f4
+
+extern crate alloc;
+extern crate lilygo_epd47;
+
+use esp_backtrace as _;
+use esp_hal::{delay::Delay, prelude::*};
+use esp_println::println;
+use esp_storage::FlashStorage;
+use lilygo_epd47::{
+    display::Rectangle,
+    pin_config,
+    Display,
+    DrawMode,
+    FlashFont,
+    FontStyle,
+    LayoutConfig,
+    Partition,
+    TextLayout,
+};
+
+static LABELS: [(&str, &str); 4] = [
+    ("温度", "23.5 °C"),
+    ("湿度", "48 %"),
+    ("東京の天気", "晴れ"),
+    ("次の電車", "12:05 品川行き"),
+];
+
+static NOTICE: &str = "本日は点検のため、午後三時から五時まで一部の機能がご利用いただけません。\
+ご不便をおかけしますが、ご理解のほどよろしくお願いいたします。";
+
+#[entry]
+fn main() -> ! {
+    esp_println::logger::init_logger_from_env();
+
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+
+    // Create PSRAM allocator
+    esp_alloc::psram_allocator!(peripherals.PSRAM, esp_hal::psram);
+
+    let mut display = Display::new(
+        pin_config!(peripherals),
+        peripherals.DMA,
+        peripherals.LCD_CAM,
+        peripherals.RMT,
+    )
+    .expect("Failed to initialize display");
+
+    let delay = Delay::new();
+
+    let mut flash = FlashStorage::new();
+    let partition = Partition::find(&mut flash, "font")
+        .unwrap()
+        .expect("No font partition, see the comment at the top");
+    // glyphs are read on demand, the 128 most recent ones are kept in RAM
+    let font = FlashFont::new(flash, partition.offset, partition.size, 128)
+        .expect("No font in the partition");
+    println!("font with {} glyphs", font.glyph_count());
+
+    delay.delay_millis(100);
+    display.power_on();
+    delay.delay_millis(10);
+    display.clear().unwrap();
+
+    // labels and values in two columns
+    let mut y = 60 + font.ascent();
+    for (label, value) in LABELS {
+        display.draw_text(&font, label, 60, y, 0).unwrap();
+        display.draw_text(&font, value, 360, y, 4).unwrap();
+        y += font.line_height() + 12;
+    }
+
+    // a notice wrapped to the width of the screen, CJK text has no spaces so
+    // the lines are broken between any two characters
+    let style = FontStyle::new(font, 0);
+    let area = Rectangle {
+        x: 60,
+        y: y + 20,
+        width: Display::WIDTH - 120,
+        height: Display::HEIGHT - y - 40,
+    };
+    let layout = TextLayout::new(NOTICE, &style, area, &LayoutConfig::default());
+    display.draw_page(&layout, &style, 0).unwrap();
+
+    display.flush(DrawMode::BlackOnWhite).unwrap();
+    println!(
+        "glyph cache: {} hits, {} misses",
+        style.font.cache_hits(),
+        style.font.cache_misses()
+    );
+    display.power_off();
+
+    loop {}
+}
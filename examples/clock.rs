@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate lilygo_epd47;
+
+use core::format_args;
+
+use embedded_graphics::prelude::*;
+use embedded_graphics_core::pixelcolor::{Gray4, GrayColor};
+use esp_backtrace as _;
+use esp_hal::{
+    delay::Delay,
+    i2c::master::{Config, I2c},
+    prelude::*,
+};
+use lilygo_epd47::{pin_config, DateTime, Display, DrawMode, Rtc};
+use u8g2_fonts::FontRenderer;
+
+static FONT: FontRenderer = FontRenderer::new::<u8g2_fonts::fonts::u8g2_font_spleen32x64_mr>();
+
+#[entry]
+fn main() -> ! {
+    esp_println::logger::init_logger_from_env();
+
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+
+    // Create PSRAM allocator
+    esp_alloc::psram_allocator!(peripherals.PSRAM, esp_hal::psram);
+
+    let mut display = Display::new(
+        pin_config!(peripherals),
+        peripherals.DMA,
+        peripherals.LCD_CAM,
+        peripherals.RMT,
+    )
+    .expect("Failed to initialize display");
+
+    let i2c = I2c::new(peripherals.I2C0, Config::default())
+        .with_sda(peripherals.GPIO18)
+        .with_scl(peripherals.GPIO17);
+    let mut rtc = Rtc::new(i2c);
+    if !rtc.is_clock_valid().unwrap() {
+        log::warn!("RTC lost its time, resetting");
+        rtc.set_datetime(&DateTime::from_timestamp(0).unwrap()).unwrap();
+    }
+
+    let delay = Delay::new();
+
+    loop {
+        let now = rtc.datetime().unwrap();
+        display.power_on();
+        delay.delay_millis(10);
+        display.clear().unwrap();
+        FONT.render_aligned(
+            format_args!(
+                "{:04}-{:02}-{:02} {:02}:{:02}",
+                now.year, now.month, now.day, now.hours, now.minutes
+            ),
+            display.bounding_box().center(),
+            u8g2_fonts::types::VerticalPosition::Center,
+            u8g2_fonts::types::HorizontalAlignment::Center,
+            u8g2_fonts::types::FontColor::Transparent(Gray4::BLACK),
+            &mut display,
+        )
+        .unwrap();
+        display.flush(DrawMode::BlackOnWhite).unwrap();
+        display.power_off();
+        // wait for the next full minute
+        delay.delay_millis((60 - now.seconds as u32) * 1000);
+    }
+}
@@ -4,7 +4,7 @@
 extern crate alloc;
 extern crate lilygo_epd47;
 
-use core::{format_args, time::Duration};
+use core::{format_args, ptr::addr_of, time::Duration};
 
 use embedded_graphics::prelude::*;
 use embedded_graphics_core::{
@@ -24,22 +24,15 @@ use esp_hal::{
     },
     Cpu,
 };
-use lilygo_epd47::{pin_config, Display, DrawMode};
+use lilygo_epd47::{pin_config, Display, DisplayState, DrawMode, RefreshPolicy};
 use u8g2_fonts::FontRenderer;
 
 static FONT: FontRenderer = FontRenderer::new::<u8g2_fonts::fonts::u8g2_font_spleen16x32_mr>();
 
-#[ram(rtc_fast)]
-static mut CYCLE: u16 = 0;
+const SLEEP_DURATION: Duration = Duration::from_secs(30);
 
 #[ram(rtc_fast)]
-static mut LAST_RECT: Rectangle = Rectangle {
-    top_left: Point { x: 0, y: 0 },
-    size: Size {
-        width: 0,
-        height: 0,
-    },
-};
+static mut STATE: DisplayState = DisplayState::new();
 
 #[entry]
 fn main() -> ! {
@@ -64,54 +57,61 @@ fn main() -> ! {
     let reason = reset_reason(Cpu::ProCpu).unwrap_or(SocResetReason::ChipPowerOn);
     let wake_reason = wakeup_cause();
 
+    // continue from the previous cycle, do a full clear every few cycles to
+    // remove the ghosting
+    display.set_refresh_policy(RefreshPolicy {
+        max_partial_updates: Some(8),
+        ..Default::default()
+    });
+    if display.restore(unsafe { &*addr_of!(STATE) }) {
+        display.add_elapsed(SLEEP_DURATION.as_millis() as u32);
+    }
+    let cycle = display.stats().refreshes;
+    let last_rect: Rectangle = display
+        .last_drawn()
+        .map(Into::into)
+        .unwrap_or(Rectangle::zero());
+
     // turn screen on
     display.power_on();
     delay.delay_millis(20);
-    // clear
-    let cycle = unsafe { CYCLE };
-    let last_rect = unsafe { LAST_RECT };
-
-    if cycle > 0 && cycle % 5 != 0 {
-        display.fill_solid(&last_rect, Gray4::WHITE).unwrap();
-        display.flush(DrawMode::WhiteOnBlack).unwrap();
-    } else {
-        display.clear().unwrap();
+    // clear: erase the previous text
+    match display.last_drawn() {
+        Some(last) => {
+            display.fill_solid(&last.into(), Gray4::WHITE).unwrap();
+            display.flush(DrawMode::WhiteOnBlack).unwrap();
+        }
+        None => display.clear().unwrap(),
     }
     // write out reset and wake reason
-    let rect = FONT
-        .render_aligned(
-            format_args!(
-                "Reset Reason: {:?}\nWake reason: {:?}\nCycle: {}\nRect: ({}, {}, {}, {})",
-                reason,
-                wake_reason,
-                cycle,
-                last_rect.top_left.x,
-                last_rect.top_left.y,
-                last_rect.size.width,
-                last_rect.size.height,
-            ),
-            Point::new(
-                display.bounding_box().center().x,
-                display.bounding_box().center().y,
-            ),
-            u8g2_fonts::types::VerticalPosition::Baseline,
-            u8g2_fonts::types::HorizontalAlignment::Center,
-            u8g2_fonts::types::FontColor::WithBackground {
-                fg: Gray4::BLACK,
-                bg: Gray4::WHITE,
-            },
-            &mut display,
-        )
-        .unwrap();
+    FONT.render_aligned(
+        format_args!(
+            "Reset Reason: {:?}\nWake reason: {:?}\nRefreshes: {}\nRect: ({}, {}, {}, {})",
+            reason,
+            wake_reason,
+            cycle,
+            last_rect.top_left.x,
+            last_rect.top_left.y,
+            last_rect.size.width,
+            last_rect.size.height,
+        ),
+        Point::new(
+            display.bounding_box().center().x,
+            display.bounding_box().center().y,
+        ),
+        u8g2_fonts::types::VerticalPosition::Baseline,
+        u8g2_fonts::types::HorizontalAlignment::Center,
+        u8g2_fonts::types::FontColor::WithBackground {
+            fg: Gray4::BLACK,
+            bg: Gray4::WHITE,
+        },
+        &mut display,
+    )
+    .unwrap();
     display.flush(DrawMode::BlackOnWhite).unwrap();
     // turn screen off
     display.power_off();
-    unsafe {
-        if let Some(rect) = rect {
-            LAST_RECT = rect;
-        }
-        CYCLE += 1;
-    }
+    unsafe { STATE = display.state() };
 
     delay.delay_millis(100);
 
@@ -119,7 +119,7 @@ fn main() -> ! {
     rtc_cfg.set_rtc_fastmem_pd_en(false);
     rtc_cfg.set_rtc_slowmem_pd_en(false);
 
-    let timer = TimerWakeupSource::new(Duration::from_secs(30));
+    let timer = TimerWakeupSource::new(SLEEP_DURATION);
     rtc.sleep(&rtc_cfg, &[&timer]);
 
     loop {}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate lilygo_epd47;
+
+use esp_backtrace as _;
+use esp_hal::{delay::Delay, prelude::*};
+use esp_println::println;
+use lilygo_epd47::{pin_config, DecodeOptions, Display, DrawMode, Jpeg};
+
+#[entry]
+fn main() -> ! {
+    esp_println::logger::init_logger_from_env();
+
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+
+    // Create PSRAM allocator
+    esp_alloc::psram_allocator!(peripherals.PSRAM, esp_hal::psram);
+
+    let mut display = Display::new(
+        pin_config!(peripherals),
+        peripherals.DMA,
+        peripherals.LCD_CAM,
+        peripherals.RMT,
+    )
+    .expect("Failed to initialize display");
+
+    let delay = Delay::new();
+
+    delay.delay_millis(100);
+    display.power_on();
+    delay.delay_millis(10);
+    display.clear().unwrap();
+
+    let jpeg = Jpeg::new(include_bytes!("../_docs/hello-world.jpg")).unwrap();
+    println!("jpeg {}x{}", jpeg.width(), jpeg.height());
+
+    // scaled to the screen height and centered
+    let options = DecodeOptions {
+        height: Some(Display::HEIGHT),
+        ..Default::default()
+    };
+    let (width, height) = options.output_size(jpeg.width(), jpeg.height());
+    display
+        .draw_jpeg(
+            &jpeg,
+            Display::WIDTH.saturating_sub(width) / 2,
+            Display::HEIGHT.saturating_sub(height) / 2,
+            &options,
+        )
+        .unwrap();
+
+    display.flush(DrawMode::BlackOnWhite).unwrap();
+    display.power_off();
+
+    loop {}
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate lilygo_epd47;
+
+use esp_backtrace as _;
+use esp_hal::{delay::Delay, prelude::*};
+use esp_println::println;
+use lilygo_epd47::{pin_config, DecodeOptions, Display, DitherMethod, DrawMode, Png};
+
+#[entry]
+fn main() -> ! {
+    esp_println::logger::init_logger_from_env();
+
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+
+    // Create PSRAM allocator
+    esp_alloc::psram_allocator!(peripherals.PSRAM, esp_hal::psram);
+
+    let mut display = Display::new(
+        pin_config!(peripherals),
+        peripherals.DMA,
+        peripherals.LCD_CAM,
+        peripherals.RMT,
+    )
+    .expect("Failed to initialize display");
+
+    let delay = Delay::new();
+
+    delay.delay_millis(100);
+    display.power_on();
+    delay.delay_millis(10);
+    display.clear().unwrap();
+
+    let png = Png::new(include_bytes!("./assets/rust.png")).unwrap();
+    println!("png {}x{}", png.width(), png.height());
+
+    // original size, dithered for smooth gradients
+    display
+        .draw_png(&png, 80, 170, &DecodeOptions::default())
+        .unwrap();
+
+    // scaled to 400px height, ordered dithering and inverted
+    let options = DecodeOptions {
+        dither: DitherMethod::Bayer,
+        height: Some(400),
+        invert: true,
+        ..Default::default()
+    };
+    let (width, height) = options.output_size(png.width(), png.height());
+    display
+        .draw_png(
+            &png,
+            Display::WIDTH - width - 80,
+            (Display::HEIGHT - height) / 2,
+            &options,
+        )
+        .unwrap();
+
+    display.flush(DrawMode::BlackOnWhite).unwrap();
+    display.power_off();
+
+    loop {}
+}
@@ -0,0 +1,124 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate lilygo_epd47;
+
+use alloc::format;
+
+use embedded_graphics_core::pixelcolor::{Gray4, GrayColor};
+use esp_backtrace as _;
+use esp_hal::{delay::Delay, prelude::*};
+use esp_println::println;
+use lilygo_epd47::{
+    display::Rectangle,
+    pin_config,
+    Align,
+    Display,
+    DrawMode,
+    Font4bpp,
+    FontStyle,
+    LayoutConfig,
+    TextLayout,
+};
+use u8g2_fonts::U8g2TextStyle;
+
+// DejaVu Serif converted with `epd-font`, see tools/epd-font
+static SERIF_24: &[u8] = include_bytes!("./assets/serif-24.f4");
+
+// Soft hyphens (U+00AD) mark where long words may be broken
+static TEXT: &str = "\
+Alice was be\u{ad}gin\u{ad}ning to get very tired of sit\u{ad}ting by her sis\u{ad}ter on the bank, \
+and of hav\u{ad}ing noth\u{ad}ing to do: once or twice she had peeped into the book her sis\u{ad}ter \
+was read\u{ad}ing, but it had no pic\u{ad}tures or con\u{ad}ver\u{ad}sa\u{ad}tions in it, \u{201c}and what is \
+the use of a book,\u{201d} thought Alice \u{201c}with\u{ad}out pic\u{ad}tures or con\u{ad}ver\u{ad}sa\u{ad}tions?\u{201d}
+So she was con\u{ad}sid\u{ad}er\u{ad}ing in her own mind (as well as she could, for the hot day made \
+her feel very sleepy and stupid), whether the plea\u{ad}sure of mak\u{ad}ing a daisy-chain would be \
+worth the trou\u{ad}ble of get\u{ad}ting up and pick\u{ad}ing the dai\u{ad}sies, when sud\u{ad}den\u{ad}ly a \
+White Rab\u{ad}bit with pink eyes ran close by her.
+There was noth\u{ad}ing so very re\u{ad}mark\u{ad}able in that; nor did Alice think it so very much out \
+of the way to hear the Rab\u{ad}bit say to it\u{ad}self, \u{201c}Oh dear! Oh dear! I shall be late!\u{201d} \
+(when she thought it over af\u{ad}ter\u{ad}wards, it oc\u{ad}curred to her that she ought to have \
+won\u{ad}dered at this, but at the time it all seemed quite nat\u{ad}ur\u{ad}al); but when the Rab\u{ad}bit \
+ac\u{ad}tu\u{ad}al\u{ad}ly took a watch out of its waist\u{ad}coat-pock\u{ad}et, and looked at it, and then \
+hur\u{ad}ried on, Alice start\u{ad}ed to her feet, for it flashed across her mind that she had never \
+be\u{ad}fore seen a rab\u{ad}bit with ei\u{ad}ther a waist\u{ad}coat-pock\u{ad}et, or a watch to take out \
+of it, and burn\u{ad}ing with cu\u{ad}ri\u{ad}os\u{ad}i\u{ad}ty, she ran across the field af\u{ad}ter it, \
+and for\u{ad}tu\u{ad}nate\u{ad}ly was just in time to see it pop down a large rab\u{ad}bit-hole un\u{ad}der \
+the hedge.
+In an\u{ad}oth\u{ad}er mo\u{ad}ment down went Alice af\u{ad}ter it, never once con\u{ad}sid\u{ad}er\u{ad}ing how \
+in the world she was to get out again.
+";
+
+#[entry]
+fn main() -> ! {
+    esp_println::logger::init_logger_from_env();
+
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+
+    // Create PSRAM allocator
+    esp_alloc::psram_allocator!(peripherals.PSRAM, esp_hal::psram);
+
+    let mut display = Display::new(
+        pin_config!(peripherals),
+        peripherals.DMA,
+        peripherals.LCD_CAM,
+        peripherals.RMT,
+    )
+    .expect("Failed to initialize display");
+
+    let delay = Delay::new();
+
+    // justified body text with the anti-aliased font
+    let body = FontStyle::new(Font4bpp::from_bytes(SERIF_24).unwrap(), 0);
+    let config = LayoutConfig {
+        align: Align::Justify,
+        paragraph_spacing: 8,
+        indent: 32,
+        hyphenation: true,
+        ..Default::default()
+    };
+    let area = Rectangle {
+        x: 60,
+        y: 40,
+        width: Display::WIDTH - 120,
+        height: 420,
+    };
+    let layout = TextLayout::new(TEXT, &body, area, &config);
+    println!(
+        "{} lines on {} pages",
+        layout.lines().len(),
+        layout.page_count()
+    );
+
+    // the page numbers with an embedded-graphics text style, centered below
+    // the text
+    let footer = U8g2TextStyle::new(u8g2_fonts::fonts::u8g2_font_helvR14_tf, Gray4::BLACK);
+    let footer_area = Rectangle {
+        x: area.x,
+        y: 480,
+        width: area.width,
+        height: 30,
+    };
+    let footer_config = LayoutConfig {
+        align: Align::Center,
+        ..Default::default()
+    };
+
+    delay.delay_millis(100);
+    display.power_on();
+    delay.delay_millis(10);
+
+    let mut page = 0;
+    loop {
+        display.clear().unwrap();
+        display.draw_page(&layout, &body, page).unwrap();
+        let number = format!("{} / {}", page + 1, layout.page_count());
+        let number = TextLayout::new(&number, &footer, footer_area, &footer_config);
+        display.draw_page(&number, &footer, 0).unwrap();
+        display.flush(DrawMode::BlackOnWhite).unwrap();
+
+        delay.delay_millis(10_000);
+        page = (page + 1) % layout.page_count();
+    }
+}
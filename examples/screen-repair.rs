@@ -5,9 +5,12 @@
 
 extern crate lilygo_epd47;
 
+use core::ops::ControlFlow;
+
 use esp_backtrace as _;
 use esp_hal::{delay::Delay, prelude::*};
-use lilygo_epd47::{pin_config, Display};
+use esp_println::println;
+use lilygo_epd47::{pin_config, Display, RepairConfig};
 
 #[entry]
 fn main() -> ! {
@@ -26,10 +29,20 @@ fn main() -> ! {
     )
     .expect("Failed to initialize display");
 
-    let delay = Delay::new();
+    let mut delay = Delay::new();
     display.power_on();
     delay.delay_millis(10);
-    display.repair(delay).unwrap();
+    let outcome = display
+        .repair(RepairConfig::default(), &mut delay, |progress| {
+            // feed a watchdog or return `ControlFlow::Break(())` to abort here
+            println!(
+                "Repair {:?}: {}/{}",
+                progress.phase, progress.step, progress.total
+            );
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+    println!("Repair {:?}", outcome);
     display.power_off();
 
     loop {}
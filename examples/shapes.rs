@@ -0,0 +1,176 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate lilygo_epd47;
+
+use alloc::vec::Vec;
+use core::f32::consts::PI;
+
+use esp_backtrace as _;
+use esp_hal::{delay::Delay, prelude::*};
+use libm::{cosf, sinf};
+use lilygo_epd47::{pin_config, Display, DrawMode, LineCap, PointF, ShapeStyle};
+
+static TEMPERATURES: [f32; 24] = [
+    14.2, 13.8, 13.1, 12.7, 12.5, 12.9, 14.0, 15.6, 17.3, 18.9, 20.4, 21.6, 22.5, 23.1, 23.4, 23.0,
+    22.2, 20.9, 19.5, 18.2, 17.1, 16.3, 15.4, 14.8,
+];
+
+fn point_on_circle(center: PointF, radius: f32, degrees: f32) -> PointF {
+    let angle = degrees * PI / 180.0;
+    PointF::new(
+        center.x + radius * cosf(angle),
+        center.y + radius * sinf(angle),
+    )
+}
+
+#[entry]
+fn main() -> ! {
+    esp_println::logger::init_logger_from_env();
+
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+
+    // Create PSRAM allocator
+    esp_alloc::psram_allocator!(peripherals.PSRAM, esp_hal::psram);
+
+    let mut display = Display::new(
+        pin_config!(peripherals),
+        peripherals.DMA,
+        peripherals.LCD_CAM,
+        peripherals.RMT,
+    )
+    .expect("Failed to initialize display");
+
+    let delay = Delay::new();
+    display.power_on();
+    delay.delay_millis(10);
+    display.clear().unwrap();
+
+    // clock face with 60 ticks, showing 10:10:37
+    let center = PointF::new(270.5, 270.5);
+    display
+        .draw_circle_aa(
+            center,
+            240.0,
+            &ShapeStyle {
+                fill: Some(14),
+                stroke: Some(0),
+                stroke_width: 6.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    for tick in 0..60 {
+        let (inner, width) = if tick % 5 == 0 {
+            (200.0, 6.0)
+        } else {
+            (220.0, 2.0)
+        };
+        let degrees = tick as f32 * 6.0;
+        display
+            .draw_line_aa(
+                point_on_circle(center, inner, degrees),
+                point_on_circle(center, 230.0, degrees),
+                &ShapeStyle::with_stroke(0, width),
+            )
+            .unwrap();
+    }
+    for (degrees, length, width, color) in [
+        (10.0 * 30.0 + 5.0, 130.0, 12.0, 0),
+        (10.0 * 6.0, 190.0, 8.0, 0),
+        (37.0 * 6.0, 200.0, 2.0, 6),
+    ] {
+        let style = ShapeStyle {
+            cap: LineCap::Round,
+            ..ShapeStyle::with_stroke(color, width)
+        };
+        // 0° is at 3 o'clock
+        let end = point_on_circle(center, length, degrees - 90.0);
+        display.draw_line_aa(center, end, &style).unwrap();
+    }
+    display
+        .draw_circle_aa(center, 9.0, &ShapeStyle::with_fill(0))
+        .unwrap();
+
+    // temperature chart in a rounded box, the area below the line is filled
+    display
+        .draw_rounded_rect_aa(
+            (560.5, 40.5),
+            360.0,
+            220.0,
+            14.0,
+            &ShapeStyle {
+                fill: Some(13),
+                stroke: Some(2),
+                stroke_width: 2.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let mut points: Vec<PointF> = TEMPERATURES
+        .iter()
+        .enumerate()
+        .map(|(hour, temperature)| {
+            PointF::new(
+                580.0 + hour as f32 * 14.0,
+                240.0 - (temperature - 10.0) * 12.0,
+            )
+        })
+        .collect();
+    let line = ShapeStyle {
+        cap: LineCap::Round,
+        ..ShapeStyle::with_stroke(0, 3.0)
+    };
+    points.push(PointF::new(580.0 + 23.0 * 14.0, 240.0));
+    points.push(PointF::new(580.0, 240.0));
+    display
+        .draw_polygon_aa(&points, &ShapeStyle::with_fill(10))
+        .unwrap();
+    points.truncate(TEMPERATURES.len());
+    display.draw_polyline_aa(&points, &line).unwrap();
+
+    // gauge at 63 %, a gray track with a black arc on top
+    let gauge = PointF::new(660.5, 430.5);
+    let track = ShapeStyle {
+        cap: LineCap::Round,
+        ..ShapeStyle::with_stroke(12, 16.0)
+    };
+    display
+        .draw_arc_aa(gauge, 80.0, 135.0, 270.0, &track)
+        .unwrap();
+    display
+        .draw_arc_aa(
+            gauge,
+            80.0,
+            135.0,
+            270.0 * 0.63,
+            &ShapeStyle {
+                stroke: Some(0),
+                ..track
+            },
+        )
+        .unwrap();
+
+    // pie chart with white gaps between the slices
+    let pie = PointF::new(850.5, 430.5);
+    let mut start = -90.0;
+    for (share, color) in [(0.36, 2), (0.22, 6), (0.28, 10), (0.14, 13)] {
+        let sweep = share * 360.0;
+        let style = ShapeStyle {
+            fill: Some(color),
+            stroke: Some(15),
+            stroke_width: 2.0,
+            ..Default::default()
+        };
+        display
+            .draw_arc_aa(pie, 80.0, start, sweep, &style)
+            .unwrap();
+        start += sweep;
+    }
+
+    display.flush(DrawMode::BlackOnWhite).unwrap();
+    display.power_off();
+
+    loop {}
+}
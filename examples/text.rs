@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate lilygo_epd47;
+
+use esp_backtrace as _;
+use esp_hal::{delay::Delay, prelude::*};
+use esp_println::println;
+use lilygo_epd47::{pin_config, Display, DrawMode, Font4bpp};
+
+// DejaVu fonts converted with `epd-font`, see tools/epd-font
+static SANS_BOLD_48: &[u8] = include_bytes!("./assets/sans-bold-48.f4");
+static SERIF_24: &[u8] = include_bytes!("./assets/serif-24.f4");
+
+#[entry]
+fn main() -> ! {
+    esp_println::logger::init_logger_from_env();
+
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+
+    // Create PSRAM allocator
+    esp_alloc::psram_allocator!(peripherals.PSRAM, esp_hal::psram);
+
+    let mut display = Display::new(
+        pin_config!(peripherals),
+        peripherals.DMA,
+        peripherals.LCD_CAM,
+        peripherals.RMT,
+    )
+    .expect("Failed to initialize display");
+
+    let delay = Delay::new();
+
+    delay.delay_millis(100);
+    display.power_on();
+    delay.delay_millis(10);
+    display.clear().unwrap();
+
+    let title = Font4bpp::from_bytes(SANS_BOLD_48).unwrap();
+    let body = Font4bpp::from_bytes(SERIF_24).unwrap();
+    println!(
+        "fonts: {} and {} glyphs",
+        title.glyph_count(),
+        body.glyph_count()
+    );
+
+    // centered title
+    let heading = "Anti-aliased Text";
+    let x = (Display::WIDTH - title.text_width(heading)) / 2;
+    let mut y = 40 + title.ascent();
+    display.draw_text(&title, heading, x, y, 0).unwrap();
+
+    // body text, one line at a time
+    y += title.descent() + 20 + body.ascent();
+    for line in [
+        "The quick brown fox jumps over the lazy dog.",
+        "Kerning: AVATAR, Toyota, WAVE, Type.",
+        "Latin-1: Äpfel, Ökonomie, Übung, façade, naïve, señor.",
+    ] {
+        display.draw_text(&body, line, 40, y, 0).unwrap();
+        y += body.line_height();
+    }
+
+    // a bold word in the middle of a line, continued at the returned pen
+    // position
+    y += body.line_height();
+    let x = display.draw_text(&body, "Mixing ", 40, y, 0).unwrap();
+    let x = display.draw_text(&title, "fonts", x, y, 0).unwrap();
+    display
+        .draw_text(&body, " on one baseline.", x, y, 0)
+        .unwrap();
+
+    // white text blended over a dark gray box
+    for row in 420..500 {
+        display.set_pixels(40, row, &[3; 880]).unwrap();
+    }
+    display
+        .draw_text(&title, "Inverted", 60, 460 + title.ascent() / 2, 15)
+        .unwrap();
+
+    display.flush(DrawMode::BlackOnWhite).unwrap();
+    display.power_off();
+
+    loop {}
+}
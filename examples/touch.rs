@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate lilygo_epd47;
+
+use embedded_graphics::{
+    prelude::*,
+    primitives::{Circle, PrimitiveStyle},
+};
+use embedded_graphics_core::pixelcolor::{Gray4, GrayColor};
+use esp_backtrace as _;
+use esp_hal::{
+    delay::Delay,
+    i2c::master::{Config, I2c},
+    prelude::*,
+};
+use lilygo_epd47::{pin_config, Display, DrawMode, Touch};
+
+#[entry]
+fn main() -> ! {
+    esp_println::logger::init_logger_from_env();
+
+    let peripherals = esp_hal::init(esp_hal::Config::default());
+    let delay = Delay::new();
+
+    // Create PSRAM allocator
+    esp_alloc::psram_allocator!(peripherals.PSRAM, esp_hal::psram);
+    // Initialise the display
+    let mut display = Display::new(
+        pin_config!(peripherals),
+        peripherals.DMA,
+        peripherals.LCD_CAM,
+        peripherals.RMT,
+    )
+    .expect("Failed to initialize display");
+    // Initialise the touch controller on the shared I2C bus
+    let i2c = I2c::new(peripherals.I2C0, Config::default())
+        .with_sda(peripherals.GPIO18)
+        .with_scl(peripherals.GPIO17);
+    let mut touch = Touch::new(i2c).expect("Failed to initialize touch");
+
+    display.power_on();
+    delay.delay_millis(10);
+    display.clear().unwrap();
+    display.power_off();
+
+    loop {
+        // Draw a dot wherever the screen has been touched
+        if let Some(touches) = touch.read_touches(&display).unwrap() {
+            for point in touches.iter() {
+                log::info!("Touch {} at {}x{}", point.id, point.x, point.y);
+                Circle::with_center(Point::new(point.x as i32, point.y as i32), 20)
+                    .into_styled(PrimitiveStyle::with_fill(Gray4::BLACK))
+                    .draw(&mut display)
+                    .unwrap();
+            }
+            if !touches.is_empty() {
+                display.power_on();
+                delay.delay_millis(10);
+                display.flush(DrawMode::BlackOnWhite).unwrap();
+                display.power_off();
+            }
+        }
+        delay.delay_millis(20);
+    }
+}
@@ -1,48 +1,304 @@
+#[cfg(target_arch = "xtensa")]
+use embedded_hal::delay::DelayNs;
+#[cfg(target_arch = "xtensa")]
 use esp_hal::{
-    analog::adc::{Adc, AdcChannel, AdcConfig, AdcPin, Attenuation},
+    analog::adc::{
+        Adc,
+        AdcCalCurve,
+        AdcCalScheme,
+        AdcChannel,
+        AdcConfig,
+        AdcPin,
+        Attenuation,
+        CalibrationAccess,
+        RegisterAccess,
+    },
+    delay::Delay,
     gpio::AnalogPin,
     peripheral::Peripheral,
     peripherals::ADC2,
     prelude::nb,
 };
 
-pub struct Battery<'a, PIN>
+#[cfg(target_arch = "xtensa")]
+use crate::{Error, Result};
+
+mod calibration;
+mod charge;
+
+pub use self::{
+    calibration::Calibration,
+    charge::{ChargeEstimator, ChargeState, ChargeThresholds, DischargeCurve},
+};
+
+/// Battery voltage reader.
+///
+/// The T5 4.7 S3 measures the battery on GPIO14 which belongs to `ADC2`. ADC2
+/// readings are unreliable while WiFi is active. Boards that route the battery
+/// to an `ADC1` pin can use that instance instead.
+#[cfg(target_arch = "xtensa")]
+pub struct Battery<'a, PIN, ADCI = ADC2>
 where
     PIN: AdcChannel + AnalogPin,
+    ADCI: RegisterAccess + CalibrationAccess,
+    AdcCalCurve<ADCI>: AdcCalScheme<ADCI>,
 {
-    adc: Adc<'a, ADC2>,
-    adc_pin: AdcPin<PIN, ADC2, esp_hal::analog::adc::AdcCalCurve<ADC2>>,
+    adc: Adc<'a, ADCI>,
+    adc_pin: AdcPin<PIN, ADCI, AdcCalCurve<ADCI>>,
     correction_factor: f32,
+    delay: Delay,
 }
 
-impl<'a, PIN> Battery<'a, PIN>
+#[cfg(target_arch = "xtensa")]
+impl<'a, PIN, ADCI> Battery<'a, PIN, ADCI>
 where
     PIN: AdcChannel + AnalogPin,
+    ADCI: RegisterAccess + CalibrationAccess,
+    AdcCalCurve<ADCI>: AdcCalScheme<ADCI>,
 {
     /// Create a new battery voltage reader
-    pub fn new(pin: PIN, adc: impl Peripheral<P = ADC2> + 'a) -> Self {
+    pub fn new(pin: PIN, adc: impl Peripheral<P = ADCI> + 'a) -> Self {
+        Self::with_calibration(pin, adc, Calibration::default())
+    }
+
+    /// Create a new battery voltage reader using a stored calibration, see
+    /// [`Calibration::from_bytes`].
+    pub fn with_calibration(
+        pin: PIN,
+        adc: impl Peripheral<P = ADCI> + 'a,
+        calibration: Calibration,
+    ) -> Self {
         let mut config = AdcConfig::new();
         let adc_pin = config.enable_pin_with_cal(pin, Attenuation::Attenuation11dB);
         Battery {
             adc: Adc::new(adc, config),
             adc_pin,
-            correction_factor: Self::DEFAULT_CORRECTION_FACTOR,
+            correction_factor: calibration.correction_factor,
+            delay: Delay::new(),
         }
     }
 
     /// Default voltage correction factor. This factor has been experimentally
-    /// determined. It might be device specific.
-    pub const DEFAULT_CORRECTION_FACTOR: f32 = 1.144632;
+    /// determined. It might be device specific, see [`Battery::calibrate`].
+    pub const DEFAULT_CORRECTION_FACTOR: f32 = Calibration::DEFAULT_CORRECTION_FACTOR;
+
+    /// Time [`Battery::read`] waits for a conversion, in microseconds.
+    const TIMEOUT_US: u32 = 10_000;
+
+    /// Time between two polls of a running conversion, in microseconds.
+    const POLL_INTERVAL_US: u32 = 10;
 
     /// Set a correction factor other than [`DEFAULT_CORRECTION_FACTOR`]
     pub fn set_correction_factor(&mut self, factor: f32) {
         self.correction_factor = factor
     }
 
+    /// The calibration currently in use.
+    pub fn calibration(&self) -> Calibration {
+        Calibration {
+            correction_factor: self.correction_factor,
+        }
+    }
+
+    /// Determine the correction factor from a known reference voltage, e.g.
+    /// measured with a multimeter at the battery connector. The battery is
+    /// sampled `N` times. The new factor is applied and returned so it can be
+    /// stored with [`Calibration::to_bytes`].
+    pub fn calibrate<const N: usize>(&mut self, reference: f32) -> Result<Calibration> {
+        let measured = self.to_voltage_uncorrected(self.read_raw_oversampled::<N>()?);
+        let calibration = Calibration::from_reference(reference, measured)?;
+        self.correction_factor = calibration.correction_factor;
+        Ok(calibration)
+    }
+
     /// Read the current voltage of the battery
-    pub fn read(&mut self) -> f32 {
-        let v = nb::block!(self.adc.read_oneshot(&mut self.adc_pin)).unwrap();
+    ///
+    /// Returns [Error::Adc] if the conversion doesn't finish in time, e.g.
+    /// because WiFi holds `ADC2`.
+    pub fn read(&mut self) -> Result<f32> {
+        let raw = self.read_raw()?;
+        Ok(self.to_voltage(raw as f32))
+    }
+
+    /// Non-blocking variant of [`Battery::read`].
+    pub fn poll(&mut self) -> nb::Result<f32, Error> {
+        let raw = self.poll_raw()?;
+        Ok(self.to_voltage(raw as f32))
+    }
+
+    /// Read the voltage of the battery from `N` samples. The lowest and
+    /// highest quarter of the samples are discarded as outliers before
+    /// averaging, which keeps the reading stable during radio activity.
+    pub fn read_oversampled<const N: usize>(&mut self) -> Result<f32> {
+        let raw = self.read_raw_oversampled::<N>()?;
+        Ok(self.to_voltage(raw))
+    }
+
+    /// Non-blocking variant of [`Battery::read_oversampled`]. Each call
+    /// collects at most one sample into `sampler`, the voltage is returned once
+    /// it is full. The sampler is then reset for the next reading.
+    pub fn poll_oversampled<const N: usize>(
+        &mut self,
+        sampler: &mut Oversampler<N>,
+    ) -> nb::Result<f32, Error> {
+        if !sampler.is_full() {
+            sampler.push(self.poll_raw()?);
+        }
+        match sampler.take() {
+            Some(raw) => Ok(self.to_voltage(raw)),
+            None if sampler.is_full() => Err(nb::Error::Other(Error::Adc)),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    fn read_raw_oversampled<const N: usize>(&mut self) -> Result<f32> {
+        let mut sampler = Oversampler::<N>::new();
+        while !sampler.is_full() {
+            sampler.push(self.read_raw()?);
+        }
+        sampler.take().ok_or(Error::Adc)
+    }
+
+    fn read_raw(&mut self) -> Result<u16> {
+        for _ in 0..Self::TIMEOUT_US / Self::POLL_INTERVAL_US {
+            match self.poll_raw() {
+                Err(nb::Error::WouldBlock) => self.delay.delay_us(Self::POLL_INTERVAL_US),
+                Err(nb::Error::Other(err)) => return Err(err),
+                Ok(raw) => return Ok(raw),
+            }
+        }
+        Err(Error::Adc)
+    }
+
+    fn poll_raw(&mut self) -> nb::Result<u16, Error> {
+        self.adc
+            .read_oneshot(&mut self.adc_pin)
+            .map_err(|err| err.map(|_| Error::Adc))
+    }
+
+    fn to_voltage(&self, millivolts: f32) -> f32 {
+        self.to_voltage_uncorrected(millivolts) * self.correction_factor
+    }
+
+    /// The battery is connected through a 1:2 voltage divider.
+    fn to_voltage_uncorrected(&self, millivolts: f32) -> f32 {
+        (millivolts * 2.0) / 1000.0
+    }
+}
+
+/// Collects raw ADC samples and averages them with outlier rejection.
+#[derive(Clone, Debug)]
+pub struct Oversampler<const N: usize> {
+    samples: [u16; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for Oversampler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Oversampler<N> {
+    /// Create an empty sampler for `N` samples.
+    pub const fn new() -> Self {
+        Oversampler {
+            samples: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Returns whether `N` samples have been collected.
+    pub fn is_full(&self) -> bool {
+        self.len >= N
+    }
+
+    /// Adds a sample. Samples beyond `N` are ignored.
+    pub fn push(&mut self, sample: u16) {
+        if self.len < N {
+            self.samples[self.len] = sample;
+            self.len += 1;
+        }
+    }
+
+    /// Returns the mean of the collected samples without the lowest and
+    /// highest quarter, and resets the sampler. Returns `None` until the
+    /// sampler is full or if `N` is zero.
+    pub fn take(&mut self) -> Option<f32> {
+        if !self.is_full() || N == 0 {
+            return None;
+        }
+        self.len = 0;
+        let samples = &mut self.samples[..];
+        samples.sort_unstable();
+        let trim = N / 4;
+        let kept = &samples[trim..N - trim];
+        Some(kept.iter().map(|&s| s as f32).sum::<f32>() / kept.len() as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample<const N: usize>(samples: &[u16]) -> Option<f32> {
+        let mut sampler = Oversampler::<N>::new();
+        for &sample in samples {
+            sampler.push(sample);
+        }
+        sampler.take()
+    }
+
+    #[test]
+    fn averaging() {
+        assert_eq!(sample::<1>(&[1200]), Some(1200.0));
+        assert_eq!(sample::<3>(&[999, 1001, 1003]), Some(1001.0));
+        // nothing is trimmed from fewer than 4 samples
+        assert_eq!(sample::<3>(&[1000, 4096, 1003]), Some(2033.0));
+    }
+
+    #[test]
+    fn outliers_are_trimmed() {
+        // the lowest and highest quarter, unsorted
+        assert_eq!(sample::<4>(&[4095, 1000, 1002, 0]), Some(1001.0));
+        assert_eq!(
+            sample::<8>(&[1010, 0, 1000, 4095, 1004, 1006, 3000, 1002]),
+            Some(1005.5)
+        );
+        // N / 4 rounds down
+        assert_eq!(
+            sample::<7>(&[1000, 1000, 2000, 1000, 1000, 0, 1000]),
+            Some(1000.0)
+        );
+    }
+
+    #[test]
+    fn take_resets() {
+        let mut sampler = Oversampler::<4>::default();
+        for sample in [1000, 1001, 1002] {
+            sampler.push(sample);
+            assert!(!sampler.is_full());
+            assert_eq!(sampler.take(), None);
+        }
+        sampler.push(1003);
+        // samples beyond N are ignored
+        sampler.push(4095);
+        assert!(sampler.is_full());
+        assert_eq!(sampler.take(), Some(1001.5));
+
+        assert!(!sampler.is_full());
+        assert_eq!(sampler.take(), None);
+        for sample in [2000, 2000, 2000, 2000] {
+            sampler.push(sample);
+        }
+        assert_eq!(sampler.take(), Some(2000.0));
+    }
 
-        (((v as f32) * 2.0) / 1000.0) * self.correction_factor
+    #[test]
+    fn no_samples() {
+        let mut sampler = Oversampler::<0>::new();
+        sampler.push(1000);
+        assert!(sampler.is_full());
+        assert_eq!(sampler.take(), None);
     }
 }
@@ -0,0 +1,196 @@
+use crate::{Error, Result};
+
+/// Device specific battery calibration.
+///
+/// The calibration can be stored as a small versioned blob, e.g. in flash or
+/// in RTC memory, and passed to [`crate::Battery::with_calibration`] after a
+/// reboot.
+///
+/// ```rust ignore
+/// #[ram(rtc_fast)]
+/// static mut CALIBRATION: [u8; Calibration::SIZE] = [0; Calibration::SIZE];
+///
+/// let calibration = Calibration::from_bytes(unsafe { &CALIBRATION }).unwrap_or_default();
+/// let mut battery = Battery::with_calibration(peripherals.GPIO14, peripherals.ADC2, calibration);
+/// if calibration == Calibration::default() {
+///     // measured with a multimeter
+///     let calibration = battery.calibrate::<64>(3.92)?;
+///     unsafe { CALIBRATION = calibration.to_bytes() };
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Calibration {
+    /// Factor applied to the measured voltage.
+    pub correction_factor: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            correction_factor: Self::DEFAULT_CORRECTION_FACTOR,
+        }
+    }
+}
+
+impl Calibration {
+    /// Size of the serialized calibration in bytes.
+    pub const SIZE: usize = 10;
+    /// Version of the serialized format.
+    pub const VERSION: u8 = 1;
+    /// Accepted range of correction factors. Anything outside points to a
+    /// wrong reference voltage or a broken measurement.
+    pub const FACTOR_RANGE: core::ops::RangeInclusive<f32> = 0.5..=2.0;
+
+    const MAGIC: [u8; 2] = *b"BC";
+    pub(crate) const DEFAULT_CORRECTION_FACTOR: f32 = 1.144632;
+
+    /// Compute the calibration from a known reference voltage and the
+    /// uncorrected voltage measured at the same time.
+    pub fn from_reference(reference: f32, measured: f32) -> Result<Self> {
+        let correction_factor = reference / measured;
+        if !Self::FACTOR_RANGE.contains(&correction_factor) {
+            return Err(Error::InvalidCalibration);
+        }
+        Ok(Calibration { correction_factor })
+    }
+
+    /// Serialize the calibration.
+    ///
+    /// Layout: magic `"BC"`, version, reserved byte, correction factor (`f32`,
+    /// little endian) and a Fletcher-16 checksum over the preceding bytes.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..2].copy_from_slice(&Self::MAGIC);
+        bytes[2] = Self::VERSION;
+        bytes[4..8].copy_from_slice(&self.correction_factor.to_le_bytes());
+        let checksum = fletcher16(&bytes[..8]);
+        bytes[8..10].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a calibration written by [`Calibration::to_bytes`].
+    ///
+    /// Returns [Error::InvalidCalibration] if the blob is empty, corrupted,
+    /// written by an unknown version or out of range.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < Self::SIZE || bytes[0..2] != Self::MAGIC || bytes[2] != Self::VERSION {
+            return Err(Error::InvalidCalibration);
+        }
+        let checksum = u16::from_le_bytes([bytes[8], bytes[9]]);
+        if checksum != fletcher16(&bytes[..8]) {
+            return Err(Error::InvalidCalibration);
+        }
+        let correction_factor = f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if !Self::FACTOR_RANGE.contains(&correction_factor) {
+            return Err(Error::InvalidCalibration);
+        }
+        Ok(Calibration { correction_factor })
+    }
+}
+
+fn fletcher16(data: &[u8]) -> u16 {
+    let (mut a, mut b) = (0u16, 0u16);
+    for &byte in data {
+        a = (a + byte as u16) % 255;
+        b = (b + a) % 255;
+    }
+    (b << 8) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serialized calibration with the factor replaced and the checksum
+    /// updated, so only the factor is wrong.
+    fn with_factor(factor: f32) -> [u8; Calibration::SIZE] {
+        let mut bytes = Calibration::default().to_bytes();
+        bytes[4..8].copy_from_slice(&factor.to_le_bytes());
+        let checksum = fletcher16(&bytes[..8]);
+        bytes[8..10].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        for correction_factor in [Calibration::DEFAULT_CORRECTION_FACTOR, 0.5, 1.0, 2.0] {
+            let calibration = Calibration { correction_factor };
+            let bytes = calibration.to_bytes();
+            assert_eq!(&bytes[..4], b"BC\x01\x00");
+            assert_eq!(Calibration::from_bytes(&bytes), Ok(calibration));
+        }
+        // trailing bytes are ignored
+        let mut bytes = [0xFF; 16];
+        bytes[..Calibration::SIZE].copy_from_slice(&Calibration::default().to_bytes());
+        assert_eq!(Calibration::from_bytes(&bytes), Ok(Calibration::default()));
+    }
+
+    #[test]
+    fn corrupted_checksum() {
+        let bytes = Calibration::default().to_bytes();
+        for index in 0..Calibration::SIZE {
+            let mut corrupted = bytes;
+            corrupted[index] ^= 0x10;
+            assert_eq!(
+                Calibration::from_bytes(&corrupted),
+                Err(Error::InvalidCalibration),
+                "byte {}",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_version() {
+        let mut bytes = Calibration::default().to_bytes();
+        bytes[2] = Calibration::VERSION + 1;
+        let checksum = fletcher16(&bytes[..8]);
+        bytes[8..10].copy_from_slice(&checksum.to_le_bytes());
+        assert_eq!(
+            Calibration::from_bytes(&bytes),
+            Err(Error::InvalidCalibration)
+        );
+    }
+
+    #[test]
+    fn short_buffer() {
+        let bytes = Calibration::default().to_bytes();
+        for len in 0..Calibration::SIZE {
+            assert_eq!(
+                Calibration::from_bytes(&bytes[..len]),
+                Err(Error::InvalidCalibration)
+            );
+        }
+        // erased flash
+        assert_eq!(
+            Calibration::from_bytes(&[0xFF; Calibration::SIZE]),
+            Err(Error::InvalidCalibration)
+        );
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(Calibration::from_bytes(&with_factor(1.5)).is_ok());
+        for factor in [0.49, 2.01, 0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(
+                Calibration::from_bytes(&with_factor(factor)),
+                Err(Error::InvalidCalibration)
+            );
+        }
+        assert_eq!(
+            Calibration::from_reference(3.75, 3.0),
+            Ok(Calibration {
+                correction_factor: 1.25
+            })
+        );
+        assert_eq!(
+            Calibration::from_reference(3.9, 1.5),
+            Err(Error::InvalidCalibration)
+        );
+        assert_eq!(
+            Calibration::from_reference(3.9, 0.0),
+            Err(Error::InvalidCalibration)
+        );
+    }
+}
@@ -0,0 +1,350 @@
+/// Maps a battery voltage to a state of charge.
+///
+/// The curve is a list of `(voltage, percent)` points sorted by descending
+/// voltage. Voltages between two points are interpolated linearly, voltages
+/// outside of the curve are clamped to its first or last point.
+#[derive(Clone, Copy, Debug)]
+pub struct DischargeCurve<'a> {
+    points: &'a [(f32, u8)],
+}
+
+impl<'a> DischargeCurve<'a> {
+    /// Resting voltage curve of a typical single cell 3.7V LiPo battery.
+    pub const LIPO: DischargeCurve<'static> = DischargeCurve::new(&[
+        (4.20, 100),
+        (4.15, 95),
+        (4.11, 90),
+        (4.08, 85),
+        (4.02, 80),
+        (3.98, 75),
+        (3.95, 70),
+        (3.91, 65),
+        (3.87, 60),
+        (3.85, 55),
+        (3.84, 50),
+        (3.82, 45),
+        (3.80, 40),
+        (3.79, 35),
+        (3.77, 30),
+        (3.75, 25),
+        (3.73, 20),
+        (3.71, 15),
+        (3.69, 10),
+        (3.61, 5),
+        (3.27, 0),
+    ]);
+
+    /// Resting voltage curve of a high voltage (4.35V) LiPo battery.
+    pub const LIPO_HV: DischargeCurve<'static> = DischargeCurve::new(&[
+        (4.35, 100),
+        (4.26, 95),
+        (4.20, 90),
+        (4.14, 85),
+        (4.08, 80),
+        (4.03, 75),
+        (3.98, 70),
+        (3.94, 65),
+        (3.90, 60),
+        (3.87, 55),
+        (3.85, 50),
+        (3.83, 45),
+        (3.81, 40),
+        (3.80, 35),
+        (3.78, 30),
+        (3.76, 25),
+        (3.74, 20),
+        (3.72, 15),
+        (3.70, 10),
+        (3.62, 5),
+        (3.30, 0),
+    ]);
+
+    /// Linear mapping between 4.2V (100%) and 3.3V (0%).
+    pub const LINEAR: DischargeCurve<'static> = DischargeCurve::new(&[(4.2, 100), (3.3, 0)]);
+
+    /// Create a custom discharge curve. The points have to be sorted by
+    /// descending voltage.
+    pub const fn new(points: &'a [(f32, u8)]) -> Self {
+        DischargeCurve { points }
+    }
+
+    /// The state of charge in percent for the given voltage.
+    pub fn percentage(&self, voltage: f32) -> f32 {
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 0.0,
+        };
+        if voltage >= first.0 {
+            return first.1 as f32;
+        }
+        if voltage <= last.0 {
+            return last.1 as f32;
+        }
+        for pair in self.points.windows(2) {
+            let (upper, lower) = (pair[0], pair[1]);
+            if voltage >= lower.0 {
+                let span = upper.0 - lower.0;
+                if span <= 0.0 {
+                    return upper.1 as f32;
+                }
+                let t = (voltage - lower.0) / span;
+                return lower.1 as f32 + t * (upper.1 as f32 - lower.1 as f32);
+            }
+        }
+        last.1 as f32
+    }
+}
+
+/// Coarse battery state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChargeState {
+    Critical,
+    Low,
+    Normal,
+    Full,
+}
+
+/// Percentages at which the [ChargeState] changes.
+#[derive(Clone, Copy, Debug)]
+pub struct ChargeThresholds {
+    /// At or above this percentage the battery is [ChargeState::Full].
+    pub full: f32,
+    /// At or below this percentage the battery is [ChargeState::Low].
+    pub low: f32,
+    /// At or below this percentage the battery is [ChargeState::Critical].
+    pub critical: f32,
+    /// A state only changes once the percentage crosses a threshold by more
+    /// than this many percentage points.
+    pub hysteresis: f32,
+}
+
+impl Default for ChargeThresholds {
+    fn default() -> Self {
+        ChargeThresholds {
+            full: 95.0,
+            low: 20.0,
+            critical: 5.0,
+            hysteresis: 2.0,
+        }
+    }
+}
+
+impl ChargeThresholds {
+    fn classify(&self, percentage: f32) -> ChargeState {
+        if percentage <= self.critical {
+            ChargeState::Critical
+        } else if percentage <= self.low {
+            ChargeState::Low
+        } else if percentage >= self.full {
+            ChargeState::Full
+        } else {
+            ChargeState::Normal
+        }
+    }
+}
+
+/// Estimates the state of charge from battery voltage readings.
+///
+/// The estimator averages the last `N` readings and applies hysteresis to the
+/// reported [ChargeState], so a voltage sagging under load doesn't make the
+/// state flicker.
+///
+/// ```rust ignore
+/// use lilygo_epd47::{ChargeEstimator, DischargeCurve};
+///
+/// let mut estimator: ChargeEstimator = ChargeEstimator::new(DischargeCurve::LIPO);
+/// let state = estimator.update(battery.read_oversampled::<16>()?);
+/// let percentage = estimator.percentage();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ChargeEstimator<'a, const N: usize = 8> {
+    curve: DischargeCurve<'a>,
+    thresholds: ChargeThresholds,
+    samples: [f32; N],
+    len: usize,
+    next: usize,
+    state: Option<ChargeState>,
+}
+
+impl<'a, const N: usize> ChargeEstimator<'a, N> {
+    /// Create a new estimator using the given curve and the default
+    /// thresholds.
+    pub fn new(curve: DischargeCurve<'a>) -> Self {
+        ChargeEstimator {
+            curve,
+            thresholds: ChargeThresholds::default(),
+            samples: [0.0; N],
+            len: 0,
+            next: 0,
+            state: None,
+        }
+    }
+
+    /// Use thresholds other than the default ones.
+    pub fn with_thresholds(mut self, thresholds: ChargeThresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// Add a voltage reading and return the updated state.
+    pub fn update(&mut self, voltage: f32) -> ChargeState {
+        if N > 0 {
+            self.samples[self.next] = voltage;
+            self.next = (self.next + 1) % N;
+            self.len = (self.len + 1).min(N);
+        }
+        let percentage = self.curve.percentage(self.voltage().unwrap_or(voltage));
+        let h = self.thresholds.hysteresis;
+        let state = match self.state {
+            None => self.thresholds.classify(percentage),
+            Some(current) => {
+                let up = self.thresholds.classify(percentage - h);
+                let down = self.thresholds.classify(percentage + h);
+                if up > current {
+                    up
+                } else if down < current {
+                    down
+                } else {
+                    current
+                }
+            }
+        };
+        self.state = Some(state);
+        state
+    }
+
+    /// The averaged voltage, if any reading has been added.
+    pub fn voltage(&self) -> Option<f32> {
+        if self.len == 0 {
+            return None;
+        }
+        Some(self.samples[..self.len].iter().sum::<f32>() / self.len as f32)
+    }
+
+    /// The averaged state of charge in percent, if any reading has been added.
+    pub fn percentage(&self) -> Option<u8> {
+        self.voltage()
+            .map(|v| (self.curve.percentage(v) + 0.5).clamp(0.0, 100.0) as u8)
+    }
+
+    /// The current state, if any reading has been added.
+    pub fn state(&self) -> Option<ChargeState> {
+        self.state
+    }
+
+    /// Forget all readings, e.g. after the battery has been charged.
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.next = 0;
+        self.state = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Voltage of `percentage` on the [DischargeCurve::LINEAR] curve.
+    fn volts(percentage: f32) -> f32 {
+        3.3 + 0.9 * percentage / 100.0
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn interpolation() {
+        let curve = DischargeCurve::LIPO;
+        assert_near(curve.percentage(4.20), 100.0);
+        assert_near(curve.percentage(3.86), 57.5);
+        assert_near(curve.percentage(3.85), 55.0);
+        assert_near(curve.percentage(3.44), 2.5);
+        assert_near(DischargeCurve::LINEAR.percentage(3.75), 50.0);
+    }
+
+    #[test]
+    fn clamping() {
+        for curve in [
+            DischargeCurve::LIPO,
+            DischargeCurve::LIPO_HV,
+            DischargeCurve::LINEAR,
+        ] {
+            assert_eq!(curve.percentage(5.0), 100.0);
+            assert_eq!(curve.percentage(3.0), 0.0);
+        }
+        assert_eq!(DischargeCurve::new(&[]).percentage(3.7), 0.0);
+        // a step in the curve doesn't divide by zero
+        let step = DischargeCurve::new(&[(4.0, 100), (3.8, 60), (3.8, 40), (3.5, 0)]);
+        assert_near(step.percentage(3.9), 80.0);
+        assert_near(step.percentage(3.65), 20.0);
+    }
+
+    #[test]
+    fn discharging() {
+        let mut estimator: ChargeEstimator<1> = ChargeEstimator::new(DischargeCurve::LINEAR);
+        let states: [_; 6] =
+            [50.0, 19.0, 17.5, 4.0, 2.5, 6.0].map(|percentage| estimator.update(volts(percentage)));
+        assert_eq!(
+            states,
+            [
+                ChargeState::Normal,
+                // within the hysteresis of the threshold
+                ChargeState::Normal,
+                ChargeState::Low,
+                ChargeState::Low,
+                ChargeState::Critical,
+                ChargeState::Critical,
+            ]
+        );
+    }
+
+    #[test]
+    fn charging() {
+        let mut estimator: ChargeEstimator<1> = ChargeEstimator::new(DischargeCurve::LINEAR);
+        let states: [_; 6] =
+            [3.0, 6.0, 7.5, 21.0, 23.0, 97.5].map(|percentage| estimator.update(volts(percentage)));
+        assert_eq!(
+            states,
+            [
+                ChargeState::Critical,
+                ChargeState::Critical,
+                ChargeState::Low,
+                ChargeState::Low,
+                ChargeState::Normal,
+                ChargeState::Full,
+            ]
+        );
+        // the first reading isn't held back by the hysteresis
+        estimator.reset();
+        assert_eq!(estimator.update(volts(96.0)), ChargeState::Full);
+    }
+
+    #[test]
+    fn averaging() {
+        let mut estimator: ChargeEstimator<4> = ChargeEstimator::new(DischargeCurve::LINEAR);
+        assert_eq!(estimator.voltage(), None);
+        assert_eq!(estimator.percentage(), None);
+        assert_eq!(estimator.state(), None);
+
+        for voltage in [4.0, 4.0, 3.6, 3.6] {
+            estimator.update(voltage);
+        }
+        assert_near(estimator.voltage().unwrap(), 3.8);
+        assert_eq!(estimator.percentage(), Some(56));
+        // the oldest reading is replaced
+        estimator.update(3.2);
+        assert_near(estimator.voltage().unwrap(), 3.6);
+        assert_eq!(estimator.percentage(), Some(33));
+
+        estimator.reset();
+        assert_eq!(estimator.voltage(), None);
+        assert_eq!(estimator.state(), None);
+    }
+}
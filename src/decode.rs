@@ -0,0 +1,206 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{Display, Dither, DitherMethod, PanelLevels, Result};
+
+/// Options for drawing compressed images, e.g. with [Display::draw_png].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DecodeOptions {
+    /// Dithering into the 16 gray levels of the panel.
+    pub dither: DitherMethod,
+    /// Panel levels used for dithering.
+    pub levels: PanelLevels,
+    /// Scale the image to this width. Keeps the aspect ratio if no height is
+    /// given.
+    pub width: Option<u16>,
+    /// Scale the image to this height. Keeps the aspect ratio if no width is
+    /// given.
+    pub height: Option<u16>,
+    /// Swap black and white.
+    pub invert: bool,
+}
+
+impl DecodeOptions {
+    /// Size of an image of `width` x `height` pixels after scaling. Sizes
+    /// of 0 are raised to 1 pixel.
+    pub fn output_size(&self, width: u16, height: u16) -> (u16, u16) {
+        let scale = |len: u16, to: u16, from: u16| {
+            (len as u32 * to as u32 / from.max(1) as u32).clamp(1, u16::MAX as u32) as u16
+        };
+        let (width, height) = match (self.width, self.height) {
+            (None, None) => (width, height),
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, scale(height, w, width)),
+            (None, Some(h)) => (scale(width, h, height), h),
+        };
+        (width.max(1), height.max(1))
+    }
+}
+
+/// Scales decoded rows of 8 bit lightness to the output size and dithers
+/// them into the framebuffer.
+///
+/// Downscaling averages all source pixels of an output pixel, upscaling
+/// repeats the nearest source pixel. Only a single output row is kept in
+/// memory.
+#[cfg_attr(not(any(feature = "jpeg", feature = "png")), allow(dead_code))]
+pub(crate) struct RowWriter {
+    dither: Dither,
+    invert: bool,
+    src_width: u16,
+    src_height: u16,
+    width: u16,
+    height: u16,
+    /// Source rows pushed so far.
+    src_row: u16,
+    /// Source rows accumulated into `sums`.
+    rows: u32,
+    /// Number of source columns of each output column.
+    weights: Vec<u16>,
+    sums: Vec<u32>,
+    output: Vec<u8>,
+}
+
+#[cfg_attr(not(any(feature = "jpeg", feature = "png")), allow(dead_code))]
+impl RowWriter {
+    /// Create a writer scaling rows of an image of `source` size to `size`,
+    /// with the top left corner at `x`/`y`.
+    pub(crate) fn new(
+        options: &DecodeOptions,
+        x: u16,
+        y: u16,
+        source: (u16, u16),
+        size: (u16, u16),
+    ) -> Self {
+        let ((src_width, src_height), (width, height)) = (source, size);
+        let (width, height) = (width.max(1), height.max(1));
+        let mut weights = vec![0u16; width as usize];
+        if width < src_width {
+            for sx in 0..src_width as usize {
+                weights[sx * width as usize / src_width as usize] += 1;
+            }
+        } else {
+            weights.fill(1);
+        }
+        RowWriter {
+            dither: Dither::new(options.dither, x, y, width).with_levels(options.levels),
+            invert: options.invert,
+            src_width,
+            src_height,
+            width,
+            height,
+            src_row: 0,
+            rows: 0,
+            weights,
+            sums: vec![0; width as usize],
+            output: vec![0; width as usize],
+        }
+    }
+
+    /// Returns whether all rows have been written or the remaining rows are
+    /// below the screen.
+    pub(crate) fn is_done(&self) -> bool {
+        self.src_row >= self.src_height || self.dither.row() >= Display::HEIGHT
+    }
+
+    /// Push the next source row. Missing pixels at the end are white.
+    pub(crate) fn push_row(&mut self, display: &mut Display<'_>, luma: &[u8]) -> Result<()> {
+        if self.src_row >= self.src_height {
+            return Ok(());
+        }
+        let (src_width, width) = (self.src_width as usize, self.width as usize);
+        let pixel = |sx: usize| luma.get(sx).copied().unwrap_or(u8::MAX) as u32;
+        if width < src_width {
+            for sx in 0..src_width {
+                self.sums[sx * width / src_width] += pixel(sx);
+            }
+        } else {
+            for (x, sum) in self.sums.iter_mut().enumerate() {
+                *sum += pixel(x * src_width / width);
+            }
+        }
+        self.rows += 1;
+
+        // output rows completed by this source row
+        let (src_row, src_height, height) = (
+            self.src_row as u32,
+            self.src_height as u32,
+            self.height as u32,
+        );
+        let completed = (src_row + 1) * height / src_height - src_row * height / src_height;
+        self.src_row += 1;
+        if completed == 0 {
+            return Ok(());
+        }
+        for ((output, &sum), &weight) in self.output.iter_mut().zip(&self.sums).zip(&self.weights) {
+            let count = weight as u32 * self.rows;
+            let value = ((sum + count / 2) / count) as u8;
+            *output = if self.invert { u8::MAX - value } else { value };
+        }
+        self.sums.fill(0);
+        self.rows = 0;
+        for _ in 0..completed {
+            if self.dither.row() >= Display::HEIGHT {
+                break;
+            }
+            self.dither.write_gray8(display, &self.output)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(width: Option<u16>, height: Option<u16>) -> DecodeOptions {
+        DecodeOptions {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn output_size() {
+        assert_eq!(options(None, None).output_size(200, 100), (200, 100));
+        assert_eq!(options(Some(50), None).output_size(200, 100), (50, 25));
+        assert_eq!(options(None, Some(300)).output_size(200, 100), (600, 300));
+        assert_eq!(options(Some(30), Some(40)).output_size(200, 100), (30, 40));
+        assert_eq!(options(Some(1), None).output_size(200, 100), (1, 1));
+    }
+
+    #[test]
+    fn zero_size_is_one_pixel() {
+        assert_eq!(options(Some(0), None).output_size(200, 100), (1, 1));
+        assert_eq!(options(None, Some(0)).output_size(200, 100), (1, 1));
+        assert_eq!(options(Some(0), Some(0)).output_size(200, 100), (1, 1));
+        assert_eq!(options(None, None).output_size(0, 0), (1, 1));
+
+        let mut display = Display::mock();
+        let options = options(Some(0), Some(0));
+        let mut writer = RowWriter::new(&options, 10, 20, (4, 2), (0, 0));
+        writer.push_row(&mut display, &[0; 4]).unwrap();
+        writer.push_row(&mut display, &[0; 4]).unwrap();
+        assert!(writer.is_done());
+        assert_eq!(display.pixel(10, 20), 0);
+        assert_eq!(display.pixel(11, 20), 15);
+        assert_eq!(display.pixel(10, 21), 15);
+    }
+
+    #[test]
+    fn downscaling_averages() {
+        let mut display = Display::mock();
+        let options = DecodeOptions {
+            dither: DitherMethod::None,
+            levels: PanelLevels::LINEAR,
+            ..options(Some(2), None)
+        };
+        let mut writer = RowWriter::new(&options, 0, 0, (4, 2), (2, 1));
+        writer.push_row(&mut display, &[0, 0, 255, 255]).unwrap();
+        writer.push_row(&mut display, &[0, 68, 255, 119]).unwrap();
+        assert!(writer.is_done());
+        // (0 + 0 + 0 + 68) / 4 = 17, (255 * 3 + 119) / 4 = 221
+        assert_eq!((display.pixel(0, 0), display.pixel(1, 0)), (1, 13));
+    }
+}
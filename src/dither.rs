@@ -0,0 +1,333 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{Display, Result};
+
+/// Perceived lightness of the 16 gray levels of the panel, from black (0) to
+/// white (15).
+///
+/// Dithering works in this lightness space, so the average lightness of an
+/// area matches the source image even where the panel's gray steps are not
+/// evenly spaced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PanelLevels([u8; 16]);
+
+impl Default for PanelLevels {
+    fn default() -> Self {
+        Self::LINEAR
+    }
+}
+
+impl PanelLevels {
+    /// Evenly spaced levels, i.e. plain truncation to 4 bits.
+    pub const LINEAR: PanelLevels = PanelLevels([
+        0, 17, 34, 51, 68, 85, 102, 119, 136, 153, 170, 187, 204, 221, 238, 255,
+    ]);
+
+    /// Levels on a gamma 0.8 curve, spreading the dark end of the scale
+    /// further apart than the light end. The curve hasn't been measured on a
+    /// panel, it is an alternative to try if dark gradients look crushed.
+    pub const GAMMA_0_8: PanelLevels = PanelLevels([
+        0, 29, 51, 70, 89, 106, 123, 139, 154, 169, 184, 199, 213, 227, 241, 255,
+    ]);
+
+    /// Custom levels, e.g. measured on a specific panel. Returns `None` unless
+    /// the levels are strictly increasing.
+    pub fn new(levels: [u8; 16]) -> Option<Self> {
+        levels
+            .windows(2)
+            .all(|pair| pair[0] < pair[1])
+            .then_some(PanelLevels(levels))
+    }
+
+    /// Lightness of the given panel level.
+    pub fn lightness(&self, level: u8) -> u8 {
+        self.0[level.min(15) as usize]
+    }
+
+    /// Panel level with the closest lightness.
+    pub fn nearest(&self, lightness: u8) -> u8 {
+        let below = self.below(lightness);
+        match self.0.get(below as usize + 1) {
+            Some(&above)
+                if above - lightness < lightness.saturating_sub(self.0[below as usize]) =>
+            {
+                below + 1
+            }
+            _ => below,
+        }
+    }
+
+    /// Panel level for the pixel at `x`/`y` with ordered dithering, see
+    /// [`DitherMethod::Bayer`].
+    pub fn ordered(&self, lightness: u8, x: u16, y: u16) -> u8 {
+        let below = self.below(lightness);
+        if below == 15 {
+            return below;
+        }
+        let low = self.lightness(below) as u32;
+        let high = self.lightness(below + 1) as u32;
+        // thresholds at the centers of 16 equal steps between the levels
+        let threshold = BAYER_4X4[y as usize % 4][x as usize % 4] as u32 * 2 + 1;
+        if (lightness as u32).saturating_sub(low) * 32 > threshold * (high - low) {
+            below + 1
+        } else {
+            below
+        }
+    }
+
+    /// Darkest level with a lightness not above the given one.
+    fn below(&self, lightness: u8) -> u8 {
+        self.0
+            .iter()
+            .rposition(|&level| level <= lightness)
+            .unwrap_or(0) as u8
+    }
+}
+
+/// Dithering algorithm of a [Dither].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DitherMethod {
+    /// Nearest level without dithering.
+    None,
+    /// Error diffusion with the Floyd–Steinberg kernel. Smoothest gradients,
+    /// best for photos.
+    #[default]
+    FloydSteinberg,
+    /// Error diffusion with the Atkinson kernel. Only 3/4 of the error is
+    /// diffused, which keeps more contrast, best for line art and comics.
+    Atkinson,
+    /// Ordered dithering with a 4x4 Bayer matrix. Stable patterns that don't
+    /// change with the surroundings, best for UI elements.
+    Bayer,
+}
+
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Errors are kept in 1/16 of a lightness step.
+const ERROR_SCALE: i32 = 16;
+/// Padding of the error rows, so the kernels can write beyond both edges.
+const PADDING: usize = 2;
+
+/// Dithers image rows into the 16 gray levels of the panel.
+///
+/// The rows are written one after another into the framebuffer, starting at
+/// the given position. Rows extending beyond the screen are clipped.
+///
+/// ```rust ignore
+/// let mut dither = Dither::new(DitherMethod::FloydSteinberg, 0, 0, width);
+/// for row in image.rows() {
+///     dither.write_gray8(&mut display, row)?;
+/// }
+/// display.flush(DrawMode::BlackOnWhite)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Dither {
+    method: DitherMethod,
+    levels: PanelLevels,
+    x: u16,
+    y: u16,
+    width: u16,
+    /// Diffused errors of the current and the next two rows.
+    errors: [Vec<i32>; 3],
+    luma: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Dither {
+    /// Create a ditherer for rows of `width` pixels, the first of which is
+    /// written at `x`/`y`.
+    pub fn new(method: DitherMethod, x: u16, y: u16, width: u16) -> Self {
+        let padded = width as usize + 2 * PADDING;
+        Dither {
+            method,
+            levels: PanelLevels::default(),
+            x,
+            y,
+            width,
+            errors: [vec![0; padded], vec![0; padded], vec![0; padded]],
+            luma: Vec::new(),
+            output: vec![0; width as usize],
+        }
+    }
+
+    /// Use other panel levels than [`PanelLevels::LINEAR`].
+    pub fn with_levels(mut self, levels: PanelLevels) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    /// Screen row the next row is written to.
+    pub fn row(&self) -> u16 {
+        self.y
+    }
+
+    /// Dither a row of 8 bit lightness values into panel levels. Pixels
+    /// beyond `width` are ignored.
+    pub fn dither_row(&mut self, luma: &[u8], output: &mut [u8]) {
+        let len = luma.len().min(output.len()).min(self.width as usize);
+        let [current, next, after] = &mut self.errors;
+        for (i, (&value, output)) in luma.iter().zip(output.iter_mut()).take(len).enumerate() {
+            let e = i + PADDING;
+            let value = match self.method {
+                DitherMethod::None | DitherMethod::Bayer => value,
+                DitherMethod::FloydSteinberg | DitherMethod::Atkinson => {
+                    let diffused = (current[e] + ERROR_SCALE / 2).div_euclid(ERROR_SCALE);
+                    (value as i32 + diffused).clamp(0, 255) as u8
+                }
+            };
+            let level = match self.method {
+                DitherMethod::Bayer => {
+                    self.levels
+                        .ordered(value, self.x.wrapping_add(i as u16), self.y)
+                }
+                _ => self.levels.nearest(value),
+            };
+            *output = level;
+
+            let error = (value as i32 - self.levels.lightness(level) as i32) * ERROR_SCALE;
+            match self.method {
+                DitherMethod::FloydSteinberg => {
+                    current[e + 1] += error * 7 / 16;
+                    next[e - 1] += error * 3 / 16;
+                    next[e] += error * 5 / 16;
+                    next[e + 1] += error / 16;
+                }
+                DitherMethod::Atkinson => {
+                    let share = error / 8;
+                    current[e + 1] += share;
+                    current[e + 2] += share;
+                    next[e - 1] += share;
+                    next[e] += share;
+                    next[e + 1] += share;
+                    after[e] += share;
+                }
+                DitherMethod::None | DitherMethod::Bayer => {}
+            }
+        }
+        self.errors.rotate_left(1);
+        self.errors[2].fill(0);
+        self.y = self.y.saturating_add(1);
+    }
+
+    /// Dither a row of `Gray8` pixels into the framebuffer.
+    pub fn write_gray8(&mut self, display: &mut Display<'_>, row: &[u8]) -> Result<()> {
+        let mut output = core::mem::take(&mut self.output);
+        let y = self.y;
+        self.dither_row(row, &mut output);
+        let len = row.len().min(output.len());
+        let result = self.write(display, y, &output[..len]);
+        self.output = output;
+        result
+    }
+
+    /// Dither a row of `Rgb888` pixels, 3 bytes each, into the framebuffer.
+    pub fn write_rgb888(&mut self, display: &mut Display<'_>, row: &[u8]) -> Result<()> {
+        let mut luma = core::mem::take(&mut self.luma);
+        luma.clear();
+        luma.extend(
+            row.chunks_exact(3)
+                .take(self.width as usize)
+                .map(|rgb| luma_rgb888(rgb[0], rgb[1], rgb[2])),
+        );
+        let result = self.write_gray8(display, &luma);
+        self.luma = luma;
+        result
+    }
+
+    fn write(&self, display: &mut Display<'_>, y: u16, levels: &[u8]) -> Result<()> {
+        if levels.is_empty() || self.x >= Display::WIDTH || y >= Display::HEIGHT {
+            return Ok(());
+        }
+        display.set_pixels(self.x, y, levels)
+    }
+}
+
+/// Lightness of an RGB color (ITU-R BT.601).
+pub(crate) fn luma_rgb888(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 77 + g as u32 * 150 + b as u32 * 29 + 128) >> 8) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdata::assert_golden;
+
+    const WIDTH: usize = 64;
+    const HEIGHT: usize = 16;
+
+    /// Horizontal gradient from black to white.
+    fn gradient() -> Vec<u8> {
+        (0..WIDTH)
+            .map(|x| ((x * 255 + (WIDTH - 1) / 2) / (WIDTH - 1)) as u8)
+            .collect()
+    }
+
+    fn dither(method: DitherMethod, levels: PanelLevels) -> Vec<u8> {
+        let mut dither = Dither::new(method, 0, 0, WIDTH as u16).with_levels(levels);
+        let row = gradient();
+        let mut output = vec![0; WIDTH];
+        let mut image = Vec::new();
+        for _ in 0..HEIGHT {
+            dither.dither_row(&row, &mut output);
+            image.extend_from_slice(&output);
+        }
+        image
+    }
+
+    /// Compares the dithered gradient with the golden image and checks that
+    /// the average lightness of 4 pixel wide columns follows the gradient.
+    fn check(method: DitherMethod, name: &str, tolerance: f32) {
+        for (levels, suffix) in [
+            (PanelLevels::LINEAR, "linear"),
+            (PanelLevels::GAMMA_0_8, "gamma-0-8"),
+        ] {
+            let image = dither(method, levels);
+            let gradient = gradient();
+            for x in (0..WIDTH).step_by(4) {
+                let mean = |value: &dyn Fn(usize, usize) -> u8| {
+                    let sum: u32 = (0..HEIGHT)
+                        .flat_map(|y| (x..x + 4).map(move |x| (x, y)))
+                        .map(|(x, y)| value(x, y) as u32)
+                        .sum();
+                    sum as f32 / (4 * HEIGHT) as f32
+                };
+                let expected = mean(&|x, _| gradient[x]);
+                let actual = mean(&|x, y| levels.lightness(image[y * WIDTH + x]));
+                assert!(
+                    (actual - expected).abs() <= tolerance,
+                    "{} {}: column {} has lightness {}, expected {}",
+                    name,
+                    suffix,
+                    x,
+                    actual,
+                    expected
+                );
+            }
+            assert_golden(
+                &std::format!("dither/{}-{}.pgm", name, suffix),
+                WIDTH,
+                15,
+                &image,
+            );
+        }
+    }
+
+    #[test]
+    fn floyd_steinberg() {
+        check(DitherMethod::FloydSteinberg, "floyd-steinberg", 2.0);
+    }
+
+    #[test]
+    fn atkinson() {
+        // the error that isn't diffused shifts the dark end of the wide
+        // GAMMA_0_8 steps
+        check(DitherMethod::Atkinson, "atkinson", 4.0);
+    }
+
+    #[test]
+    fn bayer() {
+        check(DitherMethod::Bayer, "bayer", 3.0);
+    }
+}
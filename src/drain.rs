@@ -0,0 +1,393 @@
+use crate::{display::DisplayStats, DischargeCurve};
+
+/// A single entry of the [DrainLog].
+///
+/// All counters are cumulative over the lifetime of the log, even if the
+/// [DisplayStats] they were taken from have been reset by a reboot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DrainSample {
+    /// Time of the reading in seconds, e.g. from the RTC.
+    pub timestamp: u32,
+    /// Battery voltage in millivolts.
+    pub millivolts: u16,
+    /// Number of display refreshes.
+    pub refreshes: u32,
+    /// Panel on-time in milliseconds.
+    pub on_time_ms: u32,
+}
+
+/// Fixed size ring buffer of timestamped battery readings.
+///
+/// The log is plain data without pointers and can be created in a `const`
+/// context, so it can live in RTC memory and survive deep sleep. A log in
+/// uninitialized memory, e.g. a `persistent` static after power loss, is
+/// detected and starts over empty:
+///
+/// ```rust ignore
+/// #[ram(rtc_fast, persistent)]
+/// static mut LOG: DrainLog<64> = DrainLog::new();
+///
+/// let log = unsafe { &mut *core::ptr::addr_of_mut!(LOG) };
+/// log.record(rtc_seconds, battery.read_oversampled::<16>()?, display.stats());
+/// if let Some(estimate) = DrainEstimator::new(DischargeCurve::LIPO, 1500).estimate(log) {
+///     log::info!("{:?} hours left", estimate.remaining_hours);
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct DrainLog<const N: usize> {
+    magic: u32,
+    samples: [DrainSample; N],
+    len: usize,
+    next: usize,
+    /// Display counters of the last record, to accumulate across resets.
+    last_stats: DisplayStats,
+    total: DisplayStats,
+}
+
+impl<const N: usize> Default for DrainLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> DrainLog<N> {
+    const MAGIC: u32 = 0x4452_4E01;
+
+    pub const fn new() -> Self {
+        const EMPTY: DrainSample = DrainSample {
+            timestamp: 0,
+            millivolts: 0,
+            refreshes: 0,
+            on_time_ms: 0,
+        };
+        const ZERO: DisplayStats = DisplayStats {
+            refreshes: 0,
+            on_time_ms: 0,
+        };
+        DrainLog {
+            magic: Self::MAGIC,
+            samples: [EMPTY; N],
+            len: 0,
+            next: 0,
+            last_stats: ZERO,
+            total: ZERO,
+        }
+    }
+
+    /// Record a battery reading together with the current display counters.
+    ///
+    /// Counters that went backwards since the last record (e.g. because the
+    /// [Display](crate::Display) has been re-created after deep sleep) are
+    /// treated as having restarted from zero.
+    pub fn record(&mut self, timestamp: u32, voltage: f32, stats: DisplayStats) {
+        fn delta(current: u32, last: u32) -> u32 {
+            if current >= last {
+                current - last
+            } else {
+                current
+            }
+        }
+        // the totals of garbage would be carried into the fresh log
+        self.reset_if_invalid();
+        self.total.refreshes = self
+            .total
+            .refreshes
+            .wrapping_add(delta(stats.refreshes, self.last_stats.refreshes));
+        self.total.on_time_ms = self
+            .total
+            .on_time_ms
+            .wrapping_add(delta(stats.on_time_ms, self.last_stats.on_time_ms));
+        self.last_stats = stats;
+        self.push(DrainSample {
+            timestamp,
+            millivolts: (voltage * 1000.0 + 0.5).clamp(0.0, u16::MAX as f32) as u16,
+            refreshes: self.total.refreshes,
+            on_time_ms: self.total.on_time_ms,
+        });
+    }
+
+    /// Add a sample, overwriting the oldest one if the log is full.
+    pub fn push(&mut self, sample: DrainSample) {
+        if N == 0 {
+            return;
+        }
+        self.reset_if_invalid();
+        self.samples[self.next] = sample;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    /// Returns whether the log holds consistent data, i.e. it has been created
+    /// by [DrainLog::new] rather than found in uninitialized memory. An
+    /// invalid log reads as empty and is reset by the next record.
+    pub fn is_valid(&self) -> bool {
+        self.magic == Self::MAGIC && self.next < N.max(1) && self.len <= N
+    }
+
+    fn reset_if_invalid(&mut self) {
+        if !self.is_valid() {
+            *self = Self::new();
+        }
+    }
+
+    /// Number of samples in the log.
+    pub fn len(&self) -> usize {
+        if self.is_valid() {
+            self.len
+        } else {
+            0
+        }
+    }
+
+    /// Returns whether the log is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all samples.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Iterate over the samples from the oldest to the newest one.
+    pub fn iter(&self) -> impl Iterator<Item = &DrainSample> + '_ {
+        let len = self.len();
+        let start = (self.next + N - len) % N.max(1);
+        (0..len).map(move |i| &self.samples[(start + i) % N])
+    }
+
+    fn first(&self) -> Option<&DrainSample> {
+        self.iter().next()
+    }
+
+    fn last(&self) -> Option<&DrainSample> {
+        self.iter().last()
+    }
+}
+
+/// Result of [`DrainEstimator::estimate`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DrainEstimate {
+    /// Current state of charge in percent, from the fitted trend.
+    pub percentage: f32,
+    /// Discharge rate in percent per hour.
+    pub drain_per_hour: f32,
+    /// Remaining runtime at the current rate, `None` while charging.
+    pub remaining_hours: Option<f32>,
+    /// Average refreshes per hour over the log.
+    pub refreshes_per_hour: f32,
+    /// Average energy consumed per refresh in mWh, including the idle
+    /// consumption in between. `None` if there were no refreshes.
+    pub energy_per_refresh_mwh: Option<f32>,
+    /// Share of the time the panel has been powered on, from 0 to 1.
+    pub on_time_share: f32,
+}
+
+/// Estimates runtime and energy consumption from a [DrainLog].
+///
+/// The state of charge is fitted with a least squares line over time, which
+/// averages out the noise of individual readings.
+#[derive(Clone, Copy, Debug)]
+pub struct DrainEstimator<'a> {
+    curve: DischargeCurve<'a>,
+    capacity_mah: u32,
+    nominal_voltage: f32,
+}
+
+impl<'a> DrainEstimator<'a> {
+    /// Create an estimator for a battery with the given curve and capacity.
+    pub fn new(curve: DischargeCurve<'a>, capacity_mah: u32) -> Self {
+        DrainEstimator {
+            curve,
+            capacity_mah,
+            nominal_voltage: 3.7,
+        }
+    }
+
+    /// Use a nominal cell voltage other than 3.7V for energy calculations.
+    pub fn with_nominal_voltage(mut self, voltage: f32) -> Self {
+        self.nominal_voltage = voltage;
+        self
+    }
+
+    /// Estimate from the samples in the log. Returns `None` if the log spans
+    /// less than two distinct timestamps.
+    pub fn estimate<const N: usize>(&self, log: &DrainLog<N>) -> Option<DrainEstimate> {
+        let (first, last) = (log.first()?, log.last()?);
+        let span_s = last.timestamp.checked_sub(first.timestamp)?;
+        if span_s == 0 {
+            return None;
+        }
+        let hours =
+            |sample: &DrainSample| sample.timestamp.saturating_sub(first.timestamp) as f32 / 3600.0;
+        let percentage =
+            |sample: &DrainSample| self.curve.percentage(sample.millivolts as f32 / 1000.0);
+
+        // least squares fit of percentage over hours
+        let n = log.len() as f32;
+        let mean_t = log.iter().map(hours).sum::<f32>() / n;
+        let mean_p = log.iter().map(percentage).sum::<f32>() / n;
+        let (mut cov, mut var) = (0.0, 0.0);
+        for sample in log.iter() {
+            let dt = hours(sample) - mean_t;
+            cov += dt * (percentage(sample) - mean_p);
+            var += dt * dt;
+        }
+        if var <= 0.0 {
+            return None;
+        }
+        let slope = cov / var;
+        let span_h = span_s as f32 / 3600.0;
+        let current = (mean_p + slope * (span_h - mean_t)).clamp(0.0, 100.0);
+        let drain_per_hour = -slope;
+
+        let refreshes = last.refreshes.wrapping_sub(first.refreshes);
+        let on_time_ms = last.on_time_ms.wrapping_sub(first.on_time_ms);
+        let capacity_mwh = self.capacity_mah as f32 * self.nominal_voltage;
+        let consumed_mwh = drain_per_hour * span_h / 100.0 * capacity_mwh;
+
+        Some(DrainEstimate {
+            percentage: current,
+            drain_per_hour,
+            remaining_hours: (drain_per_hour > 0.0).then(|| current / drain_per_hour),
+            refreshes_per_hour: refreshes as f32 / span_h,
+            energy_per_refresh_mwh: (refreshes > 0).then(|| consumed_mwh / refreshes as f32),
+            on_time_share: (on_time_ms as f32 / 1000.0 / span_s as f32).min(1.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// 1% per 10mV between 3.2V and 4.2V.
+    const LINEAR: DischargeCurve<'static> = DischargeCurve::new(&[(4.2, 100), (3.2, 0)]);
+
+    fn stats(refreshes: u32, on_time_ms: u32) -> DisplayStats {
+        DisplayStats {
+            refreshes,
+            on_time_ms,
+        }
+    }
+
+    #[test]
+    fn counters_accumulate_across_resets() {
+        let mut log = DrainLog::<8>::new();
+        log.record(0, 4.0, stats(10, 1_000));
+        log.record(60, 4.0, stats(15, 1_500));
+        // the display has been re-created, its counters restart from zero
+        log.record(120, 4.0, stats(3, 200));
+        log.record(180, 4.0, stats(5, 400));
+        let totals: Vec<_> = log.iter().map(|s| (s.refreshes, s.on_time_ms)).collect();
+        assert_eq!(totals, [(10, 1_000), (15, 1_500), (18, 1_700), (20, 1_900)]);
+        assert_eq!(log.iter().last().unwrap().millivolts, 4_000);
+    }
+
+    #[test]
+    fn oldest_samples_are_overwritten() {
+        let mut log = DrainLog::<3>::new();
+        for timestamp in 0..5 {
+            log.push(DrainSample {
+                timestamp,
+                ..Default::default()
+            });
+        }
+        assert_eq!(log.len(), 3);
+        let timestamps: Vec<_> = log.iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, [2, 3, 4]);
+
+        log.clear();
+        assert!(log.is_empty());
+        assert_eq!(log.iter().count(), 0);
+    }
+
+    #[test]
+    fn garbage_is_reset() {
+        // as found in `persistent` RTC memory after power loss
+        let mut log = DrainLog::<4>::new();
+        log.magic = 0xDEAD_BEEF;
+        log.len = 1000;
+        log.next = 77;
+        log.total = stats(123_456, 789);
+        assert!(!log.is_valid());
+        assert!(log.is_empty());
+        assert_eq!(log.iter().count(), 0);
+
+        log.record(10, 4.0, stats(1, 100));
+        assert!(log.is_valid());
+        assert_eq!(log.len(), 1);
+        assert_eq!(log.iter().next().unwrap().refreshes, 1);
+
+        // the magic alone isn't enough
+        log.next = 4;
+        assert!(!log.is_valid());
+        log.push(DrainSample::default());
+        assert_eq!(log.len(), 1);
+    }
+
+    #[test]
+    fn estimate_linear_trace() {
+        // 1% per hour from 80%, 6 refreshes and one minute on-time per hour
+        let mut log = DrainLog::<16>::new();
+        for hour in 0..=10 {
+            let voltage = 4.0 - 0.01 * hour as f32;
+            log.record(hour * 3600, voltage, stats(hour * 6, hour * 60_000));
+        }
+        let estimate = DrainEstimator::new(LINEAR, 1000).estimate(&log).unwrap();
+        assert!((estimate.percentage - 70.0).abs() < 0.1);
+        assert!((estimate.drain_per_hour - 1.0).abs() < 0.01);
+        assert!((estimate.remaining_hours.unwrap() - 70.0).abs() < 1.0);
+        assert!((estimate.refreshes_per_hour - 6.0).abs() < 1e-3);
+        // 1% of 1000mAh at 3.7V per hour, spread over 6 refreshes
+        assert!((estimate.energy_per_refresh_mwh.unwrap() - 37.0 / 6.0).abs() < 0.1);
+        assert!((estimate.on_time_share - 1.0 / 60.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn estimate_noisy_trace() {
+        // 0.5% per hour from 90% with up to 20mV of noise, every 30 minutes
+        let mut seed = 0x2545_f491_u32;
+        let mut noise = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed % 41) as f32 / 1000.0 - 0.02
+        };
+        let mut log = DrainLog::<48>::new();
+        for i in 0..48 {
+            let voltage = 4.1 - 0.005 * i as f32 / 2.0 + noise();
+            log.record(i * 1800, voltage, stats(i, 0));
+        }
+        let estimate = DrainEstimator::new(LINEAR, 1000).estimate(&log).unwrap();
+        assert!((estimate.drain_per_hour - 0.5).abs() < 0.1);
+        assert!((estimate.percentage - 78.25).abs() < 1.5);
+        assert!((estimate.remaining_hours.unwrap() - 156.5).abs() < 40.0);
+        assert!(estimate.energy_per_refresh_mwh.is_some());
+    }
+
+    #[test]
+    fn estimate_while_charging() {
+        let mut log = DrainLog::<4>::new();
+        log.record(0, 3.8, stats(0, 0));
+        log.record(3600, 3.9, stats(0, 0));
+        let estimate = DrainEstimator::new(LINEAR, 1000).estimate(&log).unwrap();
+        assert!((estimate.drain_per_hour + 10.0).abs() < 0.1);
+        assert_eq!(estimate.remaining_hours, None);
+        assert_eq!(estimate.energy_per_refresh_mwh, None);
+    }
+
+    #[test]
+    fn estimate_needs_two_timestamps() {
+        let mut log = DrainLog::<4>::new();
+        let estimator = DrainEstimator::new(LINEAR, 1000);
+        assert_eq!(estimator.estimate(&log), None);
+        log.record(100, 4.0, stats(0, 0));
+        log.record(100, 3.9, stats(0, 0));
+        assert_eq!(estimator.estimate(&log), None);
+    }
+}
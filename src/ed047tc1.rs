@@ -1,7 +1,12 @@
+use core::convert::Infallible;
+
+use embedded_hal::{delay::DelayNs, digital::OutputPin};
+#[cfg(target_arch = "xtensa")]
 use esp_hal::{
+    delay::Delay,
     dma::{self, DmaTxBuf},
     dma_buffers,
-    gpio::{GpioPin, Level, Output, OutputPin},
+    gpio::{GpioPin, Level, Output},
     lcd_cam::{
         lcd::{i8080, i8080::Command},
         LcdCam,
@@ -12,7 +17,11 @@ use esp_hal::{
     Blocking,
 };
 
-use crate::rmt;
+use crate::{
+    hal::DmaError,
+    rmt::{self, PulseOutput},
+    Operation,
+};
 
 const DMA_BUFFER_SIZE: usize = 240;
 
@@ -42,29 +51,25 @@ impl Default for ConfigRegister {
     }
 }
 
-struct ConfigWriter<'a> {
-    pin_data: Output<'a>,
-    pin_clk: Output<'a>,
-    pin_str: Output<'a>,
+struct ConfigWriter<P> {
+    pin_data: P,
+    pin_clk: P,
+    pin_str: P,
     config: ConfigRegister,
 }
 
-impl<'a> ConfigWriter<'a> {
-    fn new(
-        data: impl Peripheral<P = impl OutputPin> + 'a,
-        clk: impl Peripheral<P = impl OutputPin> + 'a,
-        str: impl Peripheral<P = impl OutputPin> + 'a,
-    ) -> Self {
+impl<P: OutputPin<Error = Infallible>> ConfigWriter<P> {
+    fn new(data: P, clk: P, str: P) -> Self {
         ConfigWriter {
-            pin_data: Output::new(data, Level::High),
-            pin_clk: Output::new(clk, Level::High),
-            pin_str: Output::new(str, Level::Low),
+            pin_data: data,
+            pin_clk: clk,
+            pin_str: str,
             config: ConfigRegister::default(),
         }
     }
 
     fn write(&mut self) {
-        self.pin_str.set_low();
+        let _ = self.pin_str.set_low();
         self.write_bool(self.config.output_enable);
         self.write_bool(self.config.mode);
         self.write_bool(self.config.power_enable);
@@ -73,20 +78,18 @@ impl<'a> ConfigWriter<'a> {
         self.write_bool(self.config.pos_power_enable);
         self.write_bool(self.config.power_disable);
         self.write_bool(self.config.latch_enable);
-        self.pin_str.set_high();
+        let _ = self.pin_str.set_high();
     }
 
     #[inline(always)]
     fn write_bool(&mut self, v: bool) {
-        self.pin_clk.set_low();
-        self.pin_data.set_level(match v {
-            true => Level::High,
-            false => Level::Low,
-        });
-        self.pin_clk.set_high();
+        let _ = self.pin_clk.set_low();
+        let _ = self.pin_data.set_state(v.into());
+        let _ = self.pin_clk.set_high();
     }
 }
 
+#[cfg(target_arch = "xtensa")]
 pub struct PinConfig {
     pub data0: GpioPin<6>,
     pub data1: GpioPin<7>,
@@ -104,76 +107,112 @@ pub struct PinConfig {
     pub rmt: GpioPin<38>,
 }
 
-pub(crate) struct ED047TC1<'a> {
-    i8080: Option<i8080::I8080<'a, Blocking>>,
-    cfg_writer: ConfigWriter<'a>,
-    rmt: rmt::Rmt<'a>,
-    dma_buf: Option<DmaTxBuf>,
+/// Parallel bus clocking the rows out to the panel. A transfer consumes the
+/// bus and its DMA buffer and hands both back when it's done, whether it
+/// succeeded or not.
+pub(crate) trait LcdBus: Sized {
+    type Buffer;
+
+    /// The bytes sent by the next transfer.
+    fn buffer(buffer: &mut Self::Buffer) -> &mut [u8];
+
+    /// Sends the buffer and waits for the transfer to finish.
+    fn transfer(self, buffer: Self::Buffer) -> (Result<(), DmaError>, Self, Self::Buffer);
 }
 
-impl<'a> ED047TC1<'a> {
-    pub(crate) fn new(
-        pins: PinConfig,
-        dma: impl Peripheral<P = peripherals::DMA> + 'a,
-        lcd_cam: impl Peripheral<P = peripherals::LCD_CAM> + 'a,
-        rmt: impl Peripheral<P = peripherals::RMT> + 'a,
-    ) -> crate::Result<Self> {
-        // configure data pins
-        let tx_pins = i8080::TxEightBits::new(
-            pins.data0, pins.data1, pins.data2, pins.data3, pins.data4, pins.data5, pins.data6,
-            pins.data7,
-        );
+/// Monotonic time, e.g. for the refresh statistics of the
+/// [Display](crate::Display).
+pub(crate) trait Clock {
+    /// Microseconds since an arbitrary point in time, e.g. boot.
+    fn now_us(&self) -> u64;
+}
 
-        // configure dma
-        let dma = dma::Dma::new(dma);
-        let channel = dma.channel0.configure(false, dma::DmaPriority::Priority0);
+/// Peripherals driving the panel: the ones of the ESP32-S3 in the driver,
+/// mocks on the host.
+pub(crate) trait Hardware<'a> {
+    type Bus: LcdBus;
+    type Output: PulseOutput<'a>;
+    type Pin: OutputPin<Error = Infallible>;
+    type Delay: DelayNs;
+    type Clock: Clock;
+}
 
-        // init lcd
-        let lcd_cam = LcdCam::new(lcd_cam);
+/// The panel as driven by [Display](crate::Display).
+#[cfg(target_arch = "xtensa")]
+pub(crate) type Panel<'a> = ED047TC1<'a, Esp>;
+#[cfg(not(target_arch = "xtensa"))]
+pub(crate) type Panel<'a> = ED047TC1<'a, mock::Mock>;
+
+pub(crate) struct ED047TC1<'a, H: Hardware<'a>> {
+    bus: Option<H::Bus>,
+    cfg_writer: ConfigWriter<H::Pin>,
+    rmt: rmt::Rmt<'a, H::Output>,
+    dma_buf: Option<<H::Bus as LcdBus>::Buffer>,
+    delay: H::Delay,
+    clock: H::Clock,
+    /// Row of the current frame, advanced by every gate clock pulse.
+    row: u16,
+    powered: bool,
+}
 
+impl<'a, H: Hardware<'a>> ED047TC1<'a, H> {
+    /// Sets up the panel from its peripherals. `config_pins` are the data,
+    /// clock and strobe pins of the config register.
+    pub(crate) fn from_parts(
+        bus: H::Bus,
+        dma_buf: <H::Bus as LcdBus>::Buffer,
+        config_pins: (H::Pin, H::Pin, H::Pin),
+        output: H::Output,
+        delay: H::Delay,
+        clock: H::Clock,
+    ) -> crate::Result<Self> {
         // init panel config writer (?)
-        let mut cfg_writer = ConfigWriter::new(pins.cfg_data, pins.cfg_clk, pins.cfg_str);
+        let (data, clk, str) = config_pins;
+        let mut cfg_writer = ConfigWriter::new(data, clk, str);
         cfg_writer.write();
 
-        let (_, _, tx_buffer, tx_descriptors) = dma_buffers!(0, DMA_BUFFER_SIZE);
-        let dma_buf =
-            Some(DmaTxBuf::new(tx_descriptors, tx_buffer).map_err(crate::Error::DmaBuffer)?);
-
-        let ctrl = ED047TC1 {
-            i8080: Some(
-                i8080::I8080::new(
-                    lcd_cam.lcd,
-                    channel.tx,
-                    tx_pins,
-                    10.MHz(),
-                    i8080::Config {
-                        cd_idle_edge: false,  // dc_idle_level
-                        cd_cmd_edge: true,    // dc_cmd_level
-                        cd_dummy_edge: false, // dc_dummy_level
-                        cd_data_edge: false,  // dc_data_level
-                        ..Default::default()
-                    },
-                )
-                .with_ctrl_pins(pins.lcd_dc, pins.lcd_wrx),
-            ),
+        let rmt = rmt::Rmt::new(output).map_err(|err| err.during(Operation::Init))?;
+
+        Ok(ED047TC1 {
+            bus: Some(bus),
             cfg_writer,
-            rmt: rmt::Rmt::new(rmt),
-            dma_buf,
-        };
-        Ok(ctrl)
+            rmt,
+            dma_buf: Some(dma_buf),
+            delay,
+            clock,
+            row: 0,
+            powered: false,
+        })
+    }
+
+    pub(crate) fn is_powered(&self) -> bool {
+        self.powered
+    }
+
+    /// Current time of the clock in microseconds.
+    pub(crate) fn now_us(&self) -> u64 {
+        self.clock.now_us()
+    }
+
+    /// Returns whether the bus, its buffer and the pulse channel are idle and
+    /// ready for the next frame.
+    #[cfg(test)]
+    pub(crate) fn is_idle(&self) -> bool {
+        self.bus.is_some() && self.dma_buf.is_some() && self.rmt.is_idle()
     }
 
     pub(crate) fn power_on(&mut self) {
+        self.powered = true;
         self.cfg_writer.config.power_enable = true;
         self.cfg_writer.config.power_disable = false;
         self.cfg_writer.write();
-        busy_delay(100 * 240);
+        self.delay.delay_us(100);
         self.cfg_writer.config.neg_power_enable = true;
         self.cfg_writer.write();
-        busy_delay(500 * 240);
+        self.delay.delay_us(500);
         self.cfg_writer.config.pos_power_enable = true;
         self.cfg_writer.write();
-        busy_delay(100 * 240);
+        self.delay.delay_us(100);
         self.cfg_writer.config.stv = true;
         self.cfg_writer.write();
     }
@@ -182,38 +221,51 @@ impl<'a> ED047TC1<'a> {
         self.cfg_writer.config.power_enable = false;
         self.cfg_writer.config.pos_power_enable = false;
         self.cfg_writer.write();
-        busy_delay(10 * 240);
+        self.delay.delay_us(10);
         self.cfg_writer.config.neg_power_enable = false;
         self.cfg_writer.write();
-        busy_delay(100 * 240);
+        self.delay.delay_us(100);
         self.cfg_writer.config.power_disable = true;
         self.cfg_writer.config.mode = false;
         // self.cfg_writer.write();
         self.cfg_writer.config.stv = false;
         self.cfg_writer.write();
+        self.powered = false;
+    }
+
+    /// Turns the panel off if it is still powered, even in the middle of a
+    /// frame. The high voltage rails must never be left on.
+    pub(crate) fn shutdown(&mut self) {
+        if self.powered {
+            self.cfg_writer.config.output_enable = false;
+            self.cfg_writer.write();
+            self.power_off();
+        }
     }
 
     pub(crate) fn frame_start(&mut self) -> crate::Result<()> {
+        let err = |err: rmt::PulseError| err.during(Operation::FrameStart);
+        self.row = 0;
         self.cfg_writer.config.mode = true;
         self.cfg_writer.write();
 
-        self.rmt.pulse(10, 10, true)?;
+        self.rmt.pulse(10, 10, true).map_err(err)?;
 
         self.cfg_writer.config.stv = false;
         self.cfg_writer.write();
 
-        self.rmt.pulse(10000, 1000, false)?;
+        self.rmt.pulse(10000, 1000, false).map_err(err)?;
         self.cfg_writer.config.stv = true;
         self.cfg_writer.write();
         // self.rmt.pulse(0, 100, true)?;
-        self.rmt.pulse(10, 10, true)?;
-        self.rmt.pulse(10, 10, true)?;
-        self.rmt.pulse(10, 10, true)?;
-        self.rmt.pulse(10, 10, true)?;
+        self.rmt.pulse(10, 10, true).map_err(err)?;
+        self.rmt.pulse(10, 10, true).map_err(err)?;
+        self.rmt.pulse(10, 10, true).map_err(err)?;
+        self.rmt.pulse(10, 10, true).map_err(err)?;
 
         self.cfg_writer.config.output_enable = true;
         self.cfg_writer.write();
-        self.rmt.pulse(10, 10, true)?;
+        self.rmt.pulse(10, 10, true).map_err(err)?;
 
         Ok(())
     }
@@ -227,57 +279,323 @@ impl<'a> ED047TC1<'a> {
     }
 
     pub(crate) fn skip(&mut self) -> crate::Result<()> {
-        self.rmt.pulse(45, 5, false)?;
-        Ok(())
+        let operation = self.next_row();
+        self.rmt
+            .pulse(45, 5, false)
+            .map_err(|err| err.during(operation))
     }
 
     pub(crate) fn output_row(&mut self, output_time: u16) -> crate::Result<()> {
+        let operation = self.next_row();
         self.latch_row();
-        self.rmt.pulse(output_time, 50, false)?;
-        let i8080 = self.i8080.take().ok_or(crate::Error::Unknown)?;
-        let dma_buf = self.dma_buf.take().ok_or(crate::Error::Unknown)?;
-        let tx = i8080
-            .send(Command::<u8>::One(0), 0, dma_buf)
-            .map_err(|(err, i8080, buf)| {
-                self.dma_buf = Some(buf);
-                self.i8080 = Some(i8080);
-                crate::Error::Dma(err)
-            })?;
-        let (r, i8080, dma_buf) = tx.wait();
-        r.map_err(crate::Error::Dma)?;
-        self.i8080 = Some(i8080);
+        self.rmt
+            .pulse(output_time, 50, false)
+            .map_err(|err| err.during(operation))?;
+        let (bus, dma_buf) = self.take_bus(operation)?;
+        // the transfer always hands the bus and buffer back, restore them before
+        // looking at the result
+        let (r, bus, dma_buf) = bus.transfer(dma_buf);
+        self.bus = Some(bus);
         self.dma_buf = Some(dma_buf);
+        r.map_err(|err| crate::Error::Dma(operation, err))
+    }
 
-        Ok(())
+    /// Returns the operation for the row about to be clocked out and advances
+    /// the row counter.
+    fn next_row(&mut self) -> Operation {
+        let operation = Operation::Row(self.row);
+        self.row = self.row.saturating_add(1);
+        operation
     }
 
     pub(crate) fn frame_end(&mut self) -> crate::Result<()> {
+        let err = |err: rmt::PulseError| err.during(Operation::FrameEnd);
         self.cfg_writer.config.output_enable = false;
         self.cfg_writer.write();
         self.cfg_writer.config.mode = true;
         self.cfg_writer.write();
-        self.rmt.pulse(10, 10, true)?;
-        self.rmt.pulse(10, 10, true)?;
+        self.rmt.pulse(10, 10, true).map_err(err)?;
+        self.rmt.pulse(10, 10, true).map_err(err)?;
 
         Ok(())
     }
 
     pub(crate) fn set_buffer(&mut self, data: &[u8]) -> crate::Result<()> {
-        let mut dma_buf = self.dma_buf.take().ok_or(crate::Error::Unknown)?;
-        dma_buf.as_mut_slice().fill(0);
-        dma_buf.as_mut_slice()[..data.len()].copy_from_slice(data);
-        self.dma_buf = Some(dma_buf);
+        let dma_buf = self
+            .dma_buf
+            .as_mut()
+            .ok_or(crate::Error::BusBusy(Operation::Row(self.row)))?;
+        let buffer = H::Bus::buffer(dma_buf);
+        buffer.fill(0);
+        buffer[..data.len()].copy_from_slice(data);
         Ok(())
     }
+
+    /// Takes the bus and the dma buffer for a transfer. Either both are
+    /// returned or both stay in place.
+    fn take_bus(
+        &mut self,
+        operation: Operation,
+    ) -> crate::Result<(H::Bus, <H::Bus as LcdBus>::Buffer)> {
+        match (self.bus.take(), self.dma_buf.take()) {
+            (Some(bus), Some(dma_buf)) => Ok((bus, dma_buf)),
+            (bus, dma_buf) => {
+                self.bus = bus;
+                self.dma_buf = dma_buf;
+                Err(crate::Error::BusBusy(operation))
+            }
+        }
+    }
+}
+
+impl<'a, H: Hardware<'a>> Drop for ED047TC1<'a, H> {
+    /// Turns the panel off if it is still powered, also when the
+    /// [Display](crate::Display) owning it is dropped. The peripherals are
+    /// released afterwards.
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// The peripherals of the ESP32-S3.
+#[cfg(target_arch = "xtensa")]
+pub(crate) enum Esp {}
+
+#[cfg(target_arch = "xtensa")]
+impl<'a> Hardware<'a> for Esp {
+    type Bus = i8080::I8080<'a, Blocking>;
+    type Output = rmt::RmtOutput<'a>;
+    type Pin = Output<'a>;
+    type Delay = Delay;
+    type Clock = SystemTimer;
+}
+
+/// The system timer, counting microseconds since boot.
+#[cfg(target_arch = "xtensa")]
+pub(crate) struct SystemTimer;
+
+#[cfg(target_arch = "xtensa")]
+impl Clock for SystemTimer {
+    fn now_us(&self) -> u64 {
+        esp_hal::time::now().ticks()
+    }
+}
+
+#[cfg(target_arch = "xtensa")]
+impl LcdBus for i8080::I8080<'_, Blocking> {
+    type Buffer = DmaTxBuf;
+
+    fn buffer(buffer: &mut DmaTxBuf) -> &mut [u8] {
+        buffer.as_mut_slice()
+    }
+
+    fn transfer(self, buffer: DmaTxBuf) -> (Result<(), DmaError>, Self, DmaTxBuf) {
+        match self.send(Command::<u8>::One(0), 0, buffer) {
+            Ok(tx) => tx.wait(),
+            Err((err, i8080, buffer)) => (Err(err), i8080, buffer),
+        }
+    }
 }
 
-#[inline(always)]
-fn busy_delay(wait_cycles: u32) {
-    let target = cycles() + wait_cycles as u64;
-    while cycles() < target {}
+#[cfg(target_arch = "xtensa")]
+impl<'a> ED047TC1<'a, Esp> {
+    pub(crate) fn new(
+        pins: PinConfig,
+        dma: impl Peripheral<P = peripherals::DMA> + 'a,
+        lcd_cam: impl Peripheral<P = peripherals::LCD_CAM> + 'a,
+        rmt: impl Peripheral<P = peripherals::RMT> + 'a,
+    ) -> crate::Result<Self> {
+        // configure data pins
+        let tx_pins = i8080::TxEightBits::new(
+            pins.data0, pins.data1, pins.data2, pins.data3, pins.data4, pins.data5, pins.data6,
+            pins.data7,
+        );
+
+        // configure dma
+        let dma = dma::Dma::new(dma);
+        let channel = dma.channel0.configure(false, dma::DmaPriority::Priority0);
+
+        // init lcd
+        let lcd_cam = LcdCam::new(lcd_cam);
+
+        let (_, _, tx_buffer, tx_descriptors) = dma_buffers!(0, DMA_BUFFER_SIZE);
+        let dma_buf = DmaTxBuf::new(tx_descriptors, tx_buffer).map_err(crate::Error::DmaBuffer)?;
+
+        let i8080 = i8080::I8080::new(
+            lcd_cam.lcd,
+            channel.tx,
+            tx_pins,
+            10.MHz(),
+            i8080::Config {
+                cd_idle_edge: false,  // dc_idle_level
+                cd_cmd_edge: true,    // dc_cmd_level
+                cd_dummy_edge: false, // dc_dummy_level
+                cd_data_edge: false,  // dc_data_level
+                ..Default::default()
+            },
+        )
+        .with_ctrl_pins(pins.lcd_dc, pins.lcd_wrx);
+
+        Self::from_parts(
+            i8080,
+            dma_buf,
+            (
+                Output::new(pins.cfg_data, Level::High),
+                Output::new(pins.cfg_clk, Level::High),
+                Output::new(pins.cfg_str, Level::Low),
+            ),
+            rmt::RmtOutput::new(rmt, pins.rmt),
+            Delay::new(),
+            SystemTimer,
+        )
+    }
 }
 
-#[inline(always)]
-fn cycles() -> u64 {
-    esp_hal::xtensa_lx::timer::get_cycle_count() as u64
+/// Stand-ins for the peripherals on the host, failing on demand.
+#[cfg(not(target_arch = "xtensa"))]
+pub(crate) mod mock {
+    // only the tests create a panel
+    #![cfg_attr(not(test), allow(dead_code))]
+
+    use alloc::{rc::Rc, vec, vec::Vec};
+    use core::{cell::Cell, convert::Infallible};
+
+    use embedded_hal::{delay::DelayNs, digital};
+
+    use super::{Hardware, LcdBus, DMA_BUFFER_SIZE, ED047TC1};
+    use crate::{
+        hal::{DmaError, RmtError as Error},
+        rmt::{PulseChannel, PulseOutput, PulseTransaction},
+    };
+
+    /// Number of upcoming calls to fail and the time of the clock, shared
+    /// with the test.
+    #[derive(Default)]
+    pub(crate) struct Faults {
+        /// Failing LCD transfers, the bus and buffer are handed back.
+        pub(crate) transfer: Cell<u32>,
+        /// Failing pulse transmissions, the channel is lost.
+        pub(crate) transmit: Cell<u32>,
+        /// Failing waits for a pulse, the channel is handed back.
+        pub(crate) pulse: Cell<u32>,
+        /// Number of times the pulse channel has been configured.
+        pub(crate) configured: Cell<u32>,
+        /// Time of the clock in microseconds, only advanced by the test.
+        pub(crate) now_us: Cell<u64>,
+    }
+
+    fn fail(count: &Cell<u32>) -> bool {
+        let failing = count.get() > 0;
+        count.set(count.get().saturating_sub(1));
+        failing
+    }
+
+    pub(crate) enum Mock {}
+
+    impl<'a> Hardware<'a> for Mock {
+        type Bus = Bus;
+        type Output = Output;
+        type Pin = Pin;
+        type Delay = Delay;
+        type Clock = Clock;
+    }
+
+    pub(crate) struct Bus(Rc<Faults>);
+
+    impl LcdBus for Bus {
+        type Buffer = Vec<u8>;
+
+        fn buffer(buffer: &mut Vec<u8>) -> &mut [u8] {
+            buffer
+        }
+
+        fn transfer(self, buffer: Vec<u8>) -> (Result<(), DmaError>, Self, Vec<u8>) {
+            if fail(&self.0.transfer) {
+                return (Err(DmaError::DescriptorError), self, buffer);
+            }
+            (Ok(()), self, buffer)
+        }
+    }
+
+    pub(crate) struct Output(Rc<Faults>);
+
+    impl PulseOutput<'_> for Output {
+        type Channel = Channel;
+
+        fn configure(&mut self) -> Result<Channel, Error> {
+            self.0.configured.set(self.0.configured.get() + 1);
+            Ok(Channel(self.0.clone()))
+        }
+    }
+
+    pub(crate) struct Channel(Rc<Faults>);
+
+    impl PulseChannel<'_> for Channel {
+        type Transaction = Transaction;
+
+        fn transmit(self, _data: &[u32]) -> Result<Transaction, Error> {
+            if fail(&self.0.transmit) {
+                return Err(Error::TransmissionError);
+            }
+            Ok(Transaction(self))
+        }
+    }
+
+    pub(crate) struct Transaction(Channel);
+
+    impl PulseTransaction for Transaction {
+        type Channel = Channel;
+
+        fn wait(self) -> Result<Channel, (Error, Channel)> {
+            if fail(&self.0 .0.pulse) {
+                return Err((Error::TransmissionError, self.0));
+            }
+            Ok(self.0)
+        }
+    }
+
+    pub(crate) struct Pin;
+
+    impl digital::ErrorType for Pin {
+        type Error = Infallible;
+    }
+
+    impl digital::OutputPin for Pin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    pub(crate) struct Delay;
+
+    impl DelayNs for Delay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    pub(crate) struct Clock(Rc<Faults>);
+
+    impl super::Clock for Clock {
+        fn now_us(&self) -> u64 {
+            self.0.now_us.get()
+        }
+    }
+
+    /// A panel on mocked peripherals failing as set in the returned faults.
+    pub(crate) fn panel<'a>() -> (ED047TC1<'a, Mock>, Rc<Faults>) {
+        let faults = Rc::new(Faults::default());
+        let panel = ED047TC1::from_parts(
+            Bus(faults.clone()),
+            vec![0; DMA_BUFFER_SIZE],
+            (Pin, Pin, Pin),
+            Output(faults.clone()),
+            Delay,
+            Clock(faults.clone()),
+        )
+        .unwrap();
+        (panel, faults)
+    }
 }
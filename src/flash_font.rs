@@ -0,0 +1,621 @@
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use embedded_storage::ReadStorage;
+
+use crate::{
+    font::{bitmap_end, sealed, u16_at, u32_at, Glyph, TextFont},
+    Error,
+    Font4bpp,
+    Result,
+};
+
+const GLYPH_SIZE: usize = Font4bpp::GLYPH_SIZE;
+
+/// A [Font4bpp] read from flash or any other [ReadStorage] glyph by glyph,
+/// for fonts too large to be embedded into the firmware, like CJK fonts with
+/// thousands of glyphs.
+///
+/// Only the header is read up front. Glyphs are looked up when they're drawn
+/// or measured and kept in a cache of the most recently used glyphs, so text
+/// with repeated characters is read from the storage only once. The font is
+/// drawn with [Display::draw_text](crate::Display::draw_text) and laid out
+/// with [FontStyle](crate::FontStyle) like a [Font4bpp].
+///
+/// ```rust ignore
+/// // font written to a data partition labeled "font", see the `cjk` example
+/// let mut flash = FlashStorage::new();
+/// let partition = Partition::find(&mut flash, "font")?.expect("no font partition");
+/// let font = FlashFont::new(flash, partition.offset, partition.size, 128)?;
+/// display.draw_text(&font, "你好，世界", 20, 20 + font.ascent(), 0)?;
+/// ```
+pub struct FlashFont<S> {
+    line_height: u16,
+    ascent: u16,
+    descent: u16,
+    default_glyph: Option<u16>,
+    range_count: u16,
+    glyph_count: u16,
+    pair_count: u16,
+    /// Storage offsets of the tables.
+    ranges: u32,
+    glyphs: u32,
+    kerning: u32,
+    bitmaps: u32,
+    /// Size of the font after the start of the bitmaps.
+    bitmaps_len: usize,
+    cache: RefCell<Cache<S>>,
+}
+
+/// Storage and recently used glyphs of a [FlashFont].
+struct Cache<S> {
+    storage: S,
+    entries: Vec<Entry>,
+    capacity: usize,
+    clock: u32,
+    hits: u32,
+    misses: u32,
+}
+
+struct Entry {
+    c: char,
+    /// Index and table entry of the glyph drawn for the character, `None` if
+    /// the font has neither the character nor a default glyph.
+    glyph: Option<(u16, [u8; GLYPH_SIZE])>,
+    data: Vec<u8>,
+    /// Value of the clock at the last use.
+    used: u32,
+}
+
+impl<S: ReadStorage> Cache<S> {
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<()> {
+        self.storage.read(offset, bytes).map_err(|_| Error::Storage)
+    }
+}
+
+impl<S: ReadStorage> FlashFont<S> {
+    /// Open the serialized font in the `len` bytes at `offset` in the
+    /// storage, e.g. a [Partition], see [Font4bpp] for the format, keeping up
+    /// to `cache_size` glyphs in RAM.
+    ///
+    /// Returns [Error::InvalidFont] if the header is unknown or the tables
+    /// exceed `len` bytes or the storage, and [Error::Storage] if the header
+    /// can't be read. Glyphs are checked when they're read.
+    pub fn new(mut storage: S, offset: u32, len: u32, cache_size: usize) -> Result<Self> {
+        let end = offset.checked_add(len).ok_or(Error::InvalidFont)? as usize;
+        if (len as usize) < Font4bpp::HEADER_SIZE || end > storage.capacity() {
+            return Err(Error::InvalidFont);
+        }
+        let mut header = [0; Font4bpp::HEADER_SIZE];
+        storage
+            .read(offset, &mut header)
+            .map_err(|_| Error::Storage)?;
+        if header[0..2] != Font4bpp::MAGIC || header[2] != Font4bpp::VERSION {
+            return Err(Error::InvalidFont);
+        }
+        let field = |offset: usize| u16_at(&header, offset);
+        let (range_count, glyph_count, pair_count) = (field(10), field(12), field(14));
+        let ranges = offset as usize + Font4bpp::HEADER_SIZE;
+        let glyphs = ranges + range_count as usize * Font4bpp::RANGE_SIZE;
+        let kerning = glyphs + glyph_count as usize * GLYPH_SIZE;
+        let bitmaps = kerning + pair_count as usize * Font4bpp::PAIR_SIZE;
+        let default_glyph = Some(field(16)).filter(|&glyph| glyph != u16::MAX);
+        if bitmaps > end
+            || bitmaps > u32::MAX as usize
+            || default_glyph.is_some_and(|glyph| glyph >= glyph_count)
+        {
+            return Err(Error::InvalidFont);
+        }
+        Ok(FlashFont {
+            line_height: field(4),
+            ascent: field(6),
+            descent: field(8),
+            default_glyph,
+            range_count,
+            glyph_count,
+            pair_count,
+            ranges: ranges as u32,
+            glyphs: glyphs as u32,
+            kerning: kerning as u32,
+            bitmaps: bitmaps as u32,
+            bitmaps_len: end - bitmaps,
+            cache: RefCell::new(Cache {
+                storage,
+                entries: Vec::new(),
+                capacity: cache_size.max(1),
+                clock: 0,
+                hits: 0,
+                misses: 0,
+            }),
+        })
+    }
+
+    /// Release the storage.
+    pub fn release(self) -> S {
+        self.cache.into_inner().storage
+    }
+
+    /// Distance between the baselines of two lines in pixels.
+    pub fn line_height(&self) -> u16 {
+        self.line_height
+    }
+
+    /// Height of the font above the baseline in pixels.
+    pub fn ascent(&self) -> u16 {
+        self.ascent
+    }
+
+    /// Depth of the font below the baseline in pixels.
+    pub fn descent(&self) -> u16 {
+        self.descent
+    }
+
+    /// Number of glyphs in the font.
+    pub fn glyph_count(&self) -> u16 {
+        self.glyph_count
+    }
+
+    /// Width of a single line of text in pixels, see [TextFont::text_width].
+    pub fn text_width(&self, text: &str) -> u16 {
+        TextFont::text_width(self, text)
+    }
+
+    /// Whether the font has a glyph for the character, not counting the
+    /// default glyph, e.g. to fall back to another font.
+    pub fn contains(&self, c: char) -> Result<bool> {
+        let mut cache = self.cache.borrow_mut();
+        Ok(self.find(&mut cache, c)?.is_some())
+    }
+
+    /// Number of glyph lookups answered from the cache.
+    pub fn cache_hits(&self) -> u32 {
+        self.cache.borrow().hits
+    }
+
+    /// Number of glyph lookups that read from the storage.
+    pub fn cache_misses(&self) -> u32 {
+        self.cache.borrow().misses
+    }
+
+    /// Drop all cached glyphs and free their memory.
+    pub fn clear_cache(&self) {
+        self.cache.borrow_mut().entries = Vec::new();
+    }
+
+    /// Index of the glyph of a character, searching the ranges.
+    fn find(&self, cache: &mut Cache<S>, c: char) -> Result<Option<u16>> {
+        let c = c as u32;
+        let (mut low, mut high) = (0, self.range_count as u32);
+        let mut range = [0; Font4bpp::RANGE_SIZE];
+        while low < high {
+            let mid = (low + high) / 2;
+            cache.read(self.ranges + mid * Font4bpp::RANGE_SIZE as u32, &mut range)?;
+            let first = u32_at(&range, 0);
+            if c < first {
+                high = mid;
+            } else if c - first >= u16_at(&range, 4) as u32 {
+                low = mid + 1;
+            } else {
+                let index = u16_at(&range, 6) as u32 + (c - first);
+                if index >= self.glyph_count as u32 {
+                    return Err(Error::InvalidFont);
+                }
+                return Ok(Some(index as u16));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Slot of the cache entry of a character, reading the glyph on a miss
+    /// and replacing the least recently used entry if the cache is full.
+    fn entry(&self, cache: &mut Cache<S>, c: char) -> Result<usize> {
+        cache.clock = cache.clock.wrapping_add(1);
+        if let Some(slot) = cache.entries.iter().position(|entry| entry.c == c) {
+            cache.hits = cache.hits.wrapping_add(1);
+            cache.entries[slot].used = cache.clock;
+            return Ok(slot);
+        }
+        cache.misses = cache.misses.wrapping_add(1);
+
+        let mut glyph = None;
+        let mut len = 0;
+        if let Some(index) = self.find(cache, c)?.or(self.default_glyph) {
+            let mut record = [0; GLYPH_SIZE];
+            cache.read(self.glyphs + index as u32 * GLYPH_SIZE as u32, &mut record)?;
+            if bitmap_end(&record) > self.bitmaps_len {
+                return Err(Error::InvalidFont);
+            }
+            len = bitmap_end(&record) - u32_at(&record, 0) as usize;
+            glyph = Some((index, record));
+        }
+        let slot = if cache.entries.len() < cache.capacity {
+            cache.entries.push(Entry {
+                c,
+                glyph: None,
+                data: Vec::new(),
+                used: 0,
+            });
+            cache.entries.len() - 1
+        } else {
+            let clock = cache.clock;
+            (0..cache.entries.len())
+                .max_by_key(|&slot| clock.wrapping_sub(cache.entries[slot].used))
+                .unwrap_or(0)
+        };
+
+        // read into the buffer of the replaced glyph
+        let mut data = core::mem::take(&mut cache.entries[slot].data);
+        data.clear();
+        data.resize(len, 0);
+        if let Some((_, record)) = &glyph {
+            if let Err(err) = cache.read(self.bitmaps + u32_at(record, 0), &mut data) {
+                // don't leave a partially read glyph behind
+                cache.entries.swap_remove(slot);
+                return Err(err);
+            }
+        }
+        cache.entries[slot] = Entry {
+            c,
+            glyph,
+            data,
+            used: cache.clock,
+        };
+        Ok(slot)
+    }
+
+    /// Adjustment of the advance between two glyphs in 1/16 pixels.
+    fn kerning(&self, cache: &mut Cache<S>, left: u16, right: u16) -> Result<i16> {
+        let (mut low, mut high) = (0, self.pair_count as u32);
+        let mut pair = [0; Font4bpp::PAIR_SIZE];
+        while low < high {
+            let mid = (low + high) / 2;
+            cache.read(self.kerning + mid * Font4bpp::PAIR_SIZE as u32, &mut pair)?;
+            match (u16_at(&pair, 0), u16_at(&pair, 2)).cmp(&(left, right)) {
+                core::cmp::Ordering::Less => low = mid + 1,
+                core::cmp::Ordering::Greater => high = mid,
+                core::cmp::Ordering::Equal => return Ok(u16_at(&pair, 4) as i16),
+            }
+        }
+        Ok(0)
+    }
+}
+
+impl<S: ReadStorage> TextFont for FlashFont<S> {
+    fn line_height(&self) -> u16 {
+        self.line_height
+    }
+
+    fn ascent(&self) -> u16 {
+        self.ascent
+    }
+
+    fn descent(&self) -> u16 {
+        self.descent
+    }
+}
+
+impl<S: ReadStorage> sealed::Layout for FlashFont<S> {
+    fn layout(&self, text: &str, f: &mut dyn FnMut(&Glyph<'_>, i32)) -> Result<()> {
+        let mut cache = self.cache.borrow_mut();
+        let cache = &mut *cache;
+        let mut pen = 0i32;
+        let mut previous = None;
+        for c in text.chars() {
+            if c.is_control() {
+                previous = None;
+                continue;
+            }
+            let slot = self.entry(cache, c)?;
+            let Some((index, record)) = cache.entries[slot].glyph else {
+                continue;
+            };
+            if let Some(previous) = previous {
+                pen += self.kerning(cache, previous, index)? as i32;
+            }
+            let glyph = Glyph::from_record(index, &record, &cache.entries[slot].data);
+            f(&glyph, pen);
+            pen += glyph.advance() as i32;
+            previous = Some(index);
+        }
+        Ok(())
+    }
+}
+
+/// An entry of the ESP-IDF partition table, e.g. a data partition holding a
+/// [FlashFont].
+///
+/// A font is written to its own partition by adding it to the partition
+/// table, e.g. `font, data, 0x40, 0x410000, 0xBF0000,` with a custom subtype,
+/// and flashing the font file to its offset with
+/// `espflash write-bin 0x410000 font.f4`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Partition {
+    /// Type of the partition, 0 for apps and 1 for data.
+    pub kind: u8,
+    pub subtype: u8,
+    /// Offset of the partition in the flash.
+    pub offset: u32,
+    /// Size of the partition in bytes.
+    pub size: u32,
+}
+
+impl Partition {
+    /// Default offset of the partition table in the flash.
+    pub const TABLE_OFFSET: u32 = 0x8000;
+
+    const MAX_ENTRIES: u32 = 95;
+    const ENTRY_SIZE: usize = 32;
+    const MAGIC: [u8; 2] = [0xAA, 0x50];
+
+    /// Look up a partition by its label in the partition table at
+    /// [Partition::TABLE_OFFSET].
+    pub fn find<S: ReadStorage>(storage: &mut S, label: &str) -> Result<Option<Partition>> {
+        Self::find_at(storage, Self::TABLE_OFFSET, label)
+    }
+
+    /// Look up a partition by its label in the partition table at `offset`.
+    pub fn find_at<S: ReadStorage>(
+        storage: &mut S,
+        offset: u32,
+        label: &str,
+    ) -> Result<Option<Partition>> {
+        let mut entry = [0; Self::ENTRY_SIZE];
+        for index in 0..Self::MAX_ENTRIES {
+            storage
+                .read(offset + index * Self::ENTRY_SIZE as u32, &mut entry)
+                .map_err(|_| Error::Storage)?;
+            // the table ends with an MD5 checksum entry or erased flash
+            if entry[0..2] != Self::MAGIC {
+                break;
+            }
+            let name = &entry[12..28];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            if name == label.as_bytes() {
+                return Ok(Some(Partition {
+                    kind: entry[2],
+                    subtype: entry[3],
+                    offset: u32_at(&entry, 4),
+                    size: u32_at(&entry, 8),
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A [ReadStorage] over bytes in RAM, e.g. a font loaded into PSRAM from an
+/// SD card, or a stand-in for the flash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RamStorage<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> RamStorage<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        RamStorage { data }
+    }
+}
+
+impl ReadStorage for RamStorage<'_> {
+    type Error = Error;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<()> {
+        let start = offset as usize;
+        let data = self
+            .data
+            .get(start..start + bytes.len())
+            .ok_or(Error::Storage)?;
+        bytes.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// A font with the glyphs 0 to 2 for `A` to `C`, 3 for `x` and 4 for
+    /// missing characters, 5 to 9 pixels wide, and a kerning pair `AB`.
+    fn font_data(default_glyph: u16) -> Vec<u8> {
+        let mut font = Vec::new();
+        font.extend_from_slice(b"F4\x01\x00");
+        for field in [20u16, 15, 5, 2, 5, 1, default_glyph, 0] {
+            font.extend_from_slice(&field.to_le_bytes());
+        }
+        for (first, count, glyph) in [('A', 3u16, 0u16), ('x', 1, 3)] {
+            font.extend_from_slice(&(first as u32).to_le_bytes());
+            font.extend_from_slice(&count.to_le_bytes());
+            font.extend_from_slice(&glyph.to_le_bytes());
+        }
+        // 3x2 pixel bitmaps, 2 bytes per row
+        for glyph in 0..5u16 {
+            font.extend_from_slice(&(glyph as u32 * 4).to_le_bytes());
+            font.extend_from_slice(&[3, 2]);
+            for field in [0, 2, (glyph + 5) * 16] {
+                font.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        for field in [0u16, 1, -16i16 as u16] {
+            font.extend_from_slice(&field.to_le_bytes());
+        }
+        for glyph in 0..5u8 {
+            font.extend_from_slice(&[glyph, glyph << 4, 0xFF, 0x0F]);
+        }
+        font
+    }
+
+    fn open(data: &[u8], cache_size: usize) -> FlashFont<RamStorage<'_>> {
+        FlashFont::new(RamStorage::new(data), 0, data.len() as u32, cache_size).unwrap()
+    }
+
+    /// Glyph indices and pen positions of a text.
+    fn layout<S: ReadStorage>(font: &FlashFont<S>, text: &str) -> Result<Vec<(u16, i32)>> {
+        let mut glyphs = Vec::new();
+        sealed::Layout::layout(font, text, &mut |glyph, pen| {
+            glyphs.push((glyph.index(), pen))
+        })?;
+        Ok(glyphs)
+    }
+
+    #[test]
+    fn ranges() {
+        let data = font_data(4);
+        let font = open(&data, 8);
+        assert_eq!(font.glyph_count(), 5);
+        assert!(font.contains('A').unwrap());
+        assert!(font.contains('C').unwrap());
+        assert!(font.contains('x').unwrap());
+        assert!(!font.contains('@').unwrap());
+        assert!(!font.contains('D').unwrap());
+        assert!(!font.contains('y').unwrap());
+        assert_eq!(
+            layout(&font, "ABCx").unwrap(),
+            [(0, 0), (1, 64), (2, 160), (3, 272)]
+        );
+        assert_eq!(font.text_width("ABCx"), 5 + 6 + 7 + 8 - 1);
+
+        // the same as the font in RAM
+        let ram = Font4bpp::from_bytes(&data).unwrap();
+        for text in ["ABCx", "BA", "x?A"] {
+            assert_eq!(font.text_width(text), ram.text_width(text), "{}", text);
+        }
+        let glyph = Glyph::from_record(1, &data[48..60], &data[106..110]);
+        assert_eq!(ram.glyph('B'), Some(glyph));
+    }
+
+    #[test]
+    fn default_glyph() {
+        let data = font_data(4);
+        let font = open(&data, 8);
+        assert_eq!(layout(&font, "A?").unwrap(), [(0, 0), (4, 80)]);
+        assert_eq!(font.text_width("?"), 9);
+
+        let data = font_data(u16::MAX);
+        let font = open(&data, 8);
+        assert_eq!(layout(&font, "A?B").unwrap(), [(0, 0), (1, 64)]);
+        assert_eq!(font.text_width("?"), 0);
+    }
+
+    #[test]
+    fn least_recently_used_glyph_is_replaced() {
+        let data = font_data(4);
+        let font = open(&data, 2);
+        let widths: Vec<u16> = ["A", "B", "A", "C", "A", "B"]
+            .iter()
+            .map(|text| font.text_width(text))
+            .collect();
+        assert_eq!(widths, [5, 6, 5, 7, 5, 6]);
+        // C replaced B, then B replaced C
+        assert_eq!((font.cache_hits(), font.cache_misses()), (2, 4));
+
+        font.clear_cache();
+        font.text_width("AA");
+        assert_eq!((font.cache_hits(), font.cache_misses()), (3, 5));
+    }
+
+    #[test]
+    fn font_must_fit() {
+        let data = font_data(4);
+        let mut storage = Vec::from([0xFF; 100]);
+        storage.extend_from_slice(&data);
+        let len = data.len() as u32;
+        let font = FlashFont::new(RamStorage::new(&storage), 100, len, 8).unwrap();
+        assert_eq!(font.text_width("AB"), 10);
+
+        let new = |offset, len| FlashFont::new(RamStorage::new(&storage), offset, len, 8).err();
+        assert_eq!(new(100, len + 1), Some(Error::InvalidFont));
+        assert_eq!(new(100, 19), Some(Error::InvalidFont));
+        assert_eq!(new(0, len), Some(Error::InvalidFont));
+        // the tables end after the partition
+        assert_eq!(new(100, 96), Some(Error::InvalidFont));
+
+        // a partition too small for the last bitmap
+        let font = FlashFont::new(RamStorage::new(&storage), 100, len - 1, 8).unwrap();
+        assert_eq!(layout(&font, "AB").unwrap().len(), 2);
+        assert_eq!(layout(&font, "?"), Err(Error::InvalidFont));
+    }
+
+    /// Storage as large as the address space without any data.
+    struct Unbounded;
+
+    impl ReadStorage for Unbounded {
+        type Error = Error;
+
+        fn read(&mut self, _offset: u32, _bytes: &mut [u8]) -> Result<()> {
+            Err(Error::Storage)
+        }
+
+        fn capacity(&self) -> usize {
+            usize::MAX
+        }
+    }
+
+    #[test]
+    fn font_end_overflow() {
+        let data = font_data(4);
+        let len = data.len() as u32;
+        let new = |offset, len| FlashFont::new(RamStorage::new(&data), offset, len, 8).err();
+        assert_eq!(new(u32::MAX, len), Some(Error::InvalidFont));
+        assert_eq!(new(u32::MAX - 19, 20), Some(Error::InvalidFont));
+        assert_eq!(new(u32::MAX - 19, u32::MAX), Some(Error::InvalidFont));
+
+        // rejected before reading, even if the storage could hold the font
+        let new = |offset, len| FlashFont::new(Unbounded, offset, len, 8).err();
+        assert_eq!(new(u32::MAX - 9, 20), Some(Error::InvalidFont));
+        assert_eq!(new(u32::MAX - 20, 20), Some(Error::Storage));
+    }
+
+    /// Fails reads from `fail_from` on.
+    struct Flaky<'a> {
+        data: &'a [u8],
+        fail_from: &'a Cell<u32>,
+    }
+
+    impl ReadStorage for Flaky<'_> {
+        type Error = Error;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<()> {
+            if offset + bytes.len() as u32 > self.fail_from.get() {
+                return Err(Error::Storage);
+            }
+            RamStorage::new(self.data).read(offset, bytes)
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    #[test]
+    fn failed_read_is_not_cached() {
+        let data = font_data(4);
+        let fail_from = Cell::new(u32::MAX);
+        let storage = Flaky {
+            data: &data,
+            fail_from: &fail_from,
+        };
+        let font = FlashFont::new(storage, 0, data.len() as u32, 1).unwrap();
+        assert_eq!(font.text_width("A"), 5);
+
+        // fail reading the bitmap of B, which replaces A
+        fail_from.set(106);
+        assert_eq!(layout(&font, "B"), Err(Error::Storage));
+        assert_eq!((font.cache_hits(), font.cache_misses()), (0, 2));
+
+        fail_from.set(u32::MAX);
+        assert_eq!(layout(&font, "B").unwrap(), [(1, 0)]);
+        assert_eq!((font.cache_hits(), font.cache_misses()), (0, 3));
+        let mut cache = font.cache.borrow_mut();
+        let slot = font.entry(&mut cache, 'B').unwrap();
+        assert_eq!(cache.entries[slot].data, [1, 0x10, 0xFF, 0x0F]);
+    }
+}
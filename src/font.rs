@@ -0,0 +1,663 @@
+use crate::{Display, Error, Result};
+
+/// A pre-rasterized, anti-aliased font with 4 bit coverage per pixel, e.g.
+/// converted from a TTF or OTF font with `tools/epd-font`.
+///
+/// The glyphs of one or more Unicode ranges are stored as nibble packed
+/// coverage bitmaps like [Image4bpp](crate::Image4bpp), with 0 for no ink and
+/// 15 for full ink. [Display::draw_text] blends them into the framebuffer, so
+/// text gets smooth edges on any background. Advances and kerning are stored
+/// in 1/16 pixels, so spacing doesn't accumulate rounding errors.
+///
+/// The serialized format is little endian:
+///
+/// | Offset | Size | Content                                             |
+/// |--------|------|-----------------------------------------------------|
+/// | 0      | 2    | magic `"F4"`                                        |
+/// | 2      | 1    | version, currently 1                                |
+/// | 3      | 1    | reserved                                            |
+/// | 4      | 2    | line height in pixels                               |
+/// | 6      | 2    | ascent in pixels                                    |
+/// | 8      | 2    | descent in pixels                                   |
+/// | 10     | 2    | number of ranges                                    |
+/// | 12     | 2    | number of glyphs                                    |
+/// | 14     | 2    | number of kerning pairs                             |
+/// | 16     | 2    | default glyph for missing characters, `0xFFFF` none |
+/// | 18     | 2    | reserved                                            |
+/// | 20     |      | ranges, glyphs, kerning pairs and bitmaps           |
+///
+/// Ranges are sorted by their first character and take 8 bytes each: the
+/// first character (4), the number of characters (2) and the index of the
+/// glyph of the first character (2).
+///
+/// Glyphs take 12 bytes each: the offset of the bitmap from the start of the
+/// bitmaps (4), the bitmap width (1) and height (1), the offset of the left
+/// edge from the pen position (2), the offset of the top edge above the
+/// baseline (2) and the advance in 1/16 pixels (2).
+///
+/// Kerning pairs are sorted by glyph indices and take 6 bytes each: the left
+/// glyph (2), the right glyph (2) and the adjustment of the advance in 1/16
+/// pixels (2).
+///
+/// ```rust ignore
+/// static SERIF: &[u8] = include_bytes!("serif-24.f4");
+///
+/// let font = Font4bpp::from_bytes(SERIF)?;
+/// display.draw_text(&font, "Hello, World!", 20, 20 + font.ascent(), 0)?;
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Font4bpp<'a> {
+    line_height: u16,
+    ascent: u16,
+    descent: u16,
+    default_glyph: Option<u16>,
+    ranges: &'a [u8],
+    glyphs: &'a [u8],
+    kerning: &'a [u8],
+    bitmaps: &'a [u8],
+}
+
+impl<'a> Font4bpp<'a> {
+    /// Size of the header in bytes.
+    pub const HEADER_SIZE: usize = 20;
+    /// Version of the serialized format.
+    pub const VERSION: u8 = 1;
+
+    pub(crate) const MAGIC: [u8; 2] = *b"F4";
+    pub(crate) const RANGE_SIZE: usize = 8;
+    pub(crate) const GLYPH_SIZE: usize = 12;
+    pub(crate) const PAIR_SIZE: usize = 6;
+
+    /// Parse a serialized font, see [Font4bpp] for the format.
+    ///
+    /// Returns [Error::InvalidFont] if the header is unknown, the data is
+    /// truncated or a table refers to data that doesn't exist.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+        if bytes.len() < Self::HEADER_SIZE
+            || bytes[0..2] != Self::MAGIC
+            || bytes[2] != Self::VERSION
+        {
+            return Err(Error::InvalidFont);
+        }
+        let field = |offset: usize| u16_at(bytes, offset);
+        let (ranges, glyphs, pairs) = (field(10) as usize, field(12) as usize, field(14) as usize);
+        let mut offset = Self::HEADER_SIZE;
+        let mut table = |len: usize| {
+            let table = bytes.get(offset..offset + len).ok_or(Error::InvalidFont);
+            offset += len;
+            table
+        };
+        let ranges = table(ranges * Self::RANGE_SIZE)?;
+        let glyphs = table(glyphs * Self::GLYPH_SIZE)?;
+        let kerning = table(pairs * Self::PAIR_SIZE)?;
+        let bitmaps = &bytes[offset..];
+        let font = Font4bpp {
+            line_height: field(4),
+            ascent: field(6),
+            descent: field(8),
+            default_glyph: Some(field(16)).filter(|&glyph| glyph != u16::MAX),
+            ranges,
+            glyphs,
+            kerning,
+            bitmaps,
+        };
+
+        // check all references once, so lookups can't run out of data
+        let glyph_count = font.glyph_count();
+        let ranges_valid = ranges.chunks_exact(Self::RANGE_SIZE).all(|range| {
+            u16_at(range, 6) as usize + u16_at(range, 4) as usize <= glyph_count as usize
+        });
+        let glyphs_valid = glyphs
+            .chunks_exact(Self::GLYPH_SIZE)
+            .all(|glyph| bitmap_end(glyph) <= bitmaps.len());
+        let pairs_valid = kerning
+            .chunks_exact(Self::PAIR_SIZE)
+            .all(|pair| u16_at(pair, 0) < glyph_count && u16_at(pair, 2) < glyph_count);
+        if !ranges_valid
+            || !glyphs_valid
+            || !pairs_valid
+            || font.default_glyph.is_some_and(|glyph| glyph >= glyph_count)
+        {
+            return Err(Error::InvalidFont);
+        }
+        Ok(font)
+    }
+
+    /// Distance between the baselines of two lines in pixels.
+    pub fn line_height(&self) -> u16 {
+        self.line_height
+    }
+
+    /// Height of the font above the baseline in pixels.
+    pub fn ascent(&self) -> u16 {
+        self.ascent
+    }
+
+    /// Depth of the font below the baseline in pixels.
+    pub fn descent(&self) -> u16 {
+        self.descent
+    }
+
+    /// Number of glyphs in the font.
+    pub fn glyph_count(&self) -> u16 {
+        (self.glyphs.len() / Self::GLYPH_SIZE) as u16
+    }
+
+    /// The glyph of a character, if the font contains it.
+    pub fn glyph(&self, c: char) -> Option<Glyph<'a>> {
+        let c = c as u32;
+        let (mut low, mut high) = (0, self.ranges.len() / Self::RANGE_SIZE);
+        while low < high {
+            let mid = (low + high) / 2;
+            let range = &self.ranges[mid * Self::RANGE_SIZE..];
+            let first = u32_at(range, 0);
+            if c < first {
+                high = mid;
+            } else if c - first >= u16_at(range, 4) as u32 {
+                low = mid + 1;
+            } else {
+                return self.glyph_at(u16_at(range, 6) + (c - first) as u16);
+            }
+        }
+        None
+    }
+
+    /// The glyph of a character, or the default glyph of the font if it
+    /// doesn't contain the character.
+    pub fn glyph_or_default(&self, c: char) -> Option<Glyph<'a>> {
+        self.glyph(c)
+            .or_else(|| self.default_glyph.and_then(|index| self.glyph_at(index)))
+    }
+
+    fn glyph_at(&self, index: u16) -> Option<Glyph<'a>> {
+        let start = index as usize * Self::GLYPH_SIZE;
+        let record = self.glyphs.get(start..start + Self::GLYPH_SIZE)?;
+        let data = &self.bitmaps[u32_at(record, 0) as usize..bitmap_end(record)];
+        Some(Glyph::from_record(index, record, data))
+    }
+
+    /// Adjustment of the advance between two glyphs in 1/16 pixels, usually
+    /// negative.
+    pub fn kerning(&self, left: &Glyph<'_>, right: &Glyph<'_>) -> i16 {
+        let key = (left.index, right.index);
+        let (mut low, mut high) = (0, self.kerning.len() / Self::PAIR_SIZE);
+        while low < high {
+            let mid = (low + high) / 2;
+            let pair = &self.kerning[mid * Self::PAIR_SIZE..];
+            match (u16_at(pair, 0), u16_at(pair, 2)).cmp(&key) {
+                core::cmp::Ordering::Less => low = mid + 1,
+                core::cmp::Ordering::Greater => high = mid,
+                core::cmp::Ordering::Equal => return u16_at(pair, 4) as i16,
+            }
+        }
+        0
+    }
+
+    /// Width of a single line of text in pixels, i.e. the distance the pen
+    /// moves when drawing it with [Display::draw_text].
+    pub fn text_width(&self, text: &str) -> u16 {
+        TextFont::text_width(self, text)
+    }
+}
+
+/// A font [Display::draw_text] can draw: [Font4bpp] or, with the `flash-font`
+/// feature, [FlashFont](crate::FlashFont).
+pub trait TextFont: sealed::Layout {
+    /// Distance between the baselines of two lines in pixels.
+    fn line_height(&self) -> u16;
+
+    /// Height of the font above the baseline in pixels.
+    fn ascent(&self) -> u16;
+
+    /// Depth of the font below the baseline in pixels.
+    fn descent(&self) -> u16;
+
+    /// Width of a single line of text in pixels, i.e. the distance the pen
+    /// moves when drawing it with [Display::draw_text]. Glyphs that can't be
+    /// read are left out.
+    fn text_width(&self, text: &str) -> u16 {
+        let mut pen = 0i32;
+        let _ = self.layout(text, &mut |glyph, x| pen = x + glyph.advance as i32);
+        ((pen.max(0) + 8) >> 4).min(u16::MAX as i32) as u16
+    }
+}
+
+pub(crate) mod sealed {
+    use super::Glyph;
+    use crate::Result;
+
+    pub trait Layout {
+        /// Call `f` with each glyph of `text` and its pen position in 1/16
+        /// pixels, relative to the start of the text. Control characters are
+        /// skipped and break the kerning.
+        fn layout(&self, text: &str, f: &mut dyn FnMut(&Glyph<'_>, i32)) -> Result<()>;
+    }
+}
+
+impl TextFont for Font4bpp<'_> {
+    fn line_height(&self) -> u16 {
+        self.line_height
+    }
+
+    fn ascent(&self) -> u16 {
+        self.ascent
+    }
+
+    fn descent(&self) -> u16 {
+        self.descent
+    }
+}
+
+impl sealed::Layout for Font4bpp<'_> {
+    fn layout(&self, text: &str, f: &mut dyn FnMut(&Glyph<'_>, i32)) -> Result<()> {
+        let mut pen = 0i32;
+        let mut previous: Option<Glyph<'_>> = None;
+        for c in text.chars() {
+            if c.is_control() {
+                previous = None;
+                continue;
+            }
+            let Some(glyph) = self.glyph_or_default(c) else {
+                continue;
+            };
+            if let Some(previous) = &previous {
+                pen += self.kerning(previous, &glyph) as i32;
+            }
+            f(&glyph, pen);
+            pen += glyph.advance as i32;
+            previous = Some(glyph);
+        }
+        Ok(())
+    }
+}
+
+/// A glyph of a [TextFont].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Glyph<'a> {
+    index: u16,
+    width: u8,
+    height: u8,
+    left: i16,
+    top: i16,
+    advance: u16,
+    data: &'a [u8],
+}
+
+impl<'a> Glyph<'a> {
+    /// Parse a glyph table entry of the serialized font, see [Font4bpp].
+    pub(crate) fn from_record(index: u16, record: &[u8], data: &'a [u8]) -> Self {
+        Glyph {
+            index,
+            width: record[4],
+            height: record[5],
+            left: u16_at(record, 6) as i16,
+            top: u16_at(record, 8) as i16,
+            advance: u16_at(record, 10),
+            data,
+        }
+    }
+
+    /// Index of the glyph in its font.
+    #[cfg(all(test, feature = "flash-font"))]
+    pub(crate) fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Width of the bitmap in pixels.
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    /// Height of the bitmap in pixels.
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    /// Offset of the left edge of the bitmap from the pen position.
+    pub fn left(&self) -> i16 {
+        self.left
+    }
+
+    /// Offset of the top edge of the bitmap above the baseline.
+    pub fn top(&self) -> i16 {
+        self.top
+    }
+
+    /// Distance to the pen position of the next glyph in 1/16 pixels.
+    pub fn advance(&self) -> u16 {
+        self.advance
+    }
+
+    /// Ink coverage of a pixel of the bitmap, from 0 (none) to 15 (full).
+    pub fn coverage(&self, x: u8, y: u8) -> u8 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        let byte = self.data[y as usize * stride(self.width) + x as usize / 2];
+        (byte >> (4 * (x % 2))) & 0x0F
+    }
+
+    /// Nibble packed coverage of a row of the bitmap.
+    fn row(&self, y: u8) -> &[u8] {
+        let stride = stride(self.width);
+        &self.data[y as usize * stride..(y as usize + 1) * stride]
+    }
+}
+
+/// End of the bitmap of a glyph table entry from the start of the bitmaps.
+pub(crate) fn bitmap_end(record: &[u8]) -> usize {
+    u32_at(record, 0) as usize + stride(record[4]) * record[5] as usize
+}
+
+pub(crate) fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+pub(crate) fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+const fn stride(width: u8) -> usize {
+    (width as usize).div_ceil(2)
+}
+
+impl<'a> Display<'a> {
+    /// Draw a single line of text in the given color with the pen starting
+    /// at `x` and the baseline at `y`, see [TextFont]. The glyph coverage is
+    /// blended with the framebuffer and clipped to the screen.
+    ///
+    /// Characters missing from the font are drawn with its default glyph or
+    /// skipped. Control characters, including line breaks, are skipped.
+    ///
+    /// Returns the pen position after the text, e.g. to continue it in a
+    /// different font. Returns [Error::InvalidColor] if the color is greater
+    /// than 0x0F, or the error of a [FlashFont](crate::FlashFont) that fails
+    /// to read a glyph.
+    pub fn draw_text(
+        &mut self,
+        font: &impl TextFont,
+        text: &str,
+        x: u16,
+        y: u16,
+        color: u8,
+    ) -> Result<u16> {
+        if color > 0x0F {
+            return Err(Error::InvalidColor);
+        }
+        let start = (x as i32) << 4;
+        let mut end = start;
+        font.layout(text, &mut |glyph, pen| {
+            let left = ((start + pen + 8) >> 4) + glyph.left as i32;
+            let top = y as i32 - glyph.top as i32;
+            for row in 0..glyph.height {
+                self.blend_packed_row(
+                    left,
+                    top + row as i32,
+                    glyph.width as u16,
+                    glyph.row(row),
+                    color,
+                );
+            }
+            end = start + pen + glyph.advance as i32;
+        })?;
+        Ok(((end + 8) >> 4).clamp(0, u16::MAX as i32) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testdata::{read, read_pgm};
+
+    /// The font of `tools/epd-font`'s tests: ranges for ` `, `?`, `A` to `C`,
+    /// `x` and `€`, the kerning pairs `AB` and `xx` and `?` for missing
+    /// characters. The tables end at 156, the bitmaps at 328.
+    fn sample() -> Vec<u8> {
+        read("font/sample.f4")
+    }
+
+    fn with(data: &[u8], offset: usize, value: u16) -> Vec<u8> {
+        let mut data = data.to_vec();
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn header() {
+        let data = sample();
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        assert_eq!(
+            (font.line_height(), font.ascent(), font.descent()),
+            (14, 10, 4)
+        );
+        assert_eq!(font.glyph_count(), 7);
+
+        let mut magic = data.clone();
+        magic[1] = b'5';
+        assert_eq!(Font4bpp::from_bytes(&magic), Err(Error::InvalidFont));
+        let mut version = data.clone();
+        version[2] = 2;
+        assert_eq!(Font4bpp::from_bytes(&version), Err(Error::InvalidFont));
+    }
+
+    #[test]
+    fn truncated_data() {
+        let data = sample();
+        for len in 0..data.len() {
+            assert_eq!(
+                Font4bpp::from_bytes(&data[..len]),
+                Err(Error::InvalidFont),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_references() {
+        let data = sample();
+        // range `A` to `C` extending beyond the last glyph
+        assert!(Font4bpp::from_bytes(&with(&data, 40, 5)).is_ok());
+        assert_eq!(
+            Font4bpp::from_bytes(&with(&data, 40, 6)),
+            Err(Error::InvalidFont)
+        );
+        // bitmap of `€` ending beyond the data
+        assert_eq!(
+            Font4bpp::from_bytes(&with(&data, 60 + 6 * 12, 133)),
+            Err(Error::InvalidFont)
+        );
+        // kerning pair of a glyph that doesn't exist
+        assert_eq!(
+            Font4bpp::from_bytes(&with(&data, 144, 7)),
+            Err(Error::InvalidFont)
+        );
+        // default glyph that doesn't exist
+        assert_eq!(
+            Font4bpp::from_bytes(&with(&data, 16, 7)),
+            Err(Error::InvalidFont)
+        );
+    }
+
+    #[test]
+    fn glyphs_of_all_ranges() {
+        let data = sample();
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        let metrics = |c| {
+            let glyph = font.glyph(c).unwrap();
+            (
+                glyph.width(),
+                glyph.height(),
+                glyph.left(),
+                glyph.top(),
+                glyph.advance(),
+            )
+        };
+        assert_eq!(metrics(' '), (0, 0, 0, 0, 64));
+        assert_eq!(metrics('?'), (5, 8, 1, 8, 112));
+        assert_eq!(metrics('A'), (7, 9, 0, 9, 120));
+        assert_eq!(metrics('B'), (6, 9, 1, 9, 128));
+        assert_eq!(metrics('C'), (6, 9, -1, 9, 104));
+        assert_eq!(metrics('x'), (5, 6, 0, 6, 88));
+        assert_eq!(metrics('€'), (8, 10, 0, 9, 152));
+        for c in [
+            '\0',
+            '\x1F',
+            '!',
+            '@',
+            'D',
+            'w',
+            'y',
+            '\u{20AB}',
+            '\u{10FFFF}',
+        ] {
+            assert_eq!(font.glyph(c), None, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn default_glyph() {
+        let data = sample();
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        assert_eq!(font.glyph_or_default('Z'), font.glyph('?'));
+        assert_eq!(font.glyph_or_default('A'), font.glyph('A'));
+
+        let data = with(&data, 16, u16::MAX);
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        assert_eq!(font.glyph_or_default('Z'), None);
+        assert_eq!(font.text_width("AZ"), 8);
+    }
+
+    #[test]
+    fn kerning() {
+        let data = sample();
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        let pair =
+            |left, right| font.kerning(&font.glyph(left).unwrap(), &font.glyph(right).unwrap());
+        assert_eq!(pair('A', 'B'), -24);
+        assert_eq!(pair('x', 'x'), -8);
+        assert_eq!(pair('B', 'A'), 0);
+        assert_eq!(pair('A', 'A'), 0);
+        assert_eq!(pair('€', '€'), 0);
+    }
+
+    #[test]
+    fn text_width() {
+        let data = sample();
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        // (120 + 128 - 24) / 16 = 14
+        assert_eq!(font.text_width("AB"), 14);
+        // (120 + 64 + 128) / 16 = 19.5
+        assert_eq!(font.text_width("A B"), 20);
+        // (88 + 88 - 8) / 16 = 10.5
+        assert_eq!(font.text_width("xx"), 11);
+        // missing characters take the advance of the default glyph
+        assert_eq!(font.text_width("Z"), 7);
+        // control characters break the kerning
+        assert_eq!(font.text_width("A\nB"), 16);
+        assert_eq!(font.text_width(""), 0);
+        assert_eq!(font.text_width("\n"), 0);
+    }
+
+    #[test]
+    fn coverage() {
+        let data = sample();
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        // coverage of pixel i of a glyph is (i * 7 + 3) % 16 for `?`
+        let glyph = font.glyph('?').unwrap();
+        for y in 0..8 {
+            for x in 0..5 {
+                let i = (y * 5 + x) as usize;
+                assert_eq!(glyph.coverage(x, y), ((i * 7 + 3) % 16) as u8);
+            }
+        }
+        assert_eq!(glyph.coverage(5, 0), 0);
+        assert_eq!(glyph.coverage(0, 8), 0);
+    }
+
+    /// The sample text drawn like the preview of `tools/epd-font`, which
+    /// encoded the font.
+    #[test]
+    fn draw_text() {
+        let data = sample();
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        let (width, height, preview) = read_pgm("font/sample.pgm");
+        let mut display = Display::mock();
+        // margin of half a line
+        for (i, line) in ["AB C?x€xZ", "CAxx"].into_iter().enumerate() {
+            let end = display
+                .draw_text(&font, line, 7, 7 + i as u16 * 14 + 10, 0)
+                .unwrap();
+            assert_eq!(end, 7 + font.text_width(line));
+        }
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(
+                    display.pixel(x as u16, y as u16) * 17,
+                    preview[y * width + x],
+                    "pixel {}x{}",
+                    x,
+                    y
+                );
+            }
+        }
+        assert_eq!(
+            display.draw_text(&font, "A", 0, 20, 16),
+            Err(Error::InvalidColor)
+        );
+    }
+
+    #[test]
+    fn blending() {
+        let data = sample();
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        let glyph = font.glyph('?').unwrap();
+        let mut display = Display::mock();
+        display.fill(6).unwrap();
+        display.draw_text(&font, "?", 100, 50, 15).unwrap();
+        // left edge 1, top edge 8 pixels above the baseline
+        for y in 0..8 {
+            for x in 0..5 {
+                let coverage = glyph.coverage(x, y) as u16;
+                let expected = (15 * coverage + 6 * (15 - coverage) + 7) / 15;
+                assert_eq!(
+                    display.pixel(101 + x as u16, 42 + y as u16) as u16,
+                    expected
+                );
+            }
+        }
+        assert_eq!(display.pixel(100, 42), 6);
+        assert_eq!(display.pixel(106, 42), 6);
+        assert_eq!(display.pixel(101, 41), 6);
+        assert_eq!(display.pixel(101, 50), 6);
+    }
+
+    #[test]
+    fn clipping() {
+        let data = sample();
+        let font = Font4bpp::from_bytes(&data).unwrap();
+        let glyph = font.glyph('C').unwrap();
+        let mut display = Display::mock();
+        // `C` starts 1 pixel left of the pen and 9 above the baseline
+        display.draw_text(&font, "C", 0, 3, 0).unwrap();
+        for y in 0..3 {
+            for x in 0..5 {
+                let coverage = glyph.coverage(x + 1, y + 6) as u16;
+                let expected = (15 * (15 - coverage) + 7) / 15;
+                assert_eq!(display.pixel(x as u16, y as u16) as u16, expected);
+            }
+        }
+        // beyond the right and bottom edge
+        display.draw_text(&font, "€€", 955, 545, 0).unwrap();
+        assert_eq!(display.draw_text(&font, "A", u16::MAX, 20, 0), Ok(u16::MAX));
+    }
+}
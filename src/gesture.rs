@@ -0,0 +1,472 @@
+use crate::{TouchPoint, Touches};
+
+/// Thresholds of the [GestureRecognizer].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GestureConfig {
+    /// Maximum duration of a tap in milliseconds.
+    pub tap_max_ms: u32,
+    /// Maximum time between the release of a tap and the touch of the next
+    /// one to form a double tap, in milliseconds.
+    pub double_tap_ms: u32,
+    /// Minimum duration of a long press in milliseconds.
+    pub long_press_ms: u32,
+    /// Distance a finger may move before a tap or long press becomes a
+    /// swipe, in pixels.
+    pub move_tolerance: u16,
+    /// Minimum distance of a swipe along its direction, in pixels.
+    pub swipe_min_distance: u16,
+    /// Maximum duration of a swipe in milliseconds.
+    pub swipe_max_ms: u32,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            tap_max_ms: 300,
+            double_tap_ms: 300,
+            long_press_ms: 600,
+            move_tolerance: 20,
+            swipe_min_distance: 80,
+            swipe_max_ms: 800,
+        }
+    }
+}
+
+/// Direction of a [Gesture::Swipe] in display coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SwipeDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// A recognized gesture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gesture {
+    /// Short touch without movement, reported when the finger is lifted.
+    Tap { x: u16, y: u16 },
+    /// Second tap shortly after a [Gesture::Tap] at the same position,
+    /// reported instead of a second tap.
+    DoubleTap { x: u16, y: u16 },
+    /// Touch held without movement, reported as soon as
+    /// [`GestureConfig::long_press_ms`] have passed.
+    LongPress { x: u16, y: u16 },
+    /// Quick movement starting at `x`/`y`, reported when the finger is
+    /// lifted.
+    Swipe {
+        direction: SwipeDirection,
+        x: u16,
+        y: u16,
+        distance: u16,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    Idle,
+    Pressed {
+        id: u8,
+        start: TouchPoint,
+        last: TouchPoint,
+        since: u32,
+        moved: bool,
+        long_press: bool,
+    },
+    /// More than one finger, ignored until all of them have been lifted.
+    Cancelled,
+}
+
+/// Recognizes taps, double taps, long presses and swipes from timestamped
+/// touch reports.
+///
+/// The recognizer follows the first finger on the screen. Touching with a
+/// second finger cancels the gesture.
+///
+/// ```rust ignore
+/// let mut gestures = GestureRecognizer::new(GestureConfig::default());
+/// loop {
+///     let now = (esp_hal::time::now().ticks() / 1000) as u32;
+///     let gesture = match touch.read_touches(&display)? {
+///         Some(touches) => gestures.update(now, &touches),
+///         None => gestures.poll(now),
+///     };
+///     if let Some(Gesture::Swipe { direction: SwipeDirection::Left, .. }) = gesture {
+///         next_page();
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    state: State,
+    /// Position and release time of the last tap, while it can still become
+    /// a double tap.
+    last_tap: Option<(TouchPoint, u32)>,
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        Self::new(GestureConfig::default())
+    }
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> Self {
+        GestureRecognizer {
+            config,
+            state: State::Idle,
+            last_tap: None,
+        }
+    }
+
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    /// Returns whether a finger is being tracked.
+    pub fn is_pressed(&self) -> bool {
+        matches!(self.state, State::Pressed { .. })
+    }
+
+    /// Forget the current touch, e.g. after switching screens.
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.last_tap = None;
+    }
+
+    /// Feed a touch report taken at `timestamp` milliseconds.
+    pub fn update(&mut self, timestamp: u32, touches: &Touches) -> Option<Gesture> {
+        match self.state {
+            State::Idle => {
+                let first = touches.iter().next()?;
+                self.state = if touches.len() > 1 {
+                    State::Cancelled
+                } else {
+                    State::Pressed {
+                        id: first.id,
+                        start: *first,
+                        last: *first,
+                        since: timestamp,
+                        moved: false,
+                        long_press: false,
+                    }
+                };
+                None
+            }
+            State::Cancelled => {
+                if touches.is_empty() {
+                    self.state = State::Idle;
+                }
+                None
+            }
+            State::Pressed {
+                id,
+                start,
+                ref mut last,
+                ref mut moved,
+                ..
+            } => {
+                if touches.len() > 1 {
+                    self.state = State::Cancelled;
+                    return None;
+                }
+                match touches.get(id) {
+                    Some(point) => {
+                        *last = *point;
+                        *moved |= distance(&start, point) > self.config.move_tolerance;
+                        self.poll(timestamp)
+                    }
+                    None => {
+                        let gesture = self.release(timestamp);
+                        self.state = State::Idle;
+                        // a different finger may have touched in the same report
+                        self.update(timestamp, touches);
+                        gesture
+                    }
+                }
+            }
+        }
+    }
+
+    /// Check for a long press without a new touch report, e.g. when the
+    /// controller only reports changes.
+    pub fn poll(&mut self, timestamp: u32) -> Option<Gesture> {
+        match self.state {
+            State::Pressed {
+                start,
+                since,
+                moved: false,
+                ref mut long_press,
+                ..
+            } if !*long_press && timestamp.wrapping_sub(since) >= self.config.long_press_ms => {
+                *long_press = true;
+                Some(Gesture::LongPress {
+                    x: start.x,
+                    y: start.y,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn release(&mut self, timestamp: u32) -> Option<Gesture> {
+        let State::Pressed {
+            start,
+            last,
+            since,
+            moved,
+            long_press,
+            ..
+        } = self.state
+        else {
+            return None;
+        };
+        let duration = timestamp.wrapping_sub(since);
+        // any other gesture in between breaks a double tap
+        let last_tap = self.last_tap.take();
+        if long_press {
+            None
+        } else if !moved {
+            if duration > self.config.tap_max_ms {
+                return None;
+            }
+            let double = last_tap.is_some_and(|(tap, released)| {
+                since.wrapping_sub(released) <= self.config.double_tap_ms
+                    && distance(&tap, &start) <= self.config.move_tolerance
+            });
+            if double {
+                Some(Gesture::DoubleTap {
+                    x: start.x,
+                    y: start.y,
+                })
+            } else {
+                self.last_tap = Some((start, timestamp));
+                Some(Gesture::Tap {
+                    x: start.x,
+                    y: start.y,
+                })
+            }
+        } else {
+            let dx = last.x as i32 - start.x as i32;
+            let dy = last.y as i32 - start.y as i32;
+            let (direction, distance) = if dx.abs() >= dy.abs() {
+                let direction = if dx < 0 {
+                    SwipeDirection::Left
+                } else {
+                    SwipeDirection::Right
+                };
+                (direction, dx.unsigned_abs() as u16)
+            } else {
+                let direction = if dy < 0 {
+                    SwipeDirection::Up
+                } else {
+                    SwipeDirection::Down
+                };
+                (direction, dy.unsigned_abs() as u16)
+            };
+            (distance >= self.config.swipe_min_distance && duration <= self.config.swipe_max_ms)
+                .then_some(Gesture::Swipe {
+                    direction,
+                    x: start.x,
+                    y: start.y,
+                    distance,
+                })
+        }
+    }
+}
+
+/// Largest distance along either axis.
+fn distance(a: &TouchPoint, b: &TouchPoint) -> u16 {
+    a.x.abs_diff(b.x).max(a.y.abs_diff(b.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Report of a single finger at `x`/`y`, or of a lifted finger.
+    fn report(point: Option<(u16, u16)>) -> Touches {
+        point
+            .map(|(x, y)| TouchPoint {
+                id: 0,
+                x,
+                y,
+                size: 20,
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// Feed a recorded trace of timestamped reports.
+    fn replay(trace: &[(u32, Option<(u16, u16)>)]) -> Vec<Gesture> {
+        let mut gestures = GestureRecognizer::default();
+        trace
+            .iter()
+            .filter_map(|&(timestamp, point)| gestures.update(timestamp, &report(point)))
+            .collect()
+    }
+
+    fn swipe(direction: SwipeDirection, dx: i32, dy: i32) {
+        let (x, y) = (480, 270);
+        let trace: Vec<_> = (0..=6)
+            .map(|step| {
+                let x = (x + dx * step / 6) as u16;
+                let y = (y + dy * step / 6) as u16;
+                (step as u32 * 50, Some((x, y)))
+            })
+            .chain([(320, None)])
+            .collect();
+        assert_eq!(
+            replay(&trace),
+            [Gesture::Swipe {
+                direction,
+                x: 480,
+                y: 270,
+                distance: 120
+            }]
+        );
+    }
+
+    #[test]
+    fn tap() {
+        let trace = [(0, Some((100, 200))), (40, Some((101, 200))), (120, None)];
+        assert_eq!(replay(&trace), [Gesture::Tap { x: 100, y: 200 }]);
+        // held too long for a tap, too short for a long press
+        let trace = [(0, Some((100, 200))), (400, Some((100, 200))), (450, None)];
+        assert_eq!(replay(&trace), []);
+    }
+
+    #[test]
+    fn double_tap() {
+        let trace = [
+            (0, Some((100, 200))),
+            (80, None),
+            (250, Some((108, 195))),
+            (320, None),
+            // a third tap starts over
+            (500, Some((100, 200))),
+            (560, None),
+        ];
+        assert_eq!(
+            replay(&trace),
+            [
+                Gesture::Tap { x: 100, y: 200 },
+                Gesture::DoubleTap { x: 108, y: 195 },
+                Gesture::Tap { x: 100, y: 200 },
+            ]
+        );
+
+        // too late, too far apart
+        let trace = [
+            (0, Some((100, 200))),
+            (80, None),
+            (500, Some((100, 200))),
+            (560, None),
+            (600, Some((300, 200))),
+            (660, None),
+        ];
+        assert_eq!(
+            replay(&trace),
+            [
+                Gesture::Tap { x: 100, y: 200 },
+                Gesture::Tap { x: 100, y: 200 },
+                Gesture::Tap { x: 300, y: 200 },
+            ]
+        );
+    }
+
+    #[test]
+    fn long_press() {
+        let trace = [
+            (0, Some((300, 300))),
+            (300, Some((305, 298))),
+            (650, Some((302, 301))),
+            (900, Some((300, 300))),
+            (1000, None),
+        ];
+        assert_eq!(replay(&trace), [Gesture::LongPress { x: 300, y: 300 }]);
+
+        // without further reports from the controller
+        let mut gestures = GestureRecognizer::default();
+        assert_eq!(gestures.update(0, &report(Some((300, 300)))), None);
+        assert_eq!(gestures.poll(500), None);
+        assert_eq!(
+            gestures.poll(600),
+            Some(Gesture::LongPress { x: 300, y: 300 })
+        );
+        assert_eq!(gestures.poll(700), None);
+        assert!(gestures.is_pressed());
+        assert_eq!(gestures.update(800, &report(None)), None);
+        assert!(!gestures.is_pressed());
+    }
+
+    #[test]
+    fn swipes() {
+        swipe(SwipeDirection::Left, -120, 10);
+        swipe(SwipeDirection::Right, 120, -30);
+        swipe(SwipeDirection::Up, 20, -120);
+        swipe(SwipeDirection::Down, 0, 120);
+    }
+
+    #[test]
+    fn jitter_below_tolerance() {
+        // the finger wobbles by up to 20 pixels, which is still a tap
+        let trace = [
+            (0, Some((500, 300))),
+            (30, Some((512, 292))),
+            (60, Some((488, 310))),
+            (90, Some((520, 280))),
+            (150, None),
+        ];
+        assert_eq!(replay(&trace), [Gesture::Tap { x: 500, y: 300 }]);
+
+        // and still a long press
+        let trace = [
+            (0, Some((500, 300))),
+            (300, Some((515, 300))),
+            (620, Some((500, 285))),
+            (700, None),
+        ];
+        assert_eq!(replay(&trace), [Gesture::LongPress { x: 500, y: 300 }]);
+
+        // moved too far for a tap, too short for a swipe
+        let trace = [(0, Some((500, 300))), (100, Some((550, 300))), (150, None)];
+        assert_eq!(replay(&trace), []);
+    }
+
+    #[test]
+    fn second_finger_cancels() {
+        let mut gestures = GestureRecognizer::default();
+        let two: Touches = [
+            TouchPoint {
+                id: 0,
+                x: 100,
+                y: 100,
+                size: 20,
+            },
+            TouchPoint {
+                id: 1,
+                x: 400,
+                y: 100,
+                size: 20,
+            },
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(gestures.update(0, &report(Some((100, 100)))), None);
+        assert_eq!(gestures.update(50, &two), None);
+        assert_eq!(gestures.update(100, &report(Some((100, 100)))), None);
+        assert_eq!(gestures.update(150, &report(None)), None);
+        assert_eq!(gestures.update(200, &report(Some((100, 100)))), None);
+        assert_eq!(
+            gestures.update(250, &report(None)),
+            Some(Gesture::Tap { x: 100, y: 100 })
+        );
+    }
+}
@@ -1,6 +1,11 @@
-use embedded_graphics_core::{pixelcolor::Gray4, prelude::*};
+use core::marker::PhantomData;
 
-use crate::{display::Display, Error};
+use embedded_graphics_core::{
+    pixelcolor::{Gray4, Gray8},
+    prelude::*,
+};
+
+use crate::{display::Display, Error, PanelLevels};
 
 impl<'a> DrawTarget for Display<'a> {
     type Color = Gray4;
@@ -12,11 +17,10 @@ impl<'a> DrawTarget for Display<'a> {
         I: IntoIterator<Item = Pixel<Self::Color>>,
     {
         for Pixel(coord, color) in pixels.into_iter() {
-            let result = self.set_pixel(coord.x as u16, coord.y as u16, color.luma());
-            if matches!(result, Err(Error::OutOfBounds)) {
+            let Some((x, y)) = self.to_panel(coord) else {
                 continue;
-            }
-            result?;
+            };
+            self.set_pixel(x, y, color.luma())?;
         }
         Ok(())
     }
@@ -28,7 +32,19 @@ impl<'a> DrawTarget for Display<'a> {
 
 impl<'a> OriginDimensions for Display<'a> {
     fn size(&self) -> Size {
-        Size::new(Self::WIDTH as u32, Self::HEIGHT as u32)
+        let (width, height) = self.rotation().size();
+        Size::new(width as u32, height as u32)
+    }
+}
+
+impl<'a> Display<'a> {
+    /// Map a point of the rotated screen to panel coordinates, `None` if it is
+    /// outside of the screen.
+    fn to_panel(&self, point: Point) -> Option<(u16, u16)> {
+        let (width, height) = self.rotation().size();
+        let x = u16::try_from(point.x).ok().filter(|&x| x < width)?;
+        let y = u16::try_from(point.y).ok().filter(|&y| y < height)?;
+        Some(self.rotation().to_panel(x, y))
     }
 }
 
@@ -42,3 +58,247 @@ impl From<embedded_graphics_core::primitives::Rectangle> for crate::display::Rec
         }
     }
 }
+
+impl From<crate::display::Rectangle> for embedded_graphics_core::primitives::Rectangle {
+    fn from(val: crate::display::Rectangle) -> Self {
+        embedded_graphics_core::primitives::Rectangle::new(
+            Point::new(val.x as i32, val.y as i32),
+            Size::new(val.width as u32, val.height as u32),
+        )
+    }
+}
+
+/// Color converting view of a [Display], see [`Display::color_view`].
+///
+/// Draws any color that converts into [Gray8], e.g. [Gray8], `Rgb565`,
+/// `Rgb888` or `BinaryColor`. Colors are mapped to the closest panel level,
+/// or with ordered dithering if enabled.
+///
+/// ```rust ignore
+/// let bmp = Bmp::<Rgb565>::from_slice(include_bytes!("photo.bmp")).unwrap();
+/// Image::new(&bmp, Point::zero()).draw(&mut display.color_view().with_dithering(true))?;
+///
+/// // BinaryColor::On is white in embedded-graphics, draw it as ink instead
+/// Text::new("Hello", Point::new(10, 20), MonoTextStyle::new(&FONT_10X20, BinaryColor::On))
+///     .draw(&mut display.color_view().with_inverted(true))?;
+/// ```
+pub struct ColorView<'d, 'a, C> {
+    display: &'d mut Display<'a>,
+    levels: PanelLevels,
+    dithering: bool,
+    inverted: bool,
+    color: PhantomData<C>,
+}
+
+impl<'a> Display<'a> {
+    /// A view of the display drawing colors other than [Gray4].
+    pub fn color_view<C>(&mut self) -> ColorView<'_, 'a, C> {
+        ColorView {
+            display: self,
+            levels: PanelLevels::default(),
+            dithering: false,
+            inverted: false,
+            color: PhantomData,
+        }
+    }
+}
+
+impl<C> ColorView<'_, '_, C>
+where
+    C: PixelColor + Into<Gray8>,
+{
+    /// Enable ordered dithering, see
+    /// [`DitherMethod::Bayer`](crate::DitherMethod::Bayer).
+    pub fn with_dithering(mut self, dithering: bool) -> Self {
+        self.dithering = dithering;
+        self
+    }
+
+    /// Swap black and white.
+    pub fn with_inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    /// Use other panel levels than [`PanelLevels::LINEAR`].
+    pub fn with_levels(mut self, levels: PanelLevels) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    fn level(&self, color: C, x: u16, y: u16) -> u8 {
+        let mut luma = color.into().luma();
+        if self.inverted {
+            luma = 255 - luma;
+        }
+        if self.dithering {
+            self.levels.ordered(luma, x, y)
+        } else {
+            self.levels.nearest(luma)
+        }
+    }
+}
+
+impl<C> DrawTarget for ColorView<'_, '_, C>
+where
+    C: PixelColor + Into<Gray8>,
+{
+    type Color = C;
+
+    type Error = Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(coord, color) in pixels.into_iter() {
+            let Some((x, y)) = self.display.to_panel(coord) else {
+                continue;
+            };
+            // dither in panel coordinates, the pattern doesn't depend on the
+            // rotation then
+            let level = self.level(color, x, y);
+            self.display.set_pixel(x, y, level)?;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        if self.dithering {
+            self.fill_solid(&self.bounding_box(), color)
+        } else {
+            self.display.fill(self.level(color, 0, 0))
+        }
+    }
+}
+
+impl<C> OriginDimensions for ColorView<'_, '_, C> {
+    fn size(&self) -> Size {
+        self.display.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use embedded_graphics_core::pixelcolor::{
+        BinaryColor,
+        Gray4,
+        Gray8,
+        GrayColor,
+        Rgb565,
+        Rgb888,
+        RgbColor,
+    };
+
+    use super::*;
+    use crate::Rotation;
+
+    /// Panel level of a single pixel drawn in `color`, without dithering.
+    fn level<C: PixelColor + Into<Gray8>>(color: C, inverted: bool, levels: PanelLevels) -> u8 {
+        let mut display = Display::mock();
+        display
+            .color_view()
+            .with_inverted(inverted)
+            .with_levels(levels)
+            .draw_iter([Pixel(Point::new(10, 20), color)])
+            .unwrap();
+        display.pixel(10, 20)
+    }
+
+    #[test]
+    fn colors_are_mapped_to_the_nearest_level() {
+        let linear = PanelLevels::LINEAR;
+        // luma 0.299 R + 0.587 G + 0.114 B
+        assert_eq!(level(Rgb888::RED, false, linear), 5);
+        assert_eq!(level(Rgb888::GREEN, false, linear), 9);
+        assert_eq!(level(Rgb888::BLUE, false, linear), 2);
+        assert_eq!(level(Rgb888::WHITE, false, linear), 15);
+        assert_eq!(level(Rgb888::new(136, 136, 136), false, linear), 8);
+        assert_eq!(level(Rgb565::RED, false, linear), 5);
+        assert_eq!(level(Rgb565::GREEN, false, linear), 9);
+        assert_eq!(level(Rgb565::BLACK, false, linear), 0);
+        assert_eq!(level(Gray8::new(127), false, linear), 7);
+        assert_eq!(level(Gray8::new(128), false, linear), 8);
+        assert_eq!(level(Gray8::WHITE, false, linear), 15);
+        assert_eq!(level(BinaryColor::Off, false, linear), 0);
+        assert_eq!(level(BinaryColor::On, false, linear), 15);
+    }
+
+    #[test]
+    fn inverted_colors() {
+        let linear = PanelLevels::LINEAR;
+        assert_eq!(level(BinaryColor::On, true, linear), 0);
+        assert_eq!(level(BinaryColor::Off, true, linear), 15);
+        assert_eq!(level(Gray8::new(100), true, linear), 9);
+        assert_eq!(level(Rgb888::BLUE, true, linear), 13);
+    }
+
+    #[test]
+    fn custom_levels() {
+        let gamma = PanelLevels::GAMMA_0_8;
+        assert_eq!(level(Gray8::new(128), false, gamma), 6);
+        assert_eq!(level(Gray8::WHITE, false, gamma), 15);
+        assert_eq!(level(Gray8::WHITE, true, gamma), 0);
+    }
+
+    #[test]
+    fn dithered_fill() {
+        let mut display = Display::mock();
+        display
+            .color_view()
+            .with_dithering(true)
+            .clear(Gray8::new(128))
+            .unwrap();
+        // half of each 4 x 4 block is one level above the lightness
+        for (x, y) in [(0, 0), (480, 268), (956, 536)] {
+            let block: Vec<u8> = (y..y + 4)
+                .flat_map(|y| (x..x + 4).map(move |x| (x, y)))
+                .map(|(x, y)| display.pixel(x, y))
+                .collect();
+            assert_eq!(block.iter().filter(|&&level| level == 8).count(), 8);
+            assert_eq!(block.iter().filter(|&&level| level == 7).count(), 8);
+        }
+        assert_eq!(display.pixel(1, 0), display.pixel(5, 4));
+        assert_ne!(display.pixel(0, 0), display.pixel(1, 0));
+
+        // without dithering the whole screen has the nearest level
+        display.color_view().clear(Gray8::new(128)).unwrap();
+        assert!((0..960).all(|x| display.pixel(x, 0) == 8 && display.pixel(x, 539) == 8));
+
+        // solid colors aren't dithered
+        display
+            .color_view()
+            .with_dithering(true)
+            .clear(BinaryColor::On)
+            .unwrap();
+        assert!((0..960).all(|x| display.pixel(x, 0) == 15));
+    }
+
+    #[test]
+    fn drawing_follows_the_rotation() {
+        let mut display = Display::mock();
+        display.set_rotation(Rotation::Deg90);
+        assert_eq!(display.size(), Size::new(540, 960));
+
+        let pixels = [
+            Pixel(Point::new(0, 0), Gray4::BLACK),
+            Pixel(Point::new(539, 959), Gray4::new(5)),
+            // outside of the rotated screen
+            Pixel(Point::new(540, 0), Gray4::BLACK),
+            Pixel(Point::new(0, -1), Gray4::BLACK),
+        ];
+        display.draw_iter(pixels).unwrap();
+        assert_eq!(display.pixel(0, 539), 0);
+        assert_eq!(display.pixel(959, 0), 5);
+        assert_eq!(display.pixel(0, 0), 15);
+
+        display.set_rotation(Rotation::Deg180);
+        display
+            .color_view()
+            .draw_iter([Pixel(Point::new(0, 0), Gray8::BLACK)])
+            .unwrap();
+        assert_eq!(display.pixel(959, 539), 0);
+    }
+}
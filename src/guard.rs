@@ -0,0 +1,151 @@
+#[cfg(target_arch = "xtensa")]
+use esp_hal::{
+    analog::adc::{AdcCalCurve, AdcCalScheme, AdcChannel, CalibrationAccess, RegisterAccess},
+    gpio::AnalogPin,
+};
+
+#[cfg(target_arch = "xtensa")]
+use crate::{Battery, Display, DrawMode};
+use crate::{Error, Result};
+
+/// Number of samples taken from the battery for a decision.
+#[cfg(target_arch = "xtensa")]
+const SAMPLES: usize = 8;
+
+/// How a refresh has been carried out by the [LowBatteryGuard].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RefreshDecision {
+    /// Regular grayscale update.
+    Grayscale,
+    /// Downgraded to a monochrome update, see
+    /// [Display::flush_monochrome](crate::Display::flush_monochrome).
+    Monochrome,
+}
+
+/// Refuses or downgrades refreshes when the battery runs low.
+///
+/// A grayscale refresh draws enough current to brown out a nearly empty cell
+/// halfway through, leaving a half drawn image. Below
+/// [`LowBatteryGuard::grayscale`] refreshes are downgraded to monochrome,
+/// below [`LowBatteryGuard::monochrome`] they are refused with
+/// [Error::LowBattery].
+///
+/// ```rust ignore
+/// let guard = LowBatteryGuard::default();
+/// match guard.flush(&mut display, &mut battery, DrawMode::BlackOnWhite) {
+///     Ok(RefreshDecision::Monochrome) => log::warn!("battery low"),
+///     Err(Error::LowBattery(voltage)) => enter_deep_sleep(),
+///     _ => {}
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct LowBatteryGuard {
+    /// Minimum voltage for a grayscale refresh.
+    pub grayscale: f32,
+    /// Minimum voltage for a monochrome refresh.
+    pub monochrome: f32,
+}
+
+impl Default for LowBatteryGuard {
+    fn default() -> Self {
+        LowBatteryGuard {
+            grayscale: 3.6,
+            monochrome: 3.45,
+        }
+    }
+}
+
+impl LowBatteryGuard {
+    /// Decide how to refresh at the given battery voltage.
+    pub fn decide(&self, voltage: f32) -> Result<RefreshDecision> {
+        if voltage >= self.grayscale {
+            Ok(RefreshDecision::Grayscale)
+        } else if voltage >= self.monochrome {
+            Ok(RefreshDecision::Monochrome)
+        } else {
+            Err(Error::LowBattery(voltage))
+        }
+    }
+
+    /// Read the battery and flush the display as decided by
+    /// [`LowBatteryGuard::decide`]. A refused refresh leaves the framebuffer
+    /// untouched, so it can be flushed once the battery has been charged.
+    #[cfg(target_arch = "xtensa")]
+    pub fn flush<PIN, ADCI>(
+        &self,
+        display: &mut Display<'_>,
+        battery: &mut Battery<'_, PIN, ADCI>,
+        mode: DrawMode,
+    ) -> Result<RefreshDecision>
+    where
+        PIN: AdcChannel + AnalogPin,
+        ADCI: RegisterAccess + CalibrationAccess,
+        AdcCalCurve<ADCI>: AdcCalScheme<ADCI>,
+    {
+        let voltage = battery.read_oversampled::<SAMPLES>()?;
+        let decision = self.decide(voltage)?;
+        match decision {
+            RefreshDecision::Grayscale => display.flush(mode)?,
+            RefreshDecision::Monochrome => display.flush_monochrome(mode)?,
+        }
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decide() {
+        let guard = LowBatteryGuard::default();
+        let table = [
+            (4.2, Ok(RefreshDecision::Grayscale)),
+            (3.6, Ok(RefreshDecision::Grayscale)),
+            (3.599, Ok(RefreshDecision::Monochrome)),
+            (3.45, Ok(RefreshDecision::Monochrome)),
+            (3.449, Err(Error::LowBattery(3.449))),
+            (0.0, Err(Error::LowBattery(0.0))),
+        ];
+        for (voltage, decision) in table {
+            assert_eq!(guard.decide(voltage), decision, "{}V", voltage);
+        }
+    }
+
+    #[test]
+    fn custom_thresholds() {
+        let guard = LowBatteryGuard {
+            grayscale: 3.8,
+            monochrome: 3.3,
+        };
+        let table = [
+            (3.8, Ok(RefreshDecision::Grayscale)),
+            (3.7, Ok(RefreshDecision::Monochrome)),
+            (3.3, Ok(RefreshDecision::Monochrome)),
+            (3.2, Err(Error::LowBattery(3.2))),
+        ];
+        for (voltage, decision) in table {
+            assert_eq!(guard.decide(voltage), decision, "{}V", voltage);
+        }
+        // monochrome refreshes can be disabled altogether
+        let guard = LowBatteryGuard {
+            grayscale: 3.6,
+            monochrome: 3.6,
+        };
+        assert_eq!(guard.decide(3.6), Ok(RefreshDecision::Grayscale));
+        assert_eq!(guard.decide(3.59), Err(Error::LowBattery(3.59)));
+    }
+
+    #[test]
+    fn low_battery_error() {
+        let err = LowBatteryGuard::default().decide(3.3).unwrap_err();
+        assert_eq!(err, Error::LowBattery(3.3));
+        assert_ne!(err, Error::Adc);
+        assert_eq!(err.operation(), None);
+        assert_eq!(
+            alloc::format!("{}", err),
+            "refresh refused, battery too low (3.30V)"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! Types of esp-hal used outside of the drivers, e.g. in [Error](crate::Error).
+//!
+//! esp-hal only builds for the ESP32-S3. On other targets, i.e. when running
+//! the unit tests on the host, stand-ins with the variants the mocks need take
+//! their place.
+
+#[cfg(target_arch = "xtensa")]
+pub use esp_hal::{
+    dma::{DmaBufError, DmaError},
+    rmt::{Error as RmtError, PulseCode},
+};
+
+#[cfg(not(target_arch = "xtensa"))]
+pub use self::host::*;
+
+#[cfg(not(target_arch = "xtensa"))]
+mod host {
+    /// Stand-in for `esp_hal::rmt::Error`.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub enum RmtError {
+        TransmissionError,
+    }
+
+    /// Stand-in for `esp_hal::dma::DmaError`.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub enum DmaError {
+        DescriptorError,
+    }
+
+    /// Stand-in for `esp_hal::dma::DmaBufError`.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub enum DmaBufError {
+        BufferTooSmall,
+    }
+
+    /// Stand-in for `esp_hal::rmt::PulseCode`, with the same encoding: a level
+    /// in bit 15 and a length in bits 0 to 14 for each half.
+    pub trait PulseCode {
+        fn new(level1: bool, length1: u16, level2: bool, length2: u16) -> Self;
+
+        fn empty() -> Self;
+    }
+
+    impl PulseCode for u32 {
+        fn new(level1: bool, length1: u16, level2: bool, length2: u16) -> Self {
+            let half = |level: bool, length: u16| (level as u32) << 15 | (length as u32 & 0x7FFF);
+            half(level1, length1) | half(level2, length2) << 16
+        }
+
+        fn empty() -> Self {
+            0
+        }
+    }
+}
@@ -0,0 +1,449 @@
+use alloc::vec;
+
+use crate::{Display, Error, Result};
+
+/// A 4 bit grayscale image in the native format of the framebuffer.
+///
+/// Rows are stored nibble packed like the framebuffer, with even pixels in the
+/// low nibble, so [`Display::blit`] can copy them without conversion. 0 is
+/// black and 15 is white.
+///
+/// The serialized format is little endian:
+///
+/// | Offset | Size | Content                                          |
+/// |--------|------|--------------------------------------------------|
+/// | 0      | 2    | magic `"E4"`                                     |
+/// | 2      | 1    | version, currently 1                             |
+/// | 3      | 1    | flags: bit 0 transparency key, bit 1 RLE         |
+/// | 4      | 2    | width                                            |
+/// | 6      | 2    | height                                           |
+/// | 8      | 1    | transparency key (low nibble), if flagged        |
+/// | 9      | 1    | reserved                                         |
+/// | 10     |      | rows of `(width + 1) / 2` bytes each             |
+///
+/// With RLE, every row is compressed on its own with PackBits: a header byte
+/// `n` below 128 is followed by `n + 1` literal bytes, any other header byte
+/// repeats the following byte `n - 126` times.
+///
+/// ```rust ignore
+/// static LOGO: &[u8] = include_bytes!("logo.e4");
+///
+/// let logo = Image4bpp::from_bytes(LOGO)?;
+/// display.blit(&logo, 20, 20)?;
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Image4bpp<'a> {
+    width: u16,
+    height: u16,
+    transparent: Option<u8>,
+    rle: bool,
+    data: &'a [u8],
+}
+
+impl<'a> Image4bpp<'a> {
+    /// Size of the header in bytes.
+    pub const HEADER_SIZE: usize = 10;
+    /// Version of the serialized format.
+    pub const VERSION: u8 = 1;
+
+    const MAGIC: [u8; 2] = *b"E4";
+    const FLAG_TRANSPARENT: u8 = 0x01;
+    const FLAG_RLE: u8 = 0x02;
+
+    /// Wrap uncompressed, nibble packed rows without a header, e.g. from a
+    /// generated `const` array. Returns [Error::InvalidImage] if the data
+    /// doesn't match the size.
+    pub const fn from_raw(width: u16, height: u16, data: &'a [u8]) -> Result<Self> {
+        if data.len() != stride(width) * height as usize {
+            return Err(Error::InvalidImage);
+        }
+        Ok(Image4bpp {
+            width,
+            height,
+            transparent: None,
+            rle: false,
+            data,
+        })
+    }
+
+    /// Parse a serialized image, see [Image4bpp] for the format.
+    ///
+    /// Returns [Error::InvalidImage] if the header is unknown or the data is
+    /// truncated.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+        if bytes.len() < Self::HEADER_SIZE
+            || bytes[0..2] != Self::MAGIC
+            || bytes[2] != Self::VERSION
+        {
+            return Err(Error::InvalidImage);
+        }
+        let flags = bytes[3];
+        let image = Image4bpp {
+            width: u16::from_le_bytes([bytes[4], bytes[5]]),
+            height: u16::from_le_bytes([bytes[6], bytes[7]]),
+            transparent: (flags & Self::FLAG_TRANSPARENT != 0).then_some(bytes[8] & 0x0F),
+            rle: flags & Self::FLAG_RLE != 0,
+            data: &bytes[Self::HEADER_SIZE..],
+        };
+        if image.rle {
+            // walk all rows once, so blitting can't run out of data
+            let mut row = vec![0u8; image.stride()];
+            let mut offset = 0;
+            for _ in 0..image.height {
+                offset = image.decode_row(offset, &mut row)?;
+            }
+            if offset != image.data.len() {
+                return Err(Error::InvalidImage);
+            }
+        } else if image.data.len() != image.stride() * image.height as usize {
+            return Err(Error::InvalidImage);
+        }
+        Ok(image)
+    }
+
+    /// Draw pixels of the given color transparent.
+    pub const fn with_transparency(mut self, color: u8) -> Self {
+        self.transparent = Some(color & 0x0F);
+        self
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Transparent color, if any.
+    pub fn transparent(&self) -> Option<u8> {
+        self.transparent
+    }
+
+    /// Returns whether the rows are RLE compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.rle
+    }
+
+    /// Bytes per uncompressed row.
+    pub fn stride(&self) -> usize {
+        stride(self.width)
+    }
+
+    /// Decode the RLE compressed row starting at `offset` into `row`, returns
+    /// the offset of the next row.
+    fn decode_row(&self, mut offset: usize, row: &mut [u8]) -> Result<usize> {
+        let mut filled = 0;
+        while filled < row.len() {
+            let header = *self.data.get(offset).ok_or(Error::InvalidImage)? as usize;
+            offset += 1;
+            if header < 128 {
+                let len = header + 1;
+                let literal = self.data.get(offset..offset + len);
+                let target = row.get_mut(filled..filled + len);
+                match (literal, target) {
+                    (Some(literal), Some(target)) => target.copy_from_slice(literal),
+                    _ => return Err(Error::InvalidImage),
+                }
+                offset += len;
+                filled += len;
+            } else {
+                let len = header - 126;
+                let value = *self.data.get(offset).ok_or(Error::InvalidImage)?;
+                row.get_mut(filled..filled + len)
+                    .ok_or(Error::InvalidImage)?
+                    .fill(value);
+                offset += 1;
+                filled += len;
+            }
+        }
+        Ok(offset)
+    }
+}
+
+const fn stride(width: u16) -> usize {
+    (width as usize).div_ceil(2)
+}
+
+impl<'a> Display<'a> {
+    /// Copy an image into the framebuffer with its top left corner at
+    /// `x`/`y`. The image is clipped to the screen, the origin may lie above
+    /// or left of it.
+    pub fn blit(&mut self, image: &Image4bpp<'_>, x: i32, y: i32) -> Result<()> {
+        // columns cut off at the left edge
+        let skip = x.min(0).unsigned_abs() as usize;
+        if skip >= image.width as usize || x >= Self::WIDTH as i32 || y >= Self::HEIGHT as i32 {
+            return Ok(());
+        }
+        let width = image.width - skip as u16;
+        let stride = image.stride();
+        let mut row = vec![0u8; if image.rle { stride } else { 0 }];
+        // the visible part of a row, if it doesn't start at a byte boundary
+        let mut shifted = vec![0u8; if skip % 2 == 1 { stride } else { 0 }];
+        let mut offset = 0;
+        for i in 0..image.height {
+            let packed = if image.rle {
+                offset = image.decode_row(offset, &mut row)?;
+                &row[..]
+            } else {
+                let start = i as usize * stride;
+                &image.data[start..start + stride]
+            };
+            let py = y + i as i32;
+            if py < 0 {
+                continue;
+            }
+            if py >= Self::HEIGHT as i32 {
+                break;
+            }
+            let visible = if skip % 2 == 1 {
+                for j in 0..width as usize {
+                    let shift = 4 * (j % 2);
+                    shifted[j / 2] =
+                        shifted[j / 2] & !(0x0F << shift) | nibble(packed, skip + j) << shift;
+                }
+                &shifted[..]
+            } else {
+                &packed[skip / 2..]
+            };
+            self.write_packed_row(
+                x.max(0) as u16,
+                py as u16,
+                width,
+                visible,
+                image.transparent,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Pixel `i` of a nibble packed row.
+fn nibble(packed: &[u8], i: usize) -> u8 {
+    (packed[i / 2] >> (4 * (i % 2))) & 0x0F
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+
+    const WIDTH: u16 = 5;
+    const HEIGHT: u16 = 3;
+
+    /// Level of the test image at `x`/`y`, all different and none white.
+    fn level(x: u16, y: u16) -> u8 {
+        (x + WIDTH * y) as u8
+    }
+
+    /// Nibble packed rows of the test image.
+    fn rows() -> Vec<u8> {
+        let mut rows = vec![0u8; stride(WIDTH) * HEIGHT as usize];
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                rows[y as usize * stride(WIDTH) + x as usize / 2] |= level(x, y) << (4 * (x % 2));
+            }
+        }
+        rows
+    }
+
+    fn serialize(width: u16, height: u16, flags: u8, key: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![b'E', b'4', Image4bpp::VERSION, flags];
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(&[key, 0]);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// Checks that the test image has been drawn with its origin at `x`/`y`
+    /// and that the surrounding pixels are untouched.
+    fn assert_blitted(display: &Display, x: i32, y: i32) {
+        for py in y - 1..=y + HEIGHT as i32 {
+            for px in x - 1..=x + WIDTH as i32 {
+                let (Ok(sx), Ok(sy)) = (u16::try_from(px), u16::try_from(py)) else {
+                    continue;
+                };
+                if sx >= Display::WIDTH || sy >= Display::HEIGHT {
+                    continue;
+                }
+                let (ix, iy) = (px - x, py - y);
+                let expected =
+                    if (0..WIDTH as i32).contains(&ix) && (0..HEIGHT as i32).contains(&iy) {
+                        level(ix as u16, iy as u16)
+                    } else {
+                        15
+                    };
+                assert_eq!(display.pixel(sx, sy), expected, "pixel {}/{}", px, py);
+            }
+        }
+    }
+
+    #[test]
+    fn header() {
+        let rows = rows();
+        let valid = serialize(WIDTH, HEIGHT, 0, 0, &rows);
+        let image = Image4bpp::from_bytes(&valid).unwrap();
+        assert_eq!((image.width(), image.height()), (WIDTH, HEIGHT));
+        assert_eq!(image.stride(), 3);
+        assert_eq!(image.transparent(), None);
+        assert!(!image.is_compressed());
+        assert_eq!(image, Image4bpp::from_raw(WIDTH, HEIGHT, &rows).unwrap());
+
+        for len in 0..Image4bpp::HEADER_SIZE {
+            assert_eq!(
+                Image4bpp::from_bytes(&valid[..len]),
+                Err(Error::InvalidImage)
+            );
+        }
+        let mut magic = valid.clone();
+        magic[1] = b'5';
+        assert_eq!(Image4bpp::from_bytes(&magic), Err(Error::InvalidImage));
+        let mut version = valid.clone();
+        version[2] = Image4bpp::VERSION + 1;
+        assert_eq!(Image4bpp::from_bytes(&version), Err(Error::InvalidImage));
+    }
+
+    #[test]
+    fn truncated_data() {
+        let rows = rows();
+        let truncated = serialize(WIDTH, HEIGHT, 0, 0, &rows[..rows.len() - 1]);
+        assert_eq!(Image4bpp::from_bytes(&truncated), Err(Error::InvalidImage));
+        let trailing = serialize(WIDTH, HEIGHT, 0, 0, &[&rows[..], &[0]].concat());
+        assert_eq!(Image4bpp::from_bytes(&trailing), Err(Error::InvalidImage));
+        assert_eq!(
+            Image4bpp::from_raw(WIDTH, HEIGHT, &rows[1..]),
+            Err(Error::InvalidImage)
+        );
+        // a larger size than the data
+        let height = serialize(WIDTH, HEIGHT + 1, 0, 0, &rows);
+        assert_eq!(Image4bpp::from_bytes(&height), Err(Error::InvalidImage));
+    }
+
+    #[test]
+    fn rle() {
+        // 8 x 2 pixels, a run of 4 bytes and a literal followed by a run
+        let raw = [0x11, 0x11, 0x11, 0x11, 0x10, 0x32, 0x32, 0x32];
+        let rle = [130, 0x11, 0, 0x10, 129, 0x32];
+        let bytes = serialize(8, 2, 0x02, 0, &rle);
+        let image = Image4bpp::from_bytes(&bytes).unwrap();
+        assert!(image.is_compressed());
+
+        let mut expected = Display::mock();
+        expected
+            .blit(&Image4bpp::from_raw(8, 2, &raw).unwrap(), 3, 4)
+            .unwrap();
+        let mut display = Display::mock();
+        display.blit(&image, 3, 4).unwrap();
+        for y in 3..7 {
+            for x in 2..12 {
+                assert_eq!(
+                    display.pixel(x, y),
+                    expected.pixel(x, y),
+                    "pixel {}/{}",
+                    x,
+                    y
+                );
+            }
+        }
+        assert_eq!(display.pixel(3, 4), 1);
+        assert_eq!(display.pixel(4, 5), 1);
+        assert_eq!(display.pixel(5, 5), 2);
+        assert_eq!(display.pixel(10, 5), 3);
+    }
+
+    #[test]
+    fn corrupted_rle() {
+        for rle in [
+            // missing second row
+            &[130, 0x11][..],
+            // trailing data
+            &[130, 0x11, 130, 0x22, 0],
+            // literal beyond the row
+            &[130, 0x11, 4, 1, 2, 3, 4, 5],
+            // run beyond the row
+            &[130, 0x11, 131, 0x22],
+            // literal beyond the data
+            &[130, 0x11, 3, 1, 2],
+            // run without a value
+            &[130, 0x11, 130],
+        ] {
+            let bytes = serialize(8, 2, 0x02, 0, rle);
+            assert_eq!(
+                Image4bpp::from_bytes(&bytes),
+                Err(Error::InvalidImage),
+                "{:?}",
+                rle
+            );
+        }
+    }
+
+    #[test]
+    fn transparency_key() {
+        let rows = rows();
+        // the high nibble of the key is ignored
+        let keyed = serialize(WIDTH, HEIGHT, 0x01, 0xF6, &rows);
+        let image = Image4bpp::from_bytes(&keyed).unwrap();
+        assert_eq!(image.transparent(), Some(6));
+        let unkeyed = serialize(WIDTH, HEIGHT, 0, 6, &rows);
+        let unflagged = Image4bpp::from_bytes(&unkeyed).unwrap();
+        assert_eq!(unflagged.transparent(), None);
+        assert_eq!(unflagged.with_transparency(0x16), image);
+
+        let mut display = Display::mock();
+        display.fill(0).unwrap();
+        display.blit(&image, 10, 20).unwrap();
+        // level 6 is the pixel 1/1
+        assert_eq!(display.pixel(11, 21), 0);
+        assert_eq!(display.pixel(10, 21), 5);
+        assert_eq!(display.pixel(12, 21), 7);
+    }
+
+    #[test]
+    fn blit() {
+        let rows = rows();
+        let image = Image4bpp::from_raw(WIDTH, HEIGHT, &rows).unwrap();
+        // byte aligned and not
+        for (x, y) in [(10, 20), (11, 20)] {
+            let mut display = Display::mock();
+            display.blit(&image, x, y).unwrap();
+            assert_blitted(&display, x, y);
+        }
+    }
+
+    #[test]
+    fn clipping() {
+        let rows = rows();
+        let image = Image4bpp::from_raw(WIDTH, HEIGHT, &rows).unwrap();
+        let (right, bottom) = (Display::WIDTH as i32, Display::HEIGHT as i32);
+        for (x, y) in [
+            (right - 3, bottom - 2),
+            (right - 4, 0),
+            (-1, -1),
+            (-2, 10),
+            (-3, bottom - 1),
+            (-4, -2),
+        ] {
+            let mut display = Display::mock();
+            display.blit(&image, x, y).unwrap();
+            assert_blitted(&display, x, y);
+        }
+
+        // entirely outside of the screen
+        for (x, y) in [
+            (-5, 0),
+            (0, -3),
+            (right, 0),
+            (0, bottom),
+            (i32::MIN, i32::MAX),
+        ] {
+            let mut display = Display::mock();
+            display.blit(&image, x, y).unwrap();
+            for (x, y) in [(0, 0), (959, 0), (0, 539), (959, 539)] {
+                assert_eq!(display.pixel(x, y), 15);
+            }
+        }
+    }
+}
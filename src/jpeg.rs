@@ -0,0 +1,820 @@
+use alloc::{boxed::Box, vec};
+
+use crate::{decode::RowWriter, DecodeOptions, Display, Error, Result};
+
+const SOF0: u8 = 0xC0;
+const SOF1: u8 = 0xC1;
+const DHT: u8 = 0xC4;
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const DQT: u8 = 0xDB;
+const DRI: u8 = 0xDD;
+
+/// Natural index of the coefficients in zigzag order.
+const ZIGZAG: [u8; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// `cos(k * pi / 16)` in 1/4096.
+const COS: [i32; 32] = [
+    4096, 4017, 3784, 3406, 2896, 2276, 1567, 799, 0, -799, -1567, -2276, -2896, -3406, -3784,
+    -4017, -4096, -4017, -3784, -3406, -2896, -2276, -1567, -799, 0, 799, 1567, 2276, 2896, 3406,
+    3784, 4017,
+];
+/// `cos(pi / 4)` in 1/4096, the factor of the DC coefficient.
+const COS_DC: i32 = 2896;
+/// Dequantized coefficients of valid 8 bit images stay well within this
+/// range, clamping keeps the IDCT from overflowing on corrupted data.
+const MAX_COEFFICIENT: i32 = 1 << 12;
+
+/// A baseline JPEG image, decoded while drawing it with
+/// [Display::draw_jpeg].
+///
+/// The image is decoded one row of MCUs (8 or 16 pixel rows) at a time
+/// straight into the framebuffer, so besides the input it only needs the
+/// Huffman tables and the luma of a single MCU row, about 24 KiB for a 960
+/// pixel wide image. Only the luma is decoded, the color components are
+/// skipped.
+///
+/// When drawing smaller than the image, the image is scaled to 1/2, 1/4 or
+/// 1/8 by the IDCT, which is much faster than decoding at full size. Any
+/// remaining scaling is done by averaging.
+///
+/// Grayscale and YCbCr images with any chroma subsampling and restart
+/// intervals are supported. Progressive, arithmetic coded, 12 bit and CMYK
+/// images are not.
+///
+/// ```rust ignore
+/// let jpeg = Jpeg::new(include_bytes!("photo.jpg"))?;
+/// // decoded at 1/4 of the size by the IDCT
+/// let options = DecodeOptions {
+///     width: Some(jpeg.width() / 4),
+///     ..Default::default()
+/// };
+/// display.draw_jpeg(&jpeg, 0, 0, &options)?;
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Jpeg<'a> {
+    width: u16,
+    height: u16,
+    data: &'a [u8],
+}
+
+impl<'a> Jpeg<'a> {
+    /// Parse the headers of a JPEG file up to the frame header.
+    ///
+    /// Returns [Error::InvalidImage] if the data isn't a JPEG file and
+    /// [Error::UnsupportedImage] if it isn't a baseline or extended
+    /// sequential 8 bit grayscale or YCbCr image.
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        let mut reader = Reader::new(data)?;
+        loop {
+            let (marker, body) = reader.segment()?;
+            match marker {
+                SOF0 | SOF1 => {
+                    let frame = Frame::parse(body)?;
+                    return Ok(Jpeg {
+                        width: frame.width,
+                        height: frame.height,
+                        data,
+                    });
+                }
+                // progressive, lossless, hierarchical and arithmetic coding
+                0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                    return Err(Error::UnsupportedImage)
+                }
+                SOS | EOI => return Err(Error::InvalidImage),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Decode the luma with an IDCT of `size` x `size` pixels per block and
+    /// pass it to `row` one pixel row at a time, until `row` returns `true`.
+    fn decode_luma(&self, size: usize, mut row: impl FnMut(&[u8]) -> Result<bool>) -> Result<()> {
+        let mut decoder = Decoder::new(size);
+        let mut frame = None;
+        let mut reader = Reader::new(self.data)?;
+        loop {
+            let (marker, body) = reader.segment()?;
+            match marker {
+                DQT => decoder.set_quantization(body)?,
+                DHT => decoder.set_huffman(body)?,
+                DRI => decoder.restart_interval = be16(body.get(..2).ok_or(Error::InvalidImage)?),
+                SOF0 | SOF1 => frame = Some(Frame::parse(body)?),
+                SOS => {
+                    let frame = frame.as_ref().ok_or(Error::InvalidImage)?;
+                    let scan = Scan::parse(body, frame)?;
+                    // the luma is the first component
+                    if scan.components[..scan.count].iter().any(|c| c.index == 0) {
+                        let data = &self.data[reader.position..];
+                        return decoder.decode_scan(frame, &scan, data, &mut row);
+                    }
+                    reader.skip_scan();
+                }
+                EOI => return Err(Error::InvalidImage),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'a> Display<'a> {
+    /// Decode a JPEG image into the framebuffer with its top left corner at
+    /// `x`/`y`, see [Jpeg]. The image is clipped to the screen.
+    ///
+    /// Returns [Error::InvalidImage] if the image data is corrupted. Rows
+    /// decoded up to that point stay in the framebuffer.
+    pub fn draw_jpeg(
+        &mut self,
+        jpeg: &Jpeg<'_>,
+        x: u16,
+        y: u16,
+        options: &DecodeOptions,
+    ) -> Result<()> {
+        let (width, height) = options.output_size(jpeg.width, jpeg.height);
+        let scaled = |len: u16, size: usize| (len as usize * size).div_ceil(8) as u16;
+        // smallest IDCT size still at least as large as the output
+        let size = [1, 2, 4, 8]
+            .into_iter()
+            .find(|&size| scaled(jpeg.width, size) >= width && scaled(jpeg.height, size) >= height)
+            .unwrap_or(8);
+        let source = (scaled(jpeg.width, size), scaled(jpeg.height, size));
+        let mut writer = RowWriter::new(options, x, y, source, (width, height));
+        jpeg.decode_luma(size, |row| {
+            writer.push_row(self, row)?;
+            Ok(writer.is_done())
+        })
+    }
+}
+
+fn be16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+/// Reads the marker segments of a JPEG file.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        if data.get(..2) != Some(&[0xFF, SOI]) {
+            return Err(Error::InvalidImage);
+        }
+        Ok(Reader { data, position: 2 })
+    }
+
+    /// Next marker and its segment, empty for markers without a segment.
+    fn segment(&mut self) -> Result<(u8, &'a [u8])> {
+        // skip garbage and fill bytes up to the marker
+        let marker = loop {
+            match self.data.get(self.position..self.position + 2) {
+                Some(&[0xFF, marker]) if marker != 0xFF && marker != 0 => break marker,
+                Some(_) => self.position += 1,
+                None => return Err(Error::InvalidImage),
+            }
+        };
+        self.position += 2;
+        if matches!(marker, 0x01 | 0xD0..=0xD9) {
+            return Ok((marker, &[]));
+        }
+        let len = self
+            .data
+            .get(self.position..self.position + 2)
+            .map(be16)
+            .filter(|&len| len >= 2)
+            .ok_or(Error::InvalidImage)? as usize;
+        let body = self
+            .data
+            .get(self.position + 2..self.position + len)
+            .ok_or(Error::InvalidImage)?;
+        self.position += len;
+        Ok((marker, body))
+    }
+
+    /// Skip the entropy coded data of a scan.
+    fn skip_scan(&mut self) {
+        while let Some(&byte) = self.data.get(self.position) {
+            let next = self.data.get(self.position + 1).copied();
+            if byte == 0xFF && !matches!(next, Some(0x00 | 0xD0..=0xD7)) {
+                return;
+            }
+            self.position += 1;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Component {
+    id: u8,
+    h: u8,
+    v: u8,
+    quantization: u8,
+}
+
+/// Frame header.
+struct Frame {
+    width: u16,
+    height: u16,
+    components: [Component; 3],
+    count: usize,
+    h_max: u8,
+    v_max: u8,
+}
+
+impl Frame {
+    fn parse(body: &[u8]) -> Result<Self> {
+        if body.len() < 6 {
+            return Err(Error::InvalidImage);
+        }
+        let (precision, height, width, count) = (
+            body[0],
+            be16(&body[1..3]),
+            be16(&body[3..5]),
+            body[5] as usize,
+        );
+        // 12 bit, CMYK and the height defined by a DNL marker later on
+        if precision != 8 || !matches!(count, 1 | 3) || height == 0 {
+            return Err(Error::UnsupportedImage);
+        }
+        if width == 0 || body.len() < 6 + 3 * count {
+            return Err(Error::InvalidImage);
+        }
+        let mut components = [Component::default(); 3];
+        for (component, bytes) in components[..count]
+            .iter_mut()
+            .zip(body[6..].chunks_exact(3))
+        {
+            *component = Component {
+                id: bytes[0],
+                h: bytes[1] >> 4,
+                v: bytes[1] & 0x0F,
+                quantization: bytes[2],
+            };
+            if !(1..=4).contains(&component.h)
+                || !(1..=4).contains(&component.v)
+                || component.quantization > 3
+            {
+                return Err(Error::InvalidImage);
+            }
+        }
+        let components_used = &components[..count];
+        let h_max = components_used.iter().map(|c| c.h).max().unwrap_or(1);
+        let v_max = components_used.iter().map(|c| c.v).max().unwrap_or(1);
+        if components_used.iter().map(|c| c.h * c.v).sum::<u8>() > 10 {
+            return Err(Error::InvalidImage);
+        }
+        // subsampled luma would have to be upscaled
+        if components[0].h != h_max || components[0].v != v_max {
+            return Err(Error::UnsupportedImage);
+        }
+        Ok(Frame {
+            width,
+            height,
+            components,
+            count,
+            h_max,
+            v_max,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ScanComponent {
+    /// Index of the component in the frame.
+    index: usize,
+    dc_table: usize,
+    ac_table: usize,
+}
+
+/// Scan header.
+struct Scan {
+    components: [ScanComponent; 3],
+    count: usize,
+}
+
+impl Scan {
+    fn parse(body: &[u8], frame: &Frame) -> Result<Self> {
+        let count = *body.first().ok_or(Error::InvalidImage)? as usize;
+        if !(1..=frame.count).contains(&count) || body.len() < 1 + 2 * count {
+            return Err(Error::InvalidImage);
+        }
+        let mut components = [ScanComponent::default(); 3];
+        for (component, bytes) in components[..count]
+            .iter_mut()
+            .zip(body[1..].chunks_exact(2))
+        {
+            let index = frame.components[..frame.count]
+                .iter()
+                .position(|c| c.id == bytes[0])
+                .ok_or(Error::InvalidImage)?;
+            let (dc_table, ac_table) = ((bytes[1] >> 4) as usize, (bytes[1] & 0x0F) as usize);
+            if dc_table > 3 || ac_table > 3 {
+                return Err(Error::InvalidImage);
+            }
+            *component = ScanComponent {
+                index,
+                dc_table,
+                ac_table,
+            };
+        }
+        Ok(Scan { components, count })
+    }
+}
+
+/// Canonical Huffman table.
+struct Huffman {
+    /// Value and length of the codes of up to 8 bits, indexed by the next 8
+    /// bits. Longer codes have a length of 0.
+    fast: [(u8, u8); 256],
+    /// Largest code of each length, -1 if there is none.
+    max_code: [i32; 17],
+    /// Offset from a code of each length to the index of its value.
+    offset: [i32; 17],
+    values: [u8; 256],
+}
+
+impl Huffman {
+    const EMPTY: Huffman = Huffman {
+        fast: [(0, 0); 256],
+        max_code: [-1; 17],
+        offset: [0; 17],
+        values: [0; 256],
+    };
+
+    /// Build the table from the code counts and the values of a DHT segment,
+    /// returns the length of the definition.
+    fn build(&mut self, body: &[u8]) -> Result<usize> {
+        let counts = body.get(..16).ok_or(Error::InvalidImage)?;
+        let total = counts.iter().map(|&count| count as usize).sum::<usize>();
+        let values = body
+            .get(16..16 + total)
+            .filter(|values| values.len() <= 256)
+            .ok_or(Error::InvalidImage)?;
+        *self = Self::EMPTY;
+        self.values[..total].copy_from_slice(values);
+        let (mut code, mut index) = (0usize, 0usize);
+        for len in 1..=16 {
+            let count = counts[len - 1] as usize;
+            if code + count > 1 << len {
+                return Err(Error::InvalidImage);
+            }
+            self.offset[len] = index as i32 - code as i32;
+            if count > 0 {
+                self.max_code[len] = (code + count - 1) as i32;
+            }
+            for _ in 0..count {
+                if len <= 8 {
+                    let shift = 8 - len;
+                    for low in 0..1 << shift {
+                        self.fast[code << shift | low] = (values[index], len as u8);
+                    }
+                }
+                code += 1;
+                index += 1;
+            }
+            code <<= 1;
+        }
+        Ok(16 + total)
+    }
+}
+
+/// Bit reader of the entropy coded data.
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+    /// A marker has been reached, the remaining bits are zero.
+    marker: bool,
+    /// The data ended without a marker.
+    truncated: bool,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bits {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+            marker: false,
+            truncated: false,
+        }
+    }
+
+    /// Fill the buffer with at least 25 bits.
+    fn fill(&mut self) {
+        while self.count <= 24 {
+            let mut byte = 0;
+            if !self.marker {
+                match self.data.get(self.position) {
+                    // stuffed zero byte
+                    Some(0xFF) if self.data.get(self.position + 1) == Some(&0) => {
+                        byte = 0xFF;
+                        self.position += 2;
+                    }
+                    Some(0xFF) => self.marker = true,
+                    Some(&value) => {
+                        byte = value;
+                        self.position += 1;
+                    }
+                    None => {
+                        self.marker = true;
+                        self.truncated = true;
+                    }
+                }
+            }
+            self.buffer |= (byte as u32) << (24 - self.count);
+            self.count += 8;
+        }
+    }
+
+    /// Next `n` bits, for `n` of at most 16.
+    fn peek(&mut self, n: u32) -> u32 {
+        self.fill();
+        if n == 0 {
+            0
+        } else {
+            self.buffer >> (32 - n)
+        }
+    }
+
+    fn consume(&mut self, n: u32) {
+        self.buffer <<= n;
+        self.count -= n;
+    }
+
+    /// Read an `n` bit value and extend its sign.
+    fn receive_extend(&mut self, n: u32) -> i32 {
+        let value = self.peek(n) as i32;
+        self.consume(n);
+        if n > 0 && value < 1 << (n - 1) {
+            value - (1 << n) + 1
+        } else {
+            value
+        }
+    }
+
+    fn decode(&mut self, table: &Huffman) -> Result<u8> {
+        let bits = self.peek(16);
+        let (value, len) = table.fast[(bits >> 8) as usize];
+        if len > 0 {
+            self.consume(len as u32);
+            return Ok(value);
+        }
+        for len in 9..=16 {
+            let code = (bits >> (16 - len)) as i32;
+            if code <= table.max_code[len] {
+                self.consume(len as u32);
+                return Ok(table.values[(code + table.offset[len]) as usize]);
+            }
+        }
+        Err(Error::InvalidImage)
+    }
+
+    /// Drop the remaining bits and skip the restart marker.
+    fn restart(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+        while self.data.get(self.position) == Some(&0xFF)
+            && self.data.get(self.position + 1) == Some(&0xFF)
+        {
+            self.position += 1;
+        }
+        if self.data.get(self.position) == Some(&0xFF)
+            && matches!(self.data.get(self.position + 1), Some(0xD0..=0xD7))
+        {
+            self.position += 2;
+            self.marker = false;
+        }
+    }
+}
+
+/// Tables and state of the decoder.
+struct Decoder {
+    /// Quantization tables in natural order.
+    quantization: [[u16; 64]; 4],
+    /// DC tables followed by AC tables.
+    huffman: Box<[Huffman; 8]>,
+    restart_interval: u16,
+    /// Size of the IDCT output, 8 pixels at full scale.
+    size: usize,
+    /// IDCT factors for each coefficient and output pixel.
+    factors: [i32; 64],
+}
+
+impl Decoder {
+    fn new(size: usize) -> Self {
+        let mut factors = [0; 64];
+        for u in 0..size {
+            for x in 0..size {
+                factors[u * 8 + x] = if u == 0 {
+                    COS_DC
+                } else {
+                    COS[(2 * x + 1) * u * (8 / size) % 32]
+                };
+            }
+        }
+        Decoder {
+            quantization: [[0; 64]; 4],
+            huffman: Box::new([Huffman::EMPTY; 8]),
+            restart_interval: 0,
+            size,
+            factors,
+        }
+    }
+
+    fn set_quantization(&mut self, mut body: &[u8]) -> Result<()> {
+        while let Some(&info) = body.first() {
+            let (precision, id) = (info >> 4, (info & 0x0F) as usize);
+            let len = if precision == 0 { 64 } else { 128 };
+            let values = body.get(1..1 + len).ok_or(Error::InvalidImage)?;
+            let table = self.quantization.get_mut(id).ok_or(Error::InvalidImage)?;
+            for (i, &natural) in ZIGZAG.iter().enumerate() {
+                table[natural as usize] = if precision == 0 {
+                    values[i] as u16
+                } else {
+                    be16(&values[2 * i..])
+                };
+            }
+            body = &body[1 + len..];
+        }
+        Ok(())
+    }
+
+    fn set_huffman(&mut self, mut body: &[u8]) -> Result<()> {
+        while let Some(&info) = body.first() {
+            let (class, id) = ((info >> 4) as usize, (info & 0x0F) as usize);
+            if class > 1 || id > 3 {
+                return Err(Error::InvalidImage);
+            }
+            let len = self.huffman[class * 4 + id].build(&body[1..])?;
+            body = &body[1 + len..];
+        }
+        Ok(())
+    }
+
+    /// Decode the scan containing the luma and pass it on row by row, see
+    /// [`Jpeg::decode_luma`].
+    fn decode_scan(
+        &self,
+        frame: &Frame,
+        scan: &Scan,
+        data: &[u8],
+        output: &mut impl FnMut(&[u8]) -> Result<bool>,
+    ) -> Result<()> {
+        let size = self.size;
+        let components = &scan.components[..scan.count];
+        // blocks of each component in an MCU, a single one without interleaving
+        let blocks = |index: usize| {
+            let component = &frame.components[index];
+            if scan.count > 1 {
+                (component.h as usize, component.v as usize)
+            } else {
+                (1, 1)
+            }
+        };
+        let (mcu_width, mcu_height) = if scan.count > 1 {
+            (8 * frame.h_max as usize, 8 * frame.v_max as usize)
+        } else {
+            // the luma has the full resolution
+            (8, 8)
+        };
+        let mcus_per_row = (frame.width as usize).div_ceil(mcu_width);
+        let mcu_rows = (frame.height as usize).div_ceil(mcu_height);
+        let (luma_h, luma_v) = blocks(0);
+        let stride = mcus_per_row * luma_h * size;
+        let row_height = luma_v * size;
+        let (width, height) = (
+            (frame.width as usize * size).div_ceil(8),
+            (frame.height as usize * size).div_ceil(8),
+        );
+        let mut luma = vec![0u8; stride * row_height];
+        let mut coefficients = [0i32; 64];
+        let mut predictions = [0i32; 3];
+        let mut bits = Bits::new(data);
+        let mut mcus = 0u32;
+
+        for mcu_row in 0..mcu_rows {
+            for mcu in 0..mcus_per_row {
+                if self.restart_interval > 0 && mcus > 0 && mcus % self.restart_interval as u32 == 0
+                {
+                    bits.restart();
+                    predictions = [0; 3];
+                }
+                mcus += 1;
+                for component in components {
+                    let (h, v) = blocks(component.index);
+                    let is_luma = component.index == 0;
+                    let quantization =
+                        &self.quantization[frame.components[component.index].quantization as usize];
+                    for block_y in 0..v {
+                        for block_x in 0..h {
+                            self.decode_block(
+                                &mut bits,
+                                component,
+                                &mut predictions[component.index],
+                                is_luma.then_some(quantization),
+                                &mut coefficients,
+                            )?;
+                            if is_luma {
+                                let x = (mcu * h + block_x) * size;
+                                let offset = block_y * size * stride + x;
+                                self.idct(&coefficients, &mut luma[offset..], stride);
+                            }
+                        }
+                    }
+                }
+            }
+            if bits.truncated {
+                return Err(Error::InvalidImage);
+            }
+            let rows = row_height.min(height - mcu_row * row_height);
+            for row in luma.chunks_exact(stride).take(rows) {
+                if output(&row[..width])? {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode the coefficients of a block. Without quantization table the
+    /// block is only skipped.
+    fn decode_block(
+        &self,
+        bits: &mut Bits<'_>,
+        component: &ScanComponent,
+        prediction: &mut i32,
+        quantization: Option<&[u16; 64]>,
+        coefficients: &mut [i32; 64],
+    ) -> Result<()> {
+        let dc_table = &self.huffman[component.dc_table];
+        let ac_table = &self.huffman[4 + component.ac_table];
+        let dequantize = |value: i32, q: u16| {
+            value
+                .saturating_mul(q as i32)
+                .clamp(-MAX_COEFFICIENT, MAX_COEFFICIENT)
+        };
+
+        let len = bits.decode(dc_table)?;
+        if len > 11 {
+            return Err(Error::InvalidImage);
+        }
+        *prediction = prediction.wrapping_add(bits.receive_extend(len as u32));
+        if let Some(quantization) = quantization {
+            coefficients.fill(0);
+            coefficients[0] = dequantize(*prediction, quantization[0]);
+        }
+        let mut k = 1;
+        while k < 64 {
+            let symbol = bits.decode(ac_table)?;
+            let (run, len) = ((symbol >> 4) as usize, (symbol & 0x0F) as u32);
+            if len == 0 {
+                if run != 15 {
+                    // end of block
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += run;
+            if k > 63 {
+                return Err(Error::InvalidImage);
+            }
+            let value = bits.receive_extend(len);
+            if let Some(quantization) = quantization {
+                // coefficients beyond the IDCT size are dropped
+                let natural = ZIGZAG[k] as usize;
+                if natural % 8 < self.size && natural / 8 < self.size {
+                    coefficients[natural] = dequantize(value, quantization[natural]);
+                }
+            }
+            k += 1;
+        }
+        Ok(())
+    }
+
+    /// Inverse DCT of the top left `size` x `size` coefficients into a block
+    /// of `size` x `size` pixels.
+    fn idct(&self, coefficients: &[i32; 64], output: &mut [u8], stride: usize) {
+        let size = self.size;
+        let mut rows = [0i32; 64];
+        for v in 0..size {
+            let row = &coefficients[v * 8..v * 8 + size];
+            if row.iter().all(|&c| c == 0) {
+                continue;
+            }
+            for x in 0..size {
+                let sum: i32 = (0..size).map(|u| self.factors[u * 8 + x] * row[u]).sum();
+                rows[v * 8 + x] = (sum + (1 << 11)) >> 12;
+            }
+        }
+        for y in 0..size {
+            for x in 0..size {
+                let sum: i32 = (0..size)
+                    .map(|v| self.factors[v * 8 + y] * rows[v * 8 + x])
+                    .sum();
+                let value = ((sum + (1 << 13)) >> 14) + 128;
+                output[y * stride + x] = value.clamp(0, 255) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! The fixtures are baseline JPEGs of a synthetic scene with sizes that
+    //! aren't multiples of the MCU size. The references are their luma as
+    //! decoded by zune-jpeg, averaged over 2x2, 4x4 and 8x8 pixels for the
+    //! scaled IDCT.
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testdata::{read, read_pgm};
+
+    /// Width and pixels of the luma decoded with an IDCT of `size`.
+    fn decode(data: &[u8], size: usize) -> Result<(usize, Vec<u8>)> {
+        let jpeg = Jpeg::new(data)?;
+        let mut width = 0;
+        let mut luma = Vec::new();
+        jpeg.decode_luma(size, |row| {
+            width = row.len();
+            luma.extend_from_slice(row);
+            Ok(false)
+        })?;
+        Ok((width, luma))
+    }
+
+    /// Compare the luma with the reference, `max` is the largest and `mean`
+    /// the average allowed difference of a pixel.
+    fn check(name: &str, size: usize, reference: &str, max: u8, mean: f32) {
+        let (width, luma) = decode(&read(name), size).unwrap();
+        let (ref_width, ref_height, expected) = read_pgm(reference);
+        assert_eq!((width, luma.len() / width), (ref_width, ref_height));
+        let errors: Vec<u8> = luma
+            .iter()
+            .zip(&expected)
+            .map(|(a, b)| a.abs_diff(*b))
+            .collect();
+        let worst = errors.iter().max().copied().unwrap_or(0);
+        let average = errors.iter().map(|&e| e as f32).sum::<f32>() / errors.len() as f32;
+        assert!(
+            worst <= max && average <= mean,
+            "{} at {}/8: difference up to {}, {} on average",
+            name,
+            size,
+            worst,
+            average
+        );
+    }
+
+    #[test]
+    fn grayscale() {
+        let jpeg = read("jpeg/gray.jpg");
+        let jpeg = Jpeg::new(&jpeg).unwrap();
+        assert_eq!((jpeg.width(), jpeg.height()), (37, 29));
+        check("jpeg/gray.jpg", 8, "jpeg/gray.pgm", 1, 0.25);
+    }
+
+    #[test]
+    fn subsampled_color() {
+        check("jpeg/ycbcr420.jpg", 8, "jpeg/ycbcr420.pgm", 1, 0.25);
+    }
+
+    #[test]
+    fn restart_intervals() {
+        check("jpeg/restart.jpg", 8, "jpeg/restart.pgm", 1, 0.25);
+    }
+
+    #[test]
+    fn scaled_idct() {
+        check("jpeg/scaled.jpg", 8, "jpeg/scaled.pgm", 1, 0.25);
+        check("jpeg/scaled.jpg", 4, "jpeg/scaled-2.pgm", 20, 2.0);
+        check("jpeg/scaled.jpg", 2, "jpeg/scaled-4.pgm", 20, 2.0);
+        check("jpeg/scaled.jpg", 1, "jpeg/scaled-8.pgm", 20, 2.0);
+    }
+
+    #[test]
+    fn truncated_data() {
+        let data = read("jpeg/restart.jpg");
+        assert_eq!(decode(&data[..data.len() / 2], 8), Err(Error::InvalidImage));
+        assert_eq!(decode(&data[..100], 8), Err(Error::InvalidImage));
+    }
+}
@@ -0,0 +1,609 @@
+use alloc::vec::Vec;
+
+#[cfg(feature = "embedded-graphics")]
+use embedded_graphics::text::{renderer::TextRenderer, Baseline};
+#[cfg(feature = "embedded-graphics")]
+use embedded_graphics_core::{pixelcolor::Gray4, prelude::Point};
+
+use crate::{display::Rectangle, Display, Error, Result, TextFont};
+
+const SOFT_HYPHEN: char = '\u{AD}';
+
+/// Measures and draws text for a [TextLayout].
+///
+/// Implemented by [FontStyle] for the fonts of this crate and, with the
+/// `embedded-graphics` feature, by every embedded-graphics text style, e.g.
+/// `MonoTextStyle` or `U8g2TextStyle`.
+pub trait LayoutStyle {
+    /// Width of a single line of text in pixels.
+    fn text_width(&self, text: &str) -> u32;
+
+    /// Distance between the tops of two lines in pixels.
+    fn line_height(&self) -> u32;
+
+    /// Draw a single line of text with the top left corner of its line at
+    /// `x`/`y`.
+    fn draw_text(&self, display: &mut Display<'_>, text: &str, x: i32, y: i32) -> Result<()>;
+}
+
+/// A [TextFont] like [Font4bpp](crate::Font4bpp) drawn in a color, see
+/// [LayoutStyle].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FontStyle<F> {
+    /// Font to measure and draw the text with.
+    pub font: F,
+    /// Color of the text, 0 (black) to 15 (white).
+    pub color: u8,
+}
+
+impl<F: TextFont> FontStyle<F> {
+    pub fn new(font: F, color: u8) -> Self {
+        FontStyle { font, color }
+    }
+}
+
+impl<F: TextFont> LayoutStyle for FontStyle<F> {
+    fn text_width(&self, text: &str) -> u32 {
+        self.font.text_width(text) as u32
+    }
+
+    fn line_height(&self) -> u32 {
+        self.font.line_height() as u32
+    }
+
+    fn draw_text(&self, display: &mut Display<'_>, text: &str, x: i32, y: i32) -> Result<()> {
+        let (Ok(x), Ok(baseline)) = (
+            u16::try_from(x),
+            u16::try_from(y + self.font.ascent() as i32),
+        ) else {
+            return Ok(());
+        };
+        display.draw_text(&self.font, text, x, baseline, self.color)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-graphics")]
+impl<S> LayoutStyle for S
+where
+    S: TextRenderer<Color = Gray4>,
+{
+    fn text_width(&self, text: &str) -> u32 {
+        let metrics = self.measure_string(text, Point::zero(), Baseline::Top);
+        metrics.next_position.x.max(0) as u32
+    }
+
+    fn line_height(&self) -> u32 {
+        TextRenderer::line_height(self)
+    }
+
+    fn draw_text(&self, display: &mut Display<'_>, text: &str, x: i32, y: i32) -> Result<()> {
+        self.draw_string(text, Point::new(x, y), Baseline::Top, display)?;
+        Ok(())
+    }
+}
+
+/// Horizontal alignment of the lines of a [TextLayout].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// Stretch the spaces so the lines fill the width, except the last line
+    /// of each paragraph.
+    Justify,
+}
+
+/// Options of a [TextLayout].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LayoutConfig {
+    pub align: Align,
+    /// Extra space between the lines of a paragraph in pixels.
+    pub line_spacing: u16,
+    /// Extra space between paragraphs in pixels. Dropped at the top of a
+    /// page.
+    pub paragraph_spacing: u16,
+    /// Indentation of the first line of each paragraph in pixels.
+    pub indent: u16,
+    /// Break words at soft hyphens (U+00AD), drawn as `-`, and after hyphens.
+    /// Words wider than a line are broken anywhere regardless.
+    pub hyphenation: bool,
+}
+
+/// A line of a [TextLayout].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Line {
+    /// Byte offset of the first character in the text.
+    pub start: usize,
+    /// Byte offset after the last character in the text.
+    pub end: usize,
+    /// Offset of the line from the left edge of the area, from the alignment
+    /// and indentation.
+    pub x: u16,
+    /// Offset of the top of the line from the top of the area.
+    pub y: u16,
+    /// Width of the text in pixels, without justification.
+    pub width: u16,
+    /// Space in pixels distributed over the gaps between the words to justify
+    /// the line.
+    pub extra: u16,
+    /// The line ends at a soft hyphen, drawn as `-`.
+    pub hyphenated: bool,
+}
+
+/// Text broken into lines and pages that fit an area of the screen.
+///
+/// Paragraphs are separated by line breaks, words by spaces or tabs. Lines
+/// are filled with as many words as fit, optionally breaking words at
+/// hyphens, and pages with as many lines as fit. The layout only keeps the
+/// positions of the lines, so any page can be drawn later on with
+/// [Display::draw_page].
+///
+/// ```rust ignore
+/// let style = FontStyle::new(Font4bpp::from_bytes(SERIF)?, 0);
+/// let config = LayoutConfig {
+///     align: Align::Justify,
+///     paragraph_spacing: 12,
+///     hyphenation: true,
+///     ..Default::default()
+/// };
+/// let layout = TextLayout::new(BOOK, &style, area, &config);
+/// display.draw_page(&layout, &style, page)?;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextLayout<'t> {
+    text: &'t str,
+    area: Rectangle,
+    lines: Vec<Line>,
+    /// Index of the first line of each page.
+    pages: Vec<usize>,
+}
+
+impl<'t> TextLayout<'t> {
+    /// Break `text` into lines and pages fitting `area`, measured with
+    /// `style`.
+    pub fn new(
+        text: &'t str,
+        style: &impl LayoutStyle,
+        area: Rectangle,
+        config: &LayoutConfig,
+    ) -> Self {
+        let mut builder = Builder {
+            style,
+            config,
+            area,
+            space: style.text_width(" "),
+            hyphen: style.text_width("-"),
+            line_height: style.line_height(),
+            layout: TextLayout {
+                text,
+                area,
+                lines: Vec::new(),
+                pages: Vec::new(),
+            },
+            y: 0,
+            paragraph_start: true,
+        };
+        let mut start = 0;
+        for paragraph in text.split_terminator('\n') {
+            builder.paragraph(start, paragraph);
+            start += paragraph.len() + 1;
+        }
+        builder.layout
+    }
+
+    /// The laid out text.
+    pub fn text(&self) -> &'t str {
+        self.text
+    }
+
+    /// The area the text is laid out in.
+    pub fn area(&self) -> Rectangle {
+        self.area
+    }
+
+    /// All lines of the text.
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Number of pages.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The lines of a page, if it exists.
+    pub fn page(&self, page: usize) -> Option<&[Line]> {
+        let start = *self.pages.get(page)?;
+        let end = self
+            .pages
+            .get(page + 1)
+            .copied()
+            .unwrap_or(self.lines.len());
+        Some(&self.lines[start..end])
+    }
+
+    /// The page showing the character at a byte offset of the text, e.g. to
+    /// keep the reading position when the layout changes.
+    pub fn page_of(&self, offset: usize) -> Option<usize> {
+        let line = self.lines.partition_point(|line| line.start <= offset);
+        let line = line.checked_sub(1)?;
+        Some(self.pages.partition_point(|&start| start <= line) - 1)
+    }
+
+    /// The text of a line, including soft hyphens.
+    pub fn line_text(&self, line: &Line) -> &'t str {
+        &self.text[line.start..line.end]
+    }
+}
+
+/// State of [TextLayout::new].
+struct Builder<'s, 't, S> {
+    style: &'s S,
+    config: &'s LayoutConfig,
+    area: Rectangle,
+    space: u32,
+    hyphen: u32,
+    line_height: u32,
+    layout: TextLayout<'t>,
+    /// Top of the next line on the current page.
+    y: u32,
+    /// The next line starts a paragraph.
+    paragraph_start: bool,
+}
+
+/// Words of the current line.
+#[derive(Clone, Copy)]
+struct Current {
+    start: usize,
+    end: usize,
+    width: u32,
+    words: u32,
+}
+
+impl<S: LayoutStyle> Builder<'_, '_, S> {
+    fn paragraph(&mut self, start: usize, paragraph: &str) {
+        let paragraph = paragraph.strip_suffix('\r').unwrap_or(paragraph);
+        self.paragraph_start = true;
+        let mut current = Current {
+            start,
+            end: start,
+            width: 0,
+            words: 0,
+        };
+        for (offset, word) in words(paragraph) {
+            let (mut word_start, mut word) = (start + offset, word);
+            loop {
+                let available = self.available();
+                let width = word_width(self.style, word);
+                let gap = if current.words > 0 { self.space } else { 0 };
+                if current.width + gap + width <= available {
+                    current = Current {
+                        start: if current.words > 0 {
+                            current.start
+                        } else {
+                            word_start
+                        },
+                        end: word_start + word.len(),
+                        width: current.width + gap + width,
+                        words: current.words + 1,
+                    };
+                    break;
+                }
+                let room = available.saturating_sub(current.width + gap);
+                let force = current.words == 0;
+                if let Some((end, next, hyphenated, width)) = self.split(word, room, force) {
+                    if current.words == 0 {
+                        current.start = word_start;
+                    }
+                    current.end = word_start + end;
+                    current.width += gap + width;
+                    current.words += 1;
+                    self.push(current, hyphenated, false);
+                    word_start += next;
+                    word = &word[next..];
+                } else {
+                    self.push(current, false, false);
+                }
+                current = Current {
+                    start: word_start,
+                    end: word_start,
+                    width: 0,
+                    words: 0,
+                };
+                if word.is_empty() {
+                    break;
+                }
+            }
+        }
+        // empty paragraphs take up a line
+        if current.words > 0 || self.paragraph_start {
+            self.push(current, false, true);
+        }
+    }
+
+    /// Width available for the next line.
+    fn available(&self) -> u32 {
+        let indent = if self.paragraph_start {
+            self.config.indent as u32
+        } else {
+            0
+        };
+        (self.area.width as u32).saturating_sub(indent)
+    }
+
+    /// Longest prefix of `word` that fits into `room`, broken at a hyphen or,
+    /// with `force`, anywhere. Returns the end of the prefix, the start of the
+    /// rest, whether to draw a hyphen and the width.
+    fn split(&self, word: &str, room: u32, force: bool) -> Option<(usize, usize, bool, u32)> {
+        if self.config.hyphenation {
+            let breaks = word.char_indices().filter_map(|(i, c)| match c {
+                SOFT_HYPHEN if i > 0 && i + c.len_utf8() < word.len() => {
+                    Some((i, i + c.len_utf8(), true))
+                }
+                '-' if i > 0 && i + 1 < word.len() => Some((i + 1, i + 1, false)),
+                _ => None,
+            });
+            let mut best = None;
+            for (end, next, hyphenated) in breaks {
+                let width =
+                    word_width(self.style, &word[..end]) + if hyphenated { self.hyphen } else { 0 };
+                if width > room {
+                    break;
+                }
+                best = Some((end, next, hyphenated, width));
+            }
+            if best.is_some() {
+                return best;
+            }
+        }
+        if !force {
+            return None;
+        }
+        // at least one character, so every line makes progress
+        let mut best = None;
+        for (i, _) in word.char_indices().skip(1) {
+            let width = word_width(self.style, &word[..i]);
+            if width > room && best.is_some() {
+                break;
+            }
+            best = Some((i, i, false, width));
+        }
+        best.or(Some((
+            word.len(),
+            word.len(),
+            false,
+            word_width(self.style, word),
+        )))
+    }
+
+    /// Place a line on the current page or start a new one.
+    fn push(&mut self, current: Current, hyphenated: bool, last: bool) {
+        let mut spacing = self.config.line_spacing as u32;
+        if self.paragraph_start {
+            spacing += self.config.paragraph_spacing as u32;
+        }
+        // the first line of a page is placed even if it doesn't fit
+        let mut y = self.y + spacing;
+        if self.layout.lines.is_empty() || y + self.line_height > self.area.height as u32 {
+            self.layout.pages.push(self.layout.lines.len());
+            y = 0;
+        }
+
+        let indent = if self.paragraph_start {
+            self.config.indent as u32
+        } else {
+            0
+        };
+        let free = self.available().saturating_sub(current.width);
+        let (x, extra) = match self.config.align {
+            Align::Left => (indent, 0),
+            Align::Center => (indent + free / 2, 0),
+            Align::Right => (indent + free, 0),
+            Align::Justify if !last && current.words > 1 => (indent, free),
+            Align::Justify => (indent, 0),
+        };
+        self.layout.lines.push(Line {
+            start: current.start,
+            end: current.end,
+            x: x as u16,
+            y: y as u16,
+            width: current.width.min(u16::MAX as u32) as u16,
+            extra: extra as u16,
+            hyphenated,
+        });
+        self.y = y + self.line_height;
+        self.paragraph_start = false;
+    }
+}
+
+/// Words of a paragraph with their byte offsets.
+fn words(paragraph: &str) -> impl Iterator<Item = (usize, &str)> {
+    paragraph
+        .split([' ', '\t', '\r'])
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - paragraph.as_ptr() as usize, word))
+}
+
+/// Width of a word without its soft hyphens.
+fn word_width(style: &impl LayoutStyle, word: &str) -> u32 {
+    word.split(SOFT_HYPHEN)
+        .map(|fragment| style.text_width(fragment))
+        .sum()
+}
+
+impl<'a> Display<'a> {
+    /// Draw a page of a [TextLayout] with the style it has been laid out
+    /// with.
+    ///
+    /// Returns [Error::OutOfBounds] if the layout has no such page.
+    pub fn draw_page(
+        &mut self,
+        layout: &TextLayout<'_>,
+        style: &impl LayoutStyle,
+        page: usize,
+    ) -> Result<()> {
+        let lines = layout.page(page).ok_or(Error::OutOfBounds)?;
+        let space = style.text_width(" ") as i32;
+        for line in lines {
+            let text = layout.line_text(line);
+            let gaps = words(text).count().saturating_sub(1) as i32;
+            let mut x = layout.area.x as i32 + line.x as i32;
+            let y = layout.area.y as i32 + line.y as i32;
+            for (i, (_, word)) in words(text).enumerate() {
+                if i > 0 {
+                    // spread the extra space evenly, without rounding errors
+                    let i = i as i32;
+                    x += space + line.extra as i32 * i / gaps - line.extra as i32 * (i - 1) / gaps;
+                }
+                for fragment in word.split(SOFT_HYPHEN) {
+                    style.draw_text(self, fragment, x, y)?;
+                    x += style.text_width(fragment) as i32;
+                }
+            }
+            if line.hyphenated {
+                style.draw_text(self, "-", x, y)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Every character is 10 pixels wide and lines are 20 pixels high.
+    struct Fixed;
+
+    impl LayoutStyle for Fixed {
+        fn text_width(&self, text: &str) -> u32 {
+            text.chars().count() as u32 * 10
+        }
+
+        fn line_height(&self) -> u32 {
+            20
+        }
+
+        fn draw_text(&self, _: &mut Display<'_>, _: &str, _: i32, _: i32) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn lay_out<'t>(
+        text: &'t str,
+        width: u16,
+        height: u16,
+        config: &LayoutConfig,
+    ) -> TextLayout<'t> {
+        let area = Rectangle {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        TextLayout::new(text, &Fixed, area, config)
+    }
+
+    /// Text and top of the lines of a page.
+    fn page<'t>(layout: &TextLayout<'t>, page: usize) -> Vec<(&'t str, u16)> {
+        layout
+            .page(page)
+            .unwrap()
+            .iter()
+            .map(|line| (layout.line_text(line), line.y))
+            .collect()
+    }
+
+    #[test]
+    fn pages() {
+        let text = "aaaa bbbb cccc dddd eeee\nff";
+        let layout = lay_out(text, 100, 50, &LayoutConfig::default());
+        assert_eq!(layout.page_count(), 2);
+        assert_eq!(page(&layout, 0), [("aaaa bbbb", 0), ("cccc dddd", 20)]);
+        assert_eq!(page(&layout, 1), [("eeee", 0), ("ff", 20)]);
+        assert_eq!(layout.page(2), None);
+        assert_eq!(layout.lines()[0].width, 90);
+    }
+
+    #[test]
+    fn paragraph_spacing() {
+        let config = LayoutConfig {
+            paragraph_spacing: 5,
+            ..Default::default()
+        };
+        let layout = lay_out("a\nb\nc\nd", 100, 60, &config);
+        assert_eq!(page(&layout, 0), [("a", 0), ("b", 25)]);
+        assert_eq!(page(&layout, 1), [("c", 0), ("d", 25)]);
+    }
+
+    #[test]
+    fn soft_hyphens() {
+        let config = LayoutConfig {
+            hyphenation: true,
+            ..Default::default()
+        };
+        let layout = lay_out("aaaaaa\u{AD}bbbbbb", 100, 100, &config);
+        assert_eq!(page(&layout, 0), [("aaaaaa", 0), ("bbbbbb", 20)]);
+        let line = layout.lines()[0];
+        assert!(line.hyphenated);
+        assert_eq!(line.width, 70);
+    }
+
+    #[test]
+    fn hard_hyphens() {
+        let config = LayoutConfig {
+            hyphenation: true,
+            ..Default::default()
+        };
+        let layout = lay_out("xx aaaa-bbbbbbb", 100, 100, &config);
+        assert_eq!(page(&layout, 0), [("xx aaaa-", 0), ("bbbbbbb", 20)]);
+        assert!(!layout.lines()[0].hyphenated);
+        assert_eq!(layout.lines()[0].width, 80);
+
+        // without hyphenation the word only fits a line of its own, where it
+        // is broken anywhere
+        let layout = lay_out("xx aaaa-bbbbbbb", 100, 100, &LayoutConfig::default());
+        let lines: Vec<_> = page(&layout, 0).into_iter().map(|(text, _)| text).collect();
+        assert_eq!(lines, ["xx", "aaaa-bbbbb", "bb"]);
+    }
+
+    #[test]
+    fn long_words() {
+        let layout = lay_out(
+            "abcdefghijklmnopqrstuvwxy",
+            100,
+            100,
+            &LayoutConfig::default(),
+        );
+        let lines: Vec<_> = page(&layout, 0).into_iter().map(|(text, _)| text).collect();
+        assert_eq!(lines, ["abcdefghij", "klmnopqrst", "uvwxy"]);
+
+        // at least one character per line
+        let layout = lay_out("abc", 5, 100, &LayoutConfig::default());
+        let lines: Vec<_> = page(&layout, 0).into_iter().map(|(text, _)| text).collect();
+        assert_eq!(lines, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn page_of() {
+        let text = "aaaa bbbb cccc dddd eeee\nff";
+        let layout = lay_out(text, 100, 50, &LayoutConfig::default());
+        assert_eq!(layout.page_of(0), Some(0));
+        assert_eq!(layout.page_of(4), Some(0));
+        assert_eq!(layout.page_of(text.find("dddd").unwrap()), Some(0));
+        assert_eq!(layout.page_of(text.find("eeee").unwrap()), Some(1));
+        assert_eq!(layout.page_of(text.len()), Some(1));
+
+        let empty = lay_out("", 100, 50, &LayoutConfig::default());
+        assert_eq!(empty.page_count(), 0);
+        assert_eq!(empty.page_of(0), None);
+    }
+}
@@ -63,7 +63,7 @@
 //!     // do nothing
 //!     loop {}
 //! }
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 extern crate alloc;
 
@@ -73,31 +73,199 @@ pub mod display;
 pub mod graphics;
 
 mod battery;
+mod decode;
+mod dither;
+mod drain;
 mod ed047tc1;
+#[cfg(feature = "flash-font")]
+mod flash_font;
+mod font;
+mod gesture;
+mod guard;
+mod hal;
+mod image;
+#[cfg(feature = "jpeg")]
+mod jpeg;
+mod layout;
+#[cfg(feature = "png")]
+mod png;
+mod repair;
 mod rmt;
+mod rtc;
+mod shapes;
+#[cfg(test)]
+mod testdata;
+mod touch;
+
+/// Driver operation during which an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Operation {
+    /// Setting up the peripherals in [Display::new].
+    Init,
+    /// Starting a frame.
+    FrameStart,
+    /// Writing or skipping the given row of a frame.
+    Row(u16),
+    /// Ending a frame.
+    FrameEnd,
+}
+
+impl core::fmt::Display for Operation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Init => write!(f, "init"),
+            Self::FrameStart => write!(f, "frame start"),
+            Self::Row(row) => write!(f, "row {}", row),
+            Self::FrameEnd => write!(f, "frame end"),
+        }
+    }
+}
 
 /// Errors
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error {
     /// Pass-through
-    Rmt(esp_hal::rmt::Error),
+    Rmt(Operation, hal::RmtError),
     /// Pass-through
-    Dma(esp_hal::dma::DmaError),
+    Dma(Operation, hal::DmaError),
     /// Pass-through
-    DmaBuffer(esp_hal::dma::DmaBufError),
+    DmaBuffer(hal::DmaBufError),
     /// Provided pixel coordinates exceed the display boundary.
     OutOfBounds,
     /// Provided color exceeds the allowed range of 0x0 - 0x0F
     InvalidColor,
-    Unknown,
+    /// The LCD bus or its DMA buffer is still held by a transfer.
+    BusBusy(Operation),
+    /// The RMT channel was consumed by a failed transmission. It is
+    /// re-initialized with the next pulse.
+    ChannelLost(Operation, hal::RmtError),
+    /// The ADC conversion failed or didn't finish in time.
+    Adc,
+    /// The battery calibration is corrupted or out of range.
+    InvalidCalibration,
+    /// The refresh has been refused by the
+    /// [LowBatteryGuard](crate::LowBatteryGuard) at the given battery voltage.
+    LowBattery(f32),
+    /// Pass-through of an I2C bus error.
+    I2c(embedded_hal::i2c::ErrorKind),
+    /// The touch controller didn't respond on any of its addresses.
+    TouchNotFound,
+    /// The date, time or alarm is out of range, or the RTC returned garbage.
+    InvalidDateTime,
+    /// The image data is malformed or truncated.
+    InvalidImage,
+    /// The image uses a format feature the decoder doesn't support.
+    UnsupportedImage,
+    /// The font data is malformed or truncated.
+    InvalidFont,
+    /// Reading from the flash or another storage failed.
+    Storage,
+}
+
+impl Error {
+    /// The operation that failed, if the error originates from the hardware.
+    pub fn operation(&self) -> Option<Operation> {
+        match self {
+            Self::Rmt(op, _) | Self::Dma(op, _) | Self::BusBusy(op) | Self::ChannelLost(op, _) => {
+                Some(*op)
+            }
+            Self::DmaBuffer(_) => Some(Operation::Init),
+            Self::OutOfBounds
+            | Self::InvalidColor
+            | Self::Adc
+            | Self::InvalidCalibration
+            | Self::LowBattery(_)
+            | Self::I2c(_)
+            | Self::TouchNotFound
+            | Self::InvalidDateTime
+            | Self::InvalidImage
+            | Self::UnsupportedImage
+            | Self::InvalidFont
+            | Self::Storage => None,
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Rmt(op, err) => write!(f, "rmt error during {}: {:?}", op, err),
+            Self::Dma(op, err) => write!(f, "dma error during {}: {:?}", op, err),
+            Self::DmaBuffer(err) => write!(f, "failed to set up the dma buffer: {:?}", err),
+            Self::OutOfBounds => write!(f, "pixel coordinates out of bounds"),
+            Self::InvalidColor => write!(f, "color exceeds the range 0x0 - 0xF"),
+            Self::BusBusy(op) => write!(f, "lcd bus busy during {}", op),
+            Self::ChannelLost(op, err) => {
+                write!(f, "rmt channel lost during {}: {:?}", op, err)
+            }
+            Self::Adc => write!(f, "battery voltage conversion failed"),
+            Self::InvalidCalibration => write!(f, "invalid battery calibration"),
+            Self::LowBattery(voltage) => {
+                write!(f, "refresh refused, battery too low ({:.2}V)", voltage)
+            }
+            Self::I2c(kind) => write!(f, "i2c error: {:?}", kind),
+            Self::TouchNotFound => write!(f, "touch controller not found"),
+            Self::InvalidDateTime => write!(f, "invalid date or time"),
+            Self::InvalidImage => write!(f, "invalid image data"),
+            Self::UnsupportedImage => write!(f, "unsupported image format"),
+            Self::InvalidFont => write!(f, "invalid font data"),
+            Self::Storage => write!(f, "storage read failed"),
+        }
+    }
 }
 
 type Result<T> = core::result::Result<T, Error>;
 
+#[cfg(feature = "flash-font")]
+pub use crate::flash_font::{FlashFont, Partition, RamStorage};
+#[cfg(feature = "embedded-graphics")]
+pub use crate::graphics::ColorView;
+#[cfg(feature = "jpeg")]
+pub use crate::jpeg::Jpeg;
+#[cfg(feature = "png")]
+pub use crate::png::Png;
+#[cfg(target_arch = "xtensa")]
+pub use crate::{battery::Battery, ed047tc1::PinConfig};
 pub use crate::{
-    battery::Battery,
-    display::{Display, DrawMode},
-    ed047tc1::PinConfig,
+    battery::{
+        Calibration,
+        ChargeEstimator,
+        ChargeState,
+        ChargeThresholds,
+        DischargeCurve,
+        Oversampler,
+    },
+    decode::DecodeOptions,
+    display::{
+        Display,
+        DisplayState,
+        DisplayStats,
+        DrawMode,
+        RefreshCounters,
+        RefreshPolicy,
+        Rotation,
+    },
+    dither::{Dither, DitherMethod, PanelLevels},
+    drain::{DrainEstimate, DrainEstimator, DrainLog, DrainSample},
+    font::{Font4bpp, Glyph, TextFont},
+    gesture::{Gesture, GestureConfig, GestureRecognizer, SwipeDirection},
+    guard::{LowBatteryGuard, RefreshDecision},
+    image::Image4bpp,
+    layout::{Align, FontStyle, LayoutConfig, LayoutStyle, Line, TextLayout},
+    repair::{RepairConfig, RepairOutcome, RepairPhase, RepairProcedure, RepairProgress},
+    rtc::{Alarm, DateTime, Rtc, TimerFrequency},
+    shapes::{LineCap, PointF, ShapeStyle},
+    touch::{
+        InterruptTrigger,
+        Touch,
+        TouchInterrupt,
+        TouchPoint,
+        TouchTransform,
+        Touches,
+        MAX_POINTS,
+    },
 };
 
 /// Convenience macro to build the pin config struct.
@@ -0,0 +1,554 @@
+use alloc::{boxed::Box, vec, vec::Vec};
+
+use miniz_oxide::inflate::{
+    core::{decompress, inflate_flags, DecompressorOxide},
+    TINFLStatus,
+};
+
+use crate::{decode::RowWriter, dither::luma_rgb888, DecodeOptions, Display, Error, Result};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Size of the signature and the IHDR chunk.
+const HEADER_SIZE: usize = 33;
+/// Size of the deflate window, the ring buffer the rows are inflated into.
+const WINDOW_SIZE: usize = 32 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum ColorType {
+    Gray,
+    Rgb,
+    Indexed,
+    GrayAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    fn channels(&self) -> usize {
+        match self {
+            Self::Gray | Self::Indexed => 1,
+            Self::GrayAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+}
+
+/// A PNG image, decoded while drawing it with [Display::draw_png].
+///
+/// The image is decoded row by row straight into the framebuffer. Besides
+/// the input it only needs the 32 KiB deflate window, about 11 KiB of
+/// decompressor state and a few rows, so even a full screen RGB image is
+/// drawn with less than 64 KiB.
+///
+/// All color types and bit depths are supported. 16 bit samples are reduced
+/// to 8 bit and transparent pixels are blended onto white. Interlaced images
+/// are not supported.
+///
+/// ```rust ignore
+/// let png = Png::new(include_bytes!("photo.png"))?;
+/// let options = DecodeOptions {
+///     width: Some(Display::WIDTH),
+///     ..Default::default()
+/// };
+/// display.draw_png(&png, 0, 0, &options)?;
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Png<'a> {
+    width: u16,
+    height: u16,
+    bit_depth: u8,
+    color_type: ColorType,
+    data: &'a [u8],
+}
+
+impl<'a> Png<'a> {
+    /// Parse the header of a PNG file.
+    ///
+    /// Returns [Error::InvalidImage] if the data isn't a PNG file and
+    /// [Error::UnsupportedImage] for interlaced images and images larger than
+    /// 65535 pixels in either direction.
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        if data.len() < HEADER_SIZE
+            || data[0..8] != SIGNATURE
+            || be32(&data[8..12]) != 13
+            || data[12..16] != *b"IHDR"
+        {
+            return Err(Error::InvalidImage);
+        }
+        let (width, height) = (be32(&data[16..20]), be32(&data[20..24]));
+        let bit_depth = data[24];
+        let color_type = match data[25] {
+            0 => ColorType::Gray,
+            2 => ColorType::Rgb,
+            3 => ColorType::Indexed,
+            4 => ColorType::GrayAlpha,
+            6 => ColorType::Rgba,
+            _ => return Err(Error::InvalidImage),
+        };
+        let valid_depth = match color_type {
+            ColorType::Gray => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            ColorType::Indexed => matches!(bit_depth, 1 | 2 | 4 | 8),
+            _ => matches!(bit_depth, 8 | 16),
+        };
+        // compression and filter method 0, no or Adam7 interlacing
+        if width == 0 || height == 0 || !valid_depth || data[26] != 0 || data[27] != 0 {
+            return Err(Error::InvalidImage);
+        }
+        match data[28] {
+            0 => {}
+            1 => return Err(Error::UnsupportedImage),
+            _ => return Err(Error::InvalidImage),
+        }
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(Error::UnsupportedImage);
+        }
+        Ok(Png {
+            width: width as u16,
+            height: height as u16,
+            bit_depth,
+            color_type,
+            data,
+        })
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Bytes per row, without the filter type.
+    fn stride(&self) -> usize {
+        (self.width as usize * self.color_type.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// Decode the lightness and pass it to `row` one pixel row at a time,
+    /// until `row` returns `true`.
+    fn decode_luma(&self, mut row: impl FnMut(&[u8]) -> Result<bool>) -> Result<()> {
+        let mut decoder = Decoder::new(self);
+        let mut offset = SIGNATURE.len();
+        loop {
+            let header = self
+                .data
+                .get(offset..offset + 8)
+                .ok_or(Error::InvalidImage)?;
+            let start = offset + 8;
+            let end = start
+                .checked_add(be32(&header[0..4]) as usize)
+                .ok_or(Error::InvalidImage)?;
+            let body = self.data.get(start..end).ok_or(Error::InvalidImage)?;
+            // skip the CRC
+            offset = end + 4;
+            match &header[4..8] {
+                b"PLTE" => decoder.set_palette(body),
+                b"tRNS" => decoder.set_transparency(body),
+                b"IDAT" => {
+                    if decoder.inflate(body, &mut row)? {
+                        return Ok(());
+                    }
+                }
+                b"IEND" => return Err(Error::InvalidImage),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'a> Display<'a> {
+    /// Decode a PNG image into the framebuffer with its top left corner at
+    /// `x`/`y`, see [Png]. The image is clipped to the screen.
+    ///
+    /// Returns [Error::InvalidImage] if the image data is corrupted. Rows
+    /// decoded up to that point stay in the framebuffer.
+    pub fn draw_png(
+        &mut self,
+        png: &Png<'_>,
+        x: u16,
+        y: u16,
+        options: &DecodeOptions,
+    ) -> Result<()> {
+        let source = (png.width, png.height);
+        let size = options.output_size(png.width, png.height);
+        let mut writer = RowWriter::new(options, x, y, source, size);
+        png.decode_luma(|row| {
+            writer.push_row(self, row)?;
+            Ok(writer.is_done())
+        })
+    }
+}
+
+/// State of the streaming decoder.
+struct Decoder {
+    width: usize,
+    height: u16,
+    bit_depth: u8,
+    color_type: ColorType,
+    /// Bytes per complete pixel, at least 1, used by the filters.
+    bpp: usize,
+    inflater: Box<DecompressorOxide>,
+    window: Vec<u8>,
+    position: usize,
+    /// Filter type followed by the current row.
+    row: Vec<u8>,
+    /// Bytes of `row` inflated so far.
+    filled: usize,
+    /// Previous unfiltered row, laid out like `row`.
+    previous: Vec<u8>,
+    luma: Vec<u8>,
+    rows: u16,
+    palette: Option<[u8; 256]>,
+    alpha: [u8; 256],
+}
+
+impl Decoder {
+    fn new(png: &Png<'_>) -> Self {
+        let stride = png.stride();
+        Decoder {
+            width: png.width as usize,
+            height: png.height,
+            bit_depth: png.bit_depth,
+            color_type: png.color_type,
+            bpp: (png.color_type.channels() * png.bit_depth as usize / 8).max(1),
+            inflater: Box::default(),
+            window: vec![0; WINDOW_SIZE],
+            position: 0,
+            row: vec![0; stride + 1],
+            filled: 0,
+            previous: vec![0; stride + 1],
+            luma: vec![0; png.width as usize],
+            rows: 0,
+            palette: None,
+            alpha: [u8::MAX; 256],
+        }
+    }
+
+    fn set_palette(&mut self, body: &[u8]) {
+        let mut palette = [0; 256];
+        for (luma, rgb) in palette.iter_mut().zip(body.chunks_exact(3)) {
+            *luma = luma_rgb888(rgb[0], rgb[1], rgb[2]);
+        }
+        self.palette = Some(palette);
+    }
+
+    fn set_transparency(&mut self, body: &[u8]) {
+        // only the alpha of palette entries, color keys are ignored
+        if self.color_type == ColorType::Indexed {
+            for (alpha, &value) in self.alpha.iter_mut().zip(body) {
+                *alpha = value;
+            }
+        }
+    }
+
+    /// Inflate the data of an IDAT chunk and pass on the lightness of all
+    /// completed rows. Returns whether the image is complete or `output`
+    /// doesn't need more rows.
+    fn inflate(
+        &mut self,
+        mut input: &[u8],
+        output: &mut impl FnMut(&[u8]) -> Result<bool>,
+    ) -> Result<bool> {
+        if self.color_type == ColorType::Indexed && self.palette.is_none() {
+            return Err(Error::InvalidImage);
+        }
+        let flags =
+            inflate_flags::TINFL_FLAG_PARSE_ZLIB_HEADER | inflate_flags::TINFL_FLAG_HAS_MORE_INPUT;
+        loop {
+            let (status, consumed, written) = decompress(
+                &mut self.inflater,
+                input,
+                &mut self.window,
+                self.position,
+                flags,
+            );
+            input = &input[consumed..];
+            let mut start = self.position;
+            let end = start + written;
+            self.position = end % WINDOW_SIZE;
+            while start < end {
+                let len = (self.row.len() - self.filled).min(end - start);
+                self.row[self.filled..self.filled + len]
+                    .copy_from_slice(&self.window[start..start + len]);
+                self.filled += len;
+                start += len;
+                if self.filled == self.row.len() {
+                    self.filled = 0;
+                    self.unfilter()?;
+                    self.convert();
+                    let done = output(&self.luma)?;
+                    core::mem::swap(&mut self.row, &mut self.previous);
+                    self.rows += 1;
+                    if self.rows == self.height || done {
+                        return Ok(true);
+                    }
+                }
+            }
+            match status {
+                TINFLStatus::HasMoreOutput => {}
+                TINFLStatus::NeedsMoreInput => return Ok(false),
+                // the stream ended before all rows were complete
+                _ => return Err(Error::InvalidImage),
+            }
+        }
+    }
+
+    fn unfilter(&mut self) -> Result<()> {
+        let bpp = self.bpp;
+        let (filter, row) = self.row.split_at_mut(1);
+        let previous = &self.previous[1..];
+        match filter[0] {
+            0 => {}
+            1 => {
+                for i in bpp..row.len() {
+                    row[i] = row[i].wrapping_add(row[i - bpp]);
+                }
+            }
+            2 => {
+                for (value, &up) in row.iter_mut().zip(previous) {
+                    *value = value.wrapping_add(up);
+                }
+            }
+            3 => {
+                for i in 0..row.len() {
+                    let left = if i >= bpp { row[i - bpp] } else { 0 };
+                    let average = (left as u16 + previous[i] as u16) / 2;
+                    row[i] = row[i].wrapping_add(average as u8);
+                }
+            }
+            4 => {
+                for i in 0..row.len() {
+                    let (left, up_left) = if i >= bpp {
+                        (row[i - bpp], previous[i - bpp])
+                    } else {
+                        (0, 0)
+                    };
+                    row[i] = row[i].wrapping_add(paeth(left, previous[i], up_left));
+                }
+            }
+            _ => return Err(Error::InvalidImage),
+        }
+        Ok(())
+    }
+
+    /// Convert the unfiltered row into lightness.
+    fn convert(&mut self) {
+        let row = &self.row[1..];
+        let depth = self.bit_depth as usize;
+        // byte of the given sample, the high byte for 16 bit samples
+        let bytes = depth.div_ceil(8);
+        let channels = self.color_type.channels();
+        let byte = |x: usize, channel: usize| row[(x * channels + channel) * bytes];
+        // samples of less than 8 bit are packed starting at the high bits
+        let small = |x: usize| {
+            let bit = x * depth;
+            (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8
+        };
+        for (x, luma) in self.luma.iter_mut().enumerate().take(self.width) {
+            *luma = match self.color_type {
+                ColorType::Gray if depth < 8 => (small(x) as u16 * 255 / ((1 << depth) - 1)) as u8,
+                ColorType::Gray => byte(x, 0),
+                ColorType::Indexed => {
+                    let index = if depth < 8 { small(x) } else { row[x] } as usize;
+                    let palette = self.palette.as_ref().map_or(0, |palette| palette[index]);
+                    blend(palette, self.alpha[index])
+                }
+                ColorType::GrayAlpha => blend(byte(x, 0), byte(x, 1)),
+                ColorType::Rgb => luma_rgb888(byte(x, 0), byte(x, 1), byte(x, 2)),
+                ColorType::Rgba => {
+                    blend(luma_rgb888(byte(x, 0), byte(x, 1), byte(x, 2)), byte(x, 3))
+                }
+            };
+        }
+    }
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Blend a pixel with the given alpha onto white.
+fn blend(luma: u8, alpha: u8) -> u8 {
+    let (luma, alpha) = (luma as u32, alpha as u32);
+    ((luma * alpha + 255 * (255 - alpha) + 127) / 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    //! The fixtures were written by a minimal encoder on top of zlib, with
+    //! the rows cycling through all five filter types. The references are the
+    //! lightness of their source pixels, blended onto white.
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testdata::{read, read_pgm};
+
+    fn decode(data: &[u8]) -> Result<(usize, Vec<u8>)> {
+        let png = Png::new(data)?;
+        let mut luma = Vec::new();
+        png.decode_luma(|row| {
+            luma.extend_from_slice(row);
+            Ok(false)
+        })?;
+        Ok((png.width() as usize, luma))
+    }
+
+    fn check(name: &str) {
+        let (width, luma) = decode(&read(&std::format!("png/{}.png", name))).unwrap();
+        let (ref_width, ref_height, expected) = read_pgm(&std::format!("png/{}.pgm", name));
+        assert_eq!(
+            (width, luma.len() / width),
+            (ref_width, ref_height),
+            "{}",
+            name
+        );
+        if let Some(i) = expected.iter().zip(&luma).position(|(a, b)| a != b) {
+            panic!(
+                "{}: pixel {}x{} is {}, expected {}",
+                name,
+                i % width,
+                i / width,
+                luma[i],
+                expected[i]
+            );
+        }
+    }
+
+    /// Offset of the end of the last IDAT chunk.
+    fn image_data_end(data: &[u8]) -> usize {
+        let mut offset = SIGNATURE.len();
+        let mut end = 0;
+        while offset < data.len() {
+            let next = offset + 12 + be32(&data[offset..]) as usize;
+            if &data[offset + 4..offset + 8] == b"IDAT" {
+                end = next;
+            }
+            offset = next;
+        }
+        end
+    }
+
+    #[test]
+    fn filters_and_split_image_data() {
+        let data = read("png/rgb-filters.png");
+        let chunks = data.windows(4).filter(|&kind| kind == b"IDAT").count();
+        assert!(chunks > 10, "{} IDAT chunks", chunks);
+        check("rgb-filters");
+    }
+
+    #[test]
+    fn small_gray_depths() {
+        check("gray1");
+        check("gray2");
+        check("gray4");
+    }
+
+    #[test]
+    fn sixteen_bit_depths() {
+        check("gray16");
+        check("rgba16");
+    }
+
+    #[test]
+    fn palette_with_transparency() {
+        check("palette");
+    }
+
+    #[test]
+    fn header() {
+        let data = read("png/gray4.png");
+        let png = Png::new(&data).unwrap();
+        assert_eq!((png.width(), png.height()), (13, 10));
+
+        let mut interlaced = data.clone();
+        interlaced[28] = 1;
+        assert_eq!(Png::new(&interlaced), Err(Error::UnsupportedImage));
+        let mut depth = data.clone();
+        depth[24] = 3;
+        assert_eq!(Png::new(&depth), Err(Error::InvalidImage));
+        let mut signature = data.clone();
+        signature[1] = b'p';
+        assert_eq!(Png::new(&signature), Err(Error::InvalidImage));
+        for len in 0..HEADER_SIZE {
+            assert_eq!(Png::new(&data[..len]), Err(Error::InvalidImage));
+        }
+    }
+
+    #[test]
+    fn truncated_data() {
+        for name in ["rgb-filters", "palette", "gray16"] {
+            let data = read(&std::format!("png/{}.png", name));
+            let complete = decode(&data).unwrap();
+            let end = image_data_end(&data);
+            for len in HEADER_SIZE..end {
+                let decoded = decode(&data[..len]);
+                // only the CRC of the last chunk isn't needed
+                if len >= end - 4 {
+                    assert_eq!(decoded.as_ref(), Ok(&complete));
+                } else {
+                    assert_eq!(
+                        decoded,
+                        Err(Error::InvalidImage),
+                        "{} truncated to {} bytes",
+                        name,
+                        len
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_data() {
+        let data = read("png/rgb-filters.png");
+        let first = data.windows(4).position(|kind| kind == b"IDAT").unwrap() + 4;
+        // zlib header with an unknown compression method
+        let mut method = data.clone();
+        method[first] = 0x7A;
+        assert_eq!(decode(&method), Err(Error::InvalidImage));
+        // deflate block of the reserved type 3
+        let mut block = data.clone();
+        block[first + 2] = 0x07;
+        assert_eq!(decode(&block), Err(Error::InvalidImage));
+        // chunk length beyond the end of the file
+        let mut length = data.clone();
+        length[first - 8..first - 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(decode(&length), Err(Error::InvalidImage));
+
+        // whatever byte is broken, the decoder doesn't panic
+        for i in HEADER_SIZE..data.len() {
+            for flip in [0x01, 0x80, 0xFF] {
+                let mut corrupted = data.clone();
+                corrupted[i] ^= flip;
+                if let Err(err) = decode(&corrupted) {
+                    assert_eq!(err, Error::InvalidImage, "byte {} ^ {:#x}", i, flip);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn missing_palette() {
+        let data = read("png/palette.png");
+        let plte = data.windows(4).position(|kind| kind == b"PLTE").unwrap();
+        // rename the chunk, so it is skipped as unknown
+        let mut missing = data.clone();
+        missing[plte..plte + 4].copy_from_slice(b"xxxx");
+        assert_eq!(decode(&missing), Err(Error::InvalidImage));
+    }
+}
@@ -0,0 +1,294 @@
+use core::ops::ControlFlow;
+
+use embedded_hal::delay::DelayNs;
+
+use crate::{display::Rectangle, Display, Result};
+
+/// Configuration of the screen repair routine.
+///
+/// The defaults follow the
+/// [LilyGo routine](https://github.com/Xinyuan-LilyGO/LilyGo-EPD47/blob/master/examples/screen_repair/screen_repair.ino),
+/// which takes about 30 seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RepairConfig {
+    /// Area to repair.
+    pub area: Rectangle,
+    /// Number of passes driving the area to black.
+    pub black_passes: u16,
+    /// Number of passes driving the area to white.
+    pub white_passes: u16,
+    /// Drive time of a single pass.
+    pub pass_time: u16,
+    /// Pause after each pass, in milliseconds.
+    pub pause_ms: u32,
+}
+
+impl Default for RepairConfig {
+    fn default() -> Self {
+        RepairConfig {
+            area: Display::BOUNDING_BOX,
+            black_passes: 20,
+            white_passes: 40,
+            pass_time: 50,
+            pause_ms: 500,
+        }
+    }
+}
+
+/// Phase of the screen repair routine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RepairPhase {
+    Clear,
+    Black,
+    White,
+}
+
+/// Progress of the screen repair routine after a step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RepairProgress {
+    /// Number of completed steps.
+    pub step: u32,
+    /// Total number of steps.
+    pub total: u32,
+    /// Phase of the completed step.
+    pub phase: RepairPhase,
+    /// Time to wait before the next step, in milliseconds.
+    pub pause_ms: u32,
+}
+
+/// Result of [Display::repair].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RepairOutcome {
+    Completed,
+    /// The routine has been aborted by the progress callback. The screen has
+    /// been cleared nevertheless.
+    Aborted,
+}
+
+/// The screen repair routine as a sequence of steps.
+///
+/// Each call to [`RepairProcedure::step`] runs a single pass and leaves the
+/// waiting to the caller, who can feed a watchdog, show the progress or stop
+/// in the meantime.
+///
+/// ```rust ignore
+/// let mut repair = RepairProcedure::new(RepairConfig::default());
+/// while let Some(progress) = repair.step(&mut display) {
+///     let progress = progress?;
+///     watchdog.feed();
+///     delay.delay_millis(progress.pause_ms);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RepairProcedure {
+    config: RepairConfig,
+    step: u32,
+}
+
+impl RepairProcedure {
+    /// Create the procedure for `config`, starting with the first clear.
+    pub fn new(config: RepairConfig) -> Self {
+        RepairProcedure { config, step: 0 }
+    }
+
+    /// Total number of steps, including the clears before, between and after
+    /// the passes.
+    pub fn total_steps(&self) -> u32 {
+        self.config.black_passes as u32 + self.config.white_passes as u32 + 3
+    }
+
+    /// Returns whether all steps have been run.
+    pub fn is_finished(&self) -> bool {
+        self.step >= self.total_steps()
+    }
+
+    /// Run the next step. Returns `None` once all steps have been run.
+    pub fn step(&mut self, display: &mut Display<'_>) -> Option<Result<RepairProgress>> {
+        if self.is_finished() {
+            return None;
+        }
+        let phase = self.phase(self.step);
+        let result = match phase {
+            RepairPhase::Clear if self.config.area == Display::BOUNDING_BOX => display.clear(),
+            RepairPhase::Clear => display.clear_area(self.config.area),
+            RepairPhase::Black => self.pass(display, 0),
+            RepairPhase::White => self.pass(display, 1),
+        };
+        self.step += 1;
+        Some(result.map(|_| RepairProgress {
+            step: self.step,
+            total: self.total_steps(),
+            phase,
+            pause_ms: match phase {
+                RepairPhase::Clear => 0,
+                RepairPhase::Black | RepairPhase::White => self.config.pause_ms,
+            },
+        }))
+    }
+
+    /// Skip to the final clear, e.g. to abort the routine without leaving a
+    /// half driven screen.
+    pub fn finish(&mut self) {
+        self.step = self.step.max(self.total_steps() - 1);
+    }
+
+    /// Drive the area to black (0) or white (1) once.
+    fn pass(&self, display: &mut Display<'_>, color: u16) -> Result<()> {
+        display.count_refresh();
+        display.push_pixels(self.config.area, self.config.pass_time, color)
+    }
+
+    fn phase(&self, step: u32) -> RepairPhase {
+        let black = self.config.black_passes as u32;
+        let white = self.config.white_passes as u32;
+        match step {
+            s if s == 0 || s == black + 1 || s == black + white + 2 => RepairPhase::Clear,
+            s if s <= black => RepairPhase::Black,
+            _ => RepairPhase::White,
+        }
+    }
+}
+
+impl<'a> Display<'a> {
+    /// Performs the screen repair routine, see [RepairConfig].
+    ///
+    /// `progress` is called after every step and may abort the routine by
+    /// returning [ControlFlow::Break]. The screen is cleared in either case.
+    pub fn repair<F>(
+        &mut self,
+        config: RepairConfig,
+        delay: &mut impl DelayNs,
+        mut progress: F,
+    ) -> Result<RepairOutcome>
+    where
+        F: FnMut(RepairProgress) -> ControlFlow<()>,
+    {
+        let mut procedure = RepairProcedure::new(config);
+        let mut outcome = RepairOutcome::Completed;
+        while let Some(step) = procedure.step(self) {
+            let step = step?;
+            if outcome == RepairOutcome::Completed && progress(step).is_break() {
+                outcome = RepairOutcome::Aborted;
+                procedure.finish();
+                continue;
+            }
+            delay.delay_ms(step.pause_ms);
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use embedded_hal_mock::eh1::delay::{CheckedDelay, Transaction};
+
+    use super::*;
+
+    /// Two black and three white passes over a small area.
+    fn config() -> RepairConfig {
+        RepairConfig {
+            area: Rectangle {
+                x: 100,
+                y: 40,
+                width: 16,
+                height: 8,
+            },
+            black_passes: 2,
+            white_passes: 3,
+            pass_time: 10,
+            pause_ms: 7,
+        }
+    }
+
+    #[test]
+    fn steps() {
+        use RepairPhase::{Black, Clear, White};
+
+        let mut display = Display::mock();
+        let mut procedure = RepairProcedure::new(config());
+        assert_eq!(procedure.total_steps(), 8);
+        let mut steps = Vec::new();
+        while let Some(progress) = procedure.step(&mut display) {
+            steps.push(progress.unwrap());
+        }
+        assert!(procedure.is_finished());
+        assert!(procedure.step(&mut display).is_none());
+
+        let phases: Vec<_> = steps.iter().map(|progress| progress.phase).collect();
+        assert_eq!(
+            phases,
+            [Clear, Black, Black, Clear, White, White, White, Clear]
+        );
+        for (index, progress) in steps.iter().enumerate() {
+            assert_eq!(progress.step, index as u32 + 1);
+            assert_eq!(progress.total, 8);
+            let pause_ms = if progress.phase == Clear { 0 } else { 7 };
+            assert_eq!(progress.pause_ms, pause_ms);
+        }
+        // every clear and pass is a refresh
+        assert_eq!(display.stats().refreshes, 8);
+    }
+
+    #[test]
+    fn finish_skips_to_the_last_clear() {
+        let mut display = Display::mock();
+        let mut procedure = RepairProcedure::new(config());
+        procedure.step(&mut display);
+        procedure.step(&mut display);
+        procedure.finish();
+        let progress = procedure.step(&mut display).unwrap().unwrap();
+        assert_eq!(progress.step, 8);
+        assert_eq!(progress.phase, RepairPhase::Clear);
+        assert!(procedure.step(&mut display).is_none());
+
+        // already in the last clear
+        procedure.finish();
+        assert!(procedure.is_finished());
+    }
+
+    #[test]
+    fn repair_reports_the_progress() {
+        let mut display = Display::mock();
+        let mut delay = CheckedDelay::new(&[0, 7, 7, 0, 7, 7, 7, 0].map(Transaction::delay_ms));
+        let mut reported = Vec::new();
+        let outcome = display.repair(config(), &mut delay, |progress| {
+            reported.push((progress.step, progress.phase));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(outcome, Ok(RepairOutcome::Completed));
+        assert_eq!(reported.len(), 8);
+        assert_eq!(reported[4], (5, RepairPhase::White));
+        assert_eq!(display.stats().refreshes, 8);
+        delay.done();
+    }
+
+    #[test]
+    fn repair_is_aborted() {
+        let mut display = Display::mock();
+        // no pause after the aborting step
+        let mut delay = CheckedDelay::new(&[0, 7, 0].map(Transaction::delay_ms));
+        let mut reported = Vec::new();
+        let outcome = display.repair(config(), &mut delay, |progress| {
+            reported.push(progress.phase);
+            if progress.step == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(outcome, Ok(RepairOutcome::Aborted));
+        // the screen is cleared without reporting the last step
+        assert_eq!(
+            reported,
+            [RepairPhase::Clear, RepairPhase::Black, RepairPhase::Black]
+        );
+        assert_eq!(display.stats().refreshes, 4);
+        delay.done();
+    }
+}
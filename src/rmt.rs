@@ -1,75 +1,207 @@
-use core::ops::DerefMut;
+use alloc::boxed::Box;
 
+#[cfg(target_arch = "xtensa")]
 use esp_hal::{
     gpio::GpioPin,
     into_ref,
     peripheral::{Peripheral, PeripheralRef},
     peripherals,
     prelude::*,
-    rmt,
-    rmt::{Channel, PulseCode, TxChannel, TxChannelCreator},
+    rmt::{self, Channel, SingleShotTxTransaction, TxChannel, TxChannelCreator},
     Blocking,
 };
 
-pub(crate) struct Rmt<'a> {
-    tx_channel: Option<Channel<Blocking, 1>>,
-    rmt: PeripheralRef<'a, peripherals::RMT>,
+use crate::{
+    hal::{PulseCode, RmtError},
+    Operation,
+};
+
+/// Errors of the pulse generator. The caller knows which operation was
+/// running and turns them into a [crate::Error].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PulseError {
+    Rmt(RmtError),
+    ChannelLost(RmtError),
 }
 
-impl<'a> Rmt<'a> {
-    pub(crate) fn new(rmt: impl Peripheral<P = peripherals::RMT> + 'a) -> Self {
-        into_ref!(rmt);
-        Rmt {
-            tx_channel: None,
-            rmt,
+impl PulseError {
+    pub(crate) fn during(self, operation: Operation) -> crate::Error {
+        match self {
+            Self::Rmt(err) => crate::Error::Rmt(operation, err),
+            Self::ChannelLost(err) => crate::Error::ChannelLost(operation, err),
         }
     }
+}
+
+/// Transmit channel of the pulse generator. Like the RMT channel, it is
+/// consumed by a transmission and handed back by the transaction.
+pub(crate) trait PulseChannel<'a>: Sized {
+    type Transaction: PulseTransaction<Channel = Self>;
+
+    /// Starts sending the pulse codes. On failure the channel is lost.
+    fn transmit(self, data: &'a [u32]) -> Result<Self::Transaction, RmtError>;
+}
+
+/// A started transmission.
+pub(crate) trait PulseTransaction {
+    type Channel;
+
+    /// Waits for the transmission to finish and hands the channel back.
+    fn wait(self) -> Result<Self::Channel, (RmtError, Self::Channel)>;
+}
+
+/// Creates the transmit channel, again after it has been lost.
+pub(crate) trait PulseOutput<'a> {
+    type Channel: PulseChannel<'a>;
+
+    fn configure(&mut self) -> Result<Self::Channel, RmtError>;
+}
+
+enum State<'a, C: PulseChannel<'a>> {
+    /// The channel is idle and ready to transmit.
+    Idle(C),
+    /// A pulse has been started without waiting for it to finish.
+    Pending(C::Transaction),
+    /// The channel has been consumed by a failed transmission. It is
+    /// re-initialized before the next pulse.
+    Lost,
+}
+
+pub(crate) struct Rmt<'a, O: PulseOutput<'a>> {
+    // NOTE: `state` must be declared before `data` so that a pending
+    // transaction is dropped before the buffer it borrows.
+    state: State<'a, O::Channel>,
+    data: Box<[u32; 2]>,
+    output: O,
+}
+
+impl<'a, O: PulseOutput<'a>> Rmt<'a, O> {
+    pub(crate) fn new(mut output: O) -> Result<Self, PulseError> {
+        let channel = output.configure().map_err(PulseError::Rmt)?;
+        Ok(Rmt {
+            state: State::Idle(channel),
+            data: Box::new([PulseCode::empty(); 2]),
+            output,
+        })
+    }
+
+    /// Returns whether the channel is idle, i.e. neither busy with a pulse
+    /// nor lost.
+    #[cfg(test)]
+    pub(crate) fn is_idle(&self) -> bool {
+        matches!(self.state, State::Idle(_))
+    }
 
-    fn ensure_channel(&mut self) -> Result<(), crate::Error> {
-        if self.tx_channel.is_some() {
-            return Ok(());
+    /// Waits for a pending pulse (if any) and returns the idle channel. A lost
+    /// channel is re-initialized.
+    fn take_channel(&mut self) -> Result<O::Channel, PulseError> {
+        match core::mem::replace(&mut self.state, State::Lost) {
+            State::Idle(channel) => Ok(channel),
+            State::Pending(tx) => tx.wait().map_err(|(err, channel)| {
+                self.state = State::Idle(channel);
+                PulseError::Rmt(err)
+            }),
+            State::Lost => self.output.configure().map_err(PulseError::Rmt),
         }
-        let rmt = rmt::Rmt::new(
-            unsafe { self.rmt.deref_mut().clone_unchecked() }, // TODO: find better solution
-            80.MHz(),
-        )
-        .map_err(crate::Error::Rmt)?;
-        let tx_channel = rmt
-            .channel1
-            .configure(
-                unsafe { GpioPin::<38>::steal() }, // TODO: find better solution
-                rmt::TxChannelConfig {
-                    clk_divider: 8,
-                    idle_output_level: false,
-                    idle_output: true,
-                    carrier_modulation: false,
-                    carrier_level: false,
-                    ..Default::default()
-                },
-            )
-            .map_err(crate::Error::Rmt)?;
-        self.tx_channel = Some(tx_channel);
-        Ok(())
     }
 
-    pub(crate) fn pulse(&mut self, high: u16, low: u16, wait: bool) -> Result<(), crate::Error> {
-        self.ensure_channel()?;
-        let tx_channel = self.tx_channel.take().ok_or(crate::Error::Unknown)?;
-        let data = if high > 0 {
+    /// Sends a single pulse. If `wait` is false, the method returns as soon as
+    /// the pulse has been started. The next call waits for it to finish before
+    /// sending its own pulse.
+    pub(crate) fn pulse(&mut self, high: u16, low: u16, wait: bool) -> Result<(), PulseError> {
+        let channel = self.take_channel()?;
+        *self.data = if high > 0 {
             [PulseCode::new(true, high, false, low), PulseCode::empty()]
         } else {
             [PulseCode::new(true, low, false, 0), PulseCode::empty()]
         };
-        let tx = tx_channel.transmit(&data).map_err(crate::Error::Rmt)?;
-        // FIXME: This is the culprit.. We need the channel later again but can't wait
-        // due to some time sensitive operations. Not sure how to solve this
+        // SAFETY: `data` is heap allocated and never moves. It is only written
+        // above, after any transaction borrowing it has been waited for, and
+        // `state` is dropped before `data`.
+        let data: &'a [u32] = unsafe { &*(self.data.as_slice() as *const [u32]) };
+        // a failed transmission consumes the channel, `state` stays `Lost`
+        let tx = channel.transmit(data).map_err(PulseError::ChannelLost)?;
         if wait {
-            self.tx_channel = Some(
-                tx.wait()
-                    .map_err(|(err, _)| err)
-                    .map_err(crate::Error::Rmt)?,
-            );
+            let channel = tx.wait().map_err(|(err, channel)| {
+                self.state = State::Idle(channel);
+                PulseError::Rmt(err)
+            })?;
+            self.state = State::Idle(channel);
+        } else {
+            self.state = State::Pending(tx);
         }
         Ok(())
     }
 }
+
+impl<'a, O: PulseOutput<'a>> Drop for Rmt<'a, O> {
+    fn drop(&mut self) {
+        // let a pending pulse finish before the pin is released
+        if let State::Pending(tx) = core::mem::replace(&mut self.state, State::Lost) {
+            let _ = tx.wait();
+        }
+    }
+}
+
+#[cfg(target_arch = "xtensa")]
+type TxChannel1 = Channel<Blocking, 1>;
+
+/// Channel 1 of the RMT peripheral on the gate clock pin.
+#[cfg(target_arch = "xtensa")]
+pub(crate) struct RmtOutput<'a> {
+    rmt: PeripheralRef<'a, peripherals::RMT>,
+    pin: PeripheralRef<'a, GpioPin<38>>,
+}
+
+#[cfg(target_arch = "xtensa")]
+impl<'a> RmtOutput<'a> {
+    pub(crate) fn new(
+        rmt: impl Peripheral<P = peripherals::RMT> + 'a,
+        pin: impl Peripheral<P = GpioPin<38>> + 'a,
+    ) -> Self {
+        into_ref!(rmt, pin);
+        RmtOutput { rmt, pin }
+    }
+}
+
+#[cfg(target_arch = "xtensa")]
+impl<'a> PulseOutput<'a> for RmtOutput<'a> {
+    type Channel = TxChannel1;
+
+    /// Configures the tx channel from the owned peripheral and pin. The
+    /// channel doesn't borrow from `self`, only the reborrowed peripherals do
+    /// for the duration of this call. This allows to re-initialize the channel
+    /// after it has been lost.
+    fn configure(&mut self) -> Result<TxChannel1, RmtError> {
+        let rmt = rmt::Rmt::new(self.rmt.reborrow(), 80.MHz())?;
+        rmt.channel1.configure(
+            self.pin.reborrow(),
+            rmt::TxChannelConfig {
+                clk_divider: 8,
+                idle_output_level: false,
+                idle_output: true,
+                carrier_modulation: false,
+                carrier_level: false,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+#[cfg(target_arch = "xtensa")]
+impl<'a> PulseChannel<'a> for TxChannel1 {
+    type Transaction = SingleShotTxTransaction<'a, TxChannel1, u32>;
+
+    fn transmit(self, data: &'a [u32]) -> Result<Self::Transaction, RmtError> {
+        TxChannel::transmit(self, data)
+    }
+}
+
+#[cfg(target_arch = "xtensa")]
+impl PulseTransaction for SingleShotTxTransaction<'_, TxChannel1, u32> {
+    type Channel = TxChannel1;
+
+    fn wait(self) -> Result<TxChannel1, (RmtError, TxChannel1)> {
+        SingleShotTxTransaction::wait(self)
+    }
+}
@@ -0,0 +1,537 @@
+use embedded_hal::i2c::{Error as _, I2c};
+
+use crate::{Error, Result};
+
+const ADDRESS: u8 = 0x51;
+
+const REG_CONTROL_1: u8 = 0x00;
+const REG_CONTROL_2: u8 = 0x01;
+const REG_SECONDS: u8 = 0x02;
+const REG_ALARM: u8 = 0x09;
+const REG_TIMER_CONTROL: u8 = 0x0E;
+
+const CONTROL_2_AF: u8 = 0x08;
+const CONTROL_2_TF: u8 = 0x04;
+const CONTROL_2_AIE: u8 = 0x02;
+const CONTROL_2_TIE: u8 = 0x01;
+/// Seconds register flag: the clock has been stopped or lost power.
+const VL: u8 = 0x80;
+/// Months register flag: the year lies in the next century.
+const CENTURY: u8 = 0x80;
+/// Alarm register flag: the field is ignored.
+const ALARM_DISABLED: u8 = 0x80;
+const TIMER_ENABLE: u8 = 0x80;
+
+/// Days from 2000-01-01 to 2200-01-01, the range of the [Rtc].
+const DAYS_UNTIL_2200: u64 = 73_049;
+
+/// Calendar date and time as kept by the [Rtc], from 2000 to 2199.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    pub year: u16,
+    /// Month from 1 to 12.
+    pub month: u8,
+    /// Day of the month from 1.
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl DateTime {
+    /// Day of the week, from 0 (Sunday) to 6 (Saturday).
+    pub fn weekday(&self) -> u8 {
+        // 2000-01-01 was a Saturday
+        ((self.days_since_epoch() + 6) % 7) as u8
+    }
+
+    /// Returns whether all fields are in range.
+    pub fn is_valid(&self) -> bool {
+        (2000..=2199).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hours < 24
+            && self.minutes < 60
+            && self.seconds < 60
+    }
+
+    /// Seconds since 2000-01-01 00:00:00. The timestamps of a
+    /// [DrainLog](crate::DrainLog) are truncated to `u32`, which lasts until
+    /// 2136.
+    pub fn to_timestamp(&self) -> u64 {
+        self.days_since_epoch() as u64 * 86_400
+            + self.hours as u64 * 3600
+            + self.minutes as u64 * 60
+            + self.seconds as u64
+    }
+
+    /// Inverse of [`DateTime::to_timestamp`]. Returns `None` for timestamps
+    /// past 2199, which the [Rtc] can't keep.
+    pub fn from_timestamp(timestamp: u64) -> Option<Self> {
+        let days = timestamp / 86_400;
+        if days >= DAYS_UNTIL_2200 {
+            return None;
+        }
+        let mut days = days as u32;
+        let seconds = (timestamp % 86_400) as u32;
+        let mut year = 2000;
+        while days >= days_in_year(year) {
+            days -= days_in_year(year);
+            year += 1;
+        }
+        let mut month = 1;
+        while days >= days_in_month(year, month) as u32 {
+            days -= days_in_month(year, month) as u32;
+            month += 1;
+        }
+        Some(DateTime {
+            year,
+            month,
+            day: days as u8 + 1,
+            hours: (seconds / 3600) as u8,
+            minutes: (seconds / 60 % 60) as u8,
+            seconds: (seconds % 60) as u8,
+        })
+    }
+
+    fn days_since_epoch(&self) -> u32 {
+        let years: u32 = (2000..self.year).map(days_in_year).sum();
+        let months: u32 = (1..self.month)
+            .map(|month| days_in_month(self.year, month) as u32)
+            .sum();
+        years + months + self.day.saturating_sub(1) as u32
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_year(year: u16) -> u32 {
+    if is_leap_year(year) {
+        366
+    } else {
+        365
+    }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Alarm condition of the [Rtc]. The alarm fires when all fields that are set
+/// match, fields set to `None` are ignored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Alarm {
+    pub minute: Option<u8>,
+    pub hour: Option<u8>,
+    pub day: Option<u8>,
+    /// Day of the week, from 0 (Sunday) to 6 (Saturday).
+    pub weekday: Option<u8>,
+}
+
+impl Alarm {
+    /// Alarm firing every day at the given time.
+    pub fn daily(hour: u8, minute: u8) -> Self {
+        Alarm {
+            minute: Some(minute),
+            hour: Some(hour),
+            ..Default::default()
+        }
+    }
+}
+
+/// Source clock of the countdown timer of the [Rtc].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimerFrequency {
+    Hz4096,
+    Hz64,
+    Hz1,
+    /// One tick per minute.
+    PerMinute,
+}
+
+/// Driver for the PCF8563 real-time clock on the shared I2C bus.
+///
+/// The alarm and the timer pull the open drain `INT` output of the RTC low,
+/// which can wake the ESP32-S3 from deep sleep:
+///
+/// ```rust ignore
+/// let mut rtc = Rtc::new(i2c);
+/// if !rtc.is_clock_valid()? {
+///     rtc.set_datetime(&fetch_time_from_server())?;
+/// }
+/// rtc.set_alarm(Alarm::daily(7, 30))?;
+/// rtc.set_interrupts(true, false)?;
+/// ```
+pub struct Rtc<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C: I2c> Rtc<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        Rtc { i2c }
+    }
+
+    /// Release the I2C bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// Read the current date and time.
+    ///
+    /// The time is returned even if the clock integrity is not guaranteed,
+    /// see [`Rtc::is_clock_valid`].
+    pub fn datetime(&mut self) -> Result<DateTime> {
+        let mut regs = [0u8; 7];
+        self.read(REG_SECONDS, &mut regs)?;
+        let century = if regs[5] & CENTURY != 0 { 100 } else { 0 };
+        let datetime = DateTime {
+            seconds: from_bcd(regs[0] & 0x7F)?,
+            minutes: from_bcd(regs[1] & 0x7F)?,
+            hours: from_bcd(regs[2] & 0x3F)?,
+            day: from_bcd(regs[3] & 0x3F)?,
+            month: from_bcd(regs[5] & 0x1F)?,
+            year: 2000 + century + from_bcd(regs[6])? as u16,
+        };
+        if !datetime.is_valid() {
+            return Err(Error::InvalidDateTime);
+        }
+        Ok(datetime)
+    }
+
+    /// Set the date and time, start the clock and clear the integrity flag.
+    pub fn set_datetime(&mut self, datetime: &DateTime) -> Result<()> {
+        if !datetime.is_valid() {
+            return Err(Error::InvalidDateTime);
+        }
+        let century = if datetime.year >= 2100 { CENTURY } else { 0 };
+        let regs = [
+            to_bcd(datetime.seconds),
+            to_bcd(datetime.minutes),
+            to_bcd(datetime.hours),
+            to_bcd(datetime.day),
+            datetime.weekday(),
+            to_bcd(datetime.month) | century,
+            to_bcd((datetime.year % 100) as u8),
+        ];
+        self.write(REG_SECONDS, &regs)?;
+        self.write(REG_CONTROL_1, &[0])
+    }
+
+    /// Returns whether the clock has kept running since it has been set. The
+    /// flag is cleared by [`Rtc::set_datetime`].
+    pub fn is_clock_valid(&mut self) -> Result<bool> {
+        let mut seconds = [0u8];
+        self.read(REG_SECONDS, &mut seconds)?;
+        Ok(seconds[0] & VL == 0)
+    }
+
+    /// Set the alarm condition. The alarm flag is cleared.
+    pub fn set_alarm(&mut self, alarm: Alarm) -> Result<()> {
+        let valid = alarm.minute.iter().all(|&minute| minute < 60)
+            && alarm.hour.iter().all(|&hour| hour < 24)
+            && alarm.day.iter().all(|day| (1..=31).contains(day))
+            && alarm.weekday.iter().all(|&weekday| weekday < 7);
+        if !valid {
+            return Err(Error::InvalidDateTime);
+        }
+        let field = |value: Option<u8>| value.map_or(ALARM_DISABLED, to_bcd);
+        self.write(
+            REG_ALARM,
+            &[
+                field(alarm.minute),
+                field(alarm.hour),
+                field(alarm.day),
+                alarm.weekday.unwrap_or(ALARM_DISABLED),
+            ],
+        )?;
+        self.clear_flags(true, false)
+    }
+
+    /// Disable all alarm fields, so the alarm never fires.
+    pub fn disable_alarm(&mut self) -> Result<()> {
+        self.write(REG_ALARM, &[ALARM_DISABLED; 4])
+    }
+
+    /// Start the countdown timer. It fires after `count` ticks of
+    /// `frequency` and restarts.
+    pub fn set_timer(&mut self, frequency: TimerFrequency, count: u8) -> Result<()> {
+        self.write(REG_TIMER_CONTROL, &[0, count])?;
+        self.clear_flags(false, true)?;
+        self.write(REG_TIMER_CONTROL, &[TIMER_ENABLE | frequency as u8])
+    }
+
+    /// Stop the countdown timer.
+    pub fn stop_timer(&mut self) -> Result<()> {
+        self.write(REG_TIMER_CONTROL, &[TimerFrequency::PerMinute as u8])
+    }
+
+    /// Select which of the alarm and timer pull the `INT` output low.
+    pub fn set_interrupts(&mut self, alarm: bool, timer: bool) -> Result<()> {
+        let mut control = self.control_2()?;
+        control &= !(CONTROL_2_AIE | CONTROL_2_TIE);
+        if alarm {
+            control |= CONTROL_2_AIE;
+        }
+        if timer {
+            control |= CONTROL_2_TIE;
+        }
+        // writing 1 leaves the flags untouched
+        self.write(REG_CONTROL_2, &[control | CONTROL_2_AF | CONTROL_2_TF])
+    }
+
+    /// Returns whether the alarm has fired, e.g. to find the wake-up cause.
+    pub fn alarm_fired(&mut self) -> Result<bool> {
+        Ok(self.control_2()? & CONTROL_2_AF != 0)
+    }
+
+    /// Returns whether the timer has fired.
+    pub fn timer_fired(&mut self) -> Result<bool> {
+        Ok(self.control_2()? & CONTROL_2_TF != 0)
+    }
+
+    /// Clear the alarm and timer flags, which releases the `INT` output.
+    pub fn clear_flags(&mut self, alarm: bool, timer: bool) -> Result<()> {
+        let mut control = self.control_2()? | CONTROL_2_AF | CONTROL_2_TF;
+        if alarm {
+            control &= !CONTROL_2_AF;
+        }
+        if timer {
+            control &= !CONTROL_2_TF;
+        }
+        self.write(REG_CONTROL_2, &[control])
+    }
+
+    fn control_2(&mut self) -> Result<u8> {
+        let mut control = [0u8];
+        self.read(REG_CONTROL_2, &mut control)?;
+        // the upper bits read undefined and must be written as zero
+        Ok(control[0] & 0x1F)
+    }
+
+    fn read(&mut self, register: u8, buffer: &mut [u8]) -> Result<()> {
+        self.i2c
+            .write_read(ADDRESS, &[register], buffer)
+            .map_err(|err| Error::I2c(err.kind()))
+    }
+
+    fn write(&mut self, register: u8, data: &[u8]) -> Result<()> {
+        let mut buffer = [0u8; 8];
+        buffer[0] = register;
+        buffer[1..=data.len()].copy_from_slice(data);
+        self.i2c
+            .write(ADDRESS, &buffer[..=data.len()])
+            .map_err(|err| Error::I2c(err.kind()))
+    }
+}
+
+fn from_bcd(value: u8) -> Result<u8> {
+    if value & 0x0F > 9 || value >> 4 > 9 {
+        return Err(Error::InvalidDateTime);
+    }
+    Ok((value >> 4) * 10 + (value & 0x0F))
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use embedded_hal::i2c::ErrorKind;
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    use super::*;
+
+    fn read(register: u8, response: &[u8]) -> Transaction {
+        Transaction::write_read(ADDRESS, vec![register], response.to_vec())
+    }
+
+    fn write(register: u8, data: &[u8]) -> Transaction {
+        let mut bytes = vec![register];
+        bytes.extend_from_slice(data);
+        Transaction::write(ADDRESS, bytes)
+    }
+
+    fn rtc(expectations: &[Transaction]) -> Rtc<Mock> {
+        Rtc::new(Mock::new(expectations))
+    }
+
+    fn datetime(year: u16, month: u8, day: u8, hours: u8, minutes: u8, seconds: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hours,
+            minutes,
+            seconds,
+        }
+    }
+
+    #[test]
+    fn reads_bcd_datetime() {
+        let mut rtc = rtc(&[
+            read(REG_SECONDS, &[0x45, 0x59, 0x23, 0x31, 0x04, 0x12, 0x99]),
+            // clock integrity lost and the unused bits set
+            read(REG_SECONDS, &[0x87, 0x80, 0xC0, 0xC1, 0xF9, 0xE2, 0x04]),
+        ]);
+        assert_eq!(rtc.datetime(), Ok(datetime(2099, 12, 31, 23, 59, 45)));
+        // century flag
+        assert_eq!(rtc.datetime(), Ok(datetime(2104, 2, 1, 0, 0, 7)));
+        rtc.release().done();
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let mut rtc = rtc(&[
+            read(REG_SECONDS, &[0x5A, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00]),
+            // February 30
+            read(REG_SECONDS, &[0x00, 0x00, 0x00, 0x30, 0x00, 0x02, 0x24]),
+            read(REG_SECONDS, &[0; 7]).with_error(ErrorKind::Bus),
+        ]);
+        assert_eq!(rtc.datetime(), Err(Error::InvalidDateTime));
+        assert_eq!(rtc.datetime(), Err(Error::InvalidDateTime));
+        assert_eq!(rtc.datetime(), Err(Error::I2c(ErrorKind::Bus)));
+        rtc.release().done();
+    }
+
+    #[test]
+    fn writes_bcd_datetime() {
+        let mut rtc = rtc(&[
+            // Thursday
+            write(REG_SECONDS, &[0x56, 0x34, 0x12, 0x29, 0x04, 0x02, 0x24]),
+            write(REG_CONTROL_1, &[0x00]),
+            write(REG_SECONDS, &[0x00, 0x00, 0x00, 0x01, 0x04, 0x81, 0x50]),
+            write(REG_CONTROL_1, &[0x00]),
+        ]);
+        rtc.set_datetime(&datetime(2024, 2, 29, 12, 34, 56))
+            .unwrap();
+        rtc.set_datetime(&datetime(2150, 1, 1, 0, 0, 0)).unwrap();
+        assert_eq!(
+            rtc.set_datetime(&datetime(2023, 2, 29, 0, 0, 0)),
+            Err(Error::InvalidDateTime)
+        );
+        assert_eq!(
+            rtc.set_datetime(&datetime(2200, 1, 1, 0, 0, 0)),
+            Err(Error::InvalidDateTime)
+        );
+        rtc.release().done();
+    }
+
+    #[test]
+    fn voltage_low_flag() {
+        let mut rtc = rtc(&[
+            read(REG_SECONDS, &[0x80 | 0x12]),
+            read(REG_SECONDS, &[0x12]),
+        ]);
+        assert_eq!(rtc.is_clock_valid(), Ok(false));
+        assert_eq!(rtc.is_clock_valid(), Ok(true));
+        rtc.release().done();
+    }
+
+    #[test]
+    fn alarm_registers() {
+        let mut rtc = rtc(&[
+            write(REG_ALARM, &[0x30, 0x07, 0x80, 0x80]),
+            // the upper bits read undefined, alarm and timer flags are set
+            read(REG_CONTROL_2, &[0xEE]),
+            write(REG_CONTROL_2, &[0x06]),
+            write(REG_ALARM, &[0x80, 0x80, 0x15, 0x06]),
+            read(REG_CONTROL_2, &[0x00]),
+            write(REG_CONTROL_2, &[0x04]),
+            write(REG_ALARM, &[0x80; 4]),
+            read(REG_CONTROL_2, &[0x0A]),
+            read(REG_CONTROL_2, &[0x0A]),
+            write(REG_CONTROL_2, &[0x0D]),
+        ]);
+        rtc.set_alarm(Alarm::daily(7, 30)).unwrap();
+        rtc.set_alarm(Alarm {
+            day: Some(15),
+            weekday: Some(6),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            rtc.set_alarm(Alarm::daily(7, 60)),
+            Err(Error::InvalidDateTime)
+        );
+        assert_eq!(
+            rtc.set_alarm(Alarm {
+                day: Some(0),
+                ..Default::default()
+            }),
+            Err(Error::InvalidDateTime)
+        );
+        rtc.disable_alarm().unwrap();
+        assert_eq!(rtc.alarm_fired(), Ok(true));
+        rtc.set_interrupts(false, true).unwrap();
+        rtc.release().done();
+    }
+
+    #[test]
+    fn timer_registers() {
+        let mut rtc = rtc(&[
+            write(REG_TIMER_CONTROL, &[0x00, 10]),
+            read(REG_CONTROL_2, &[0x06]),
+            write(REG_CONTROL_2, &[0x0A]),
+            write(REG_TIMER_CONTROL, &[0x82]),
+            read(REG_CONTROL_2, &[0x00]),
+            write(REG_TIMER_CONTROL, &[0x03]),
+        ]);
+        rtc.set_timer(TimerFrequency::Hz1, 10).unwrap();
+        assert_eq!(rtc.timer_fired(), Ok(false));
+        rtc.stop_timer().unwrap();
+        rtc.release().done();
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(datetime(2000, 1, 1, 0, 0, 0).to_timestamp(), 0);
+        assert_eq!(datetime(2000, 1, 1, 0, 0, 0).weekday(), 6);
+        assert_eq!(datetime(2024, 2, 29, 0, 0, 0).weekday(), 4);
+        for date in [
+            datetime(2024, 2, 29, 12, 34, 56),
+            datetime(2100, 3, 1, 0, 0, 0),
+            // beyond u32 seconds
+            datetime(2137, 1, 1, 0, 0, 0),
+            datetime(2199, 12, 31, 23, 59, 59),
+        ] {
+            assert_eq!(DateTime::from_timestamp(date.to_timestamp()), Some(date));
+        }
+        assert!(datetime(2137, 1, 1, 0, 0, 0).to_timestamp() > u32::MAX as u64);
+    }
+
+    #[test]
+    fn timestamp_range() {
+        assert_eq!(
+            (2000..2200).map(days_in_year).sum::<u32>() as u64,
+            DAYS_UNTIL_2200
+        );
+        assert_eq!(
+            DateTime::from_timestamp(0),
+            Some(datetime(2000, 1, 1, 0, 0, 0))
+        );
+        let last = datetime(2199, 12, 31, 23, 59, 59).to_timestamp();
+        assert_eq!(last, DAYS_UNTIL_2200 * 86_400 - 1);
+        assert_eq!(
+            DateTime::from_timestamp(last),
+            Some(datetime(2199, 12, 31, 23, 59, 59))
+        );
+        // 2200-01-01 and beyond, including days past u32
+        for timestamp in [last + 1, 86_400 << 32, u64::MAX] {
+            assert_eq!(DateTime::from_timestamp(timestamp), None);
+        }
+    }
+}
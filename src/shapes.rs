@@ -0,0 +1,755 @@
+use alloc::{vec, vec::Vec};
+use core::f32::consts::PI;
+
+use libm::{acosf, atan2f, ceilf, cosf, floorf, sinf, sqrtf};
+
+use crate::{Display, Error, Result};
+
+/// Maximum distance of the flattened arcs from the exact curve in pixels.
+const TOLERANCE: f32 = 0.02;
+const MAX_ARC_SEGMENTS: usize = 1024;
+/// Sample rows per pixel row, one per level of the 16 grays.
+const SUBSAMPLES: usize = 16;
+
+/// A position on the screen with sub-pixel precision, see
+/// [Display::draw_polygon_aa].
+///
+/// Pixels are squares between whole coordinates, so `(10.5, 20.5)` is the
+/// center of the pixel at 10/20 and a one pixel wide horizontal line from
+/// `(0.0, 20.5)` to `(100.0, 20.5)` exactly covers a row of pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PointF {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl PointF {
+    pub const fn new(x: f32, y: f32) -> Self {
+        PointF { x, y }
+    }
+
+    fn add(self, other: PointF) -> PointF {
+        PointF::new(self.x + other.x, self.y + other.y)
+    }
+
+    fn sub(self, other: PointF) -> PointF {
+        PointF::new(self.x - other.x, self.y - other.y)
+    }
+
+    fn scale(self, factor: f32) -> PointF {
+        PointF::new(self.x * factor, self.y * factor)
+    }
+
+    fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+}
+
+impl From<(f32, f32)> for PointF {
+    fn from((x, y): (f32, f32)) -> Self {
+        PointF::new(x, y)
+    }
+}
+
+/// Shape of the ends of open lines and arcs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LineCap {
+    /// The line ends at its end points.
+    #[default]
+    Butt,
+    /// A half circle beyond each end point.
+    Round,
+    /// The line is extended by half its width beyond each end point.
+    Square,
+}
+
+/// Fill and outline of the anti-aliased shapes, see [Display::draw_polygon_aa].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ShapeStyle {
+    /// Color of the inside, 0 (black) to 15 (white), or `None` to keep the
+    /// framebuffer.
+    pub fill: Option<u8>,
+    /// Color of the outline, or `None` for no outline. Lines only have an
+    /// outline.
+    pub stroke: Option<u8>,
+    /// Width of the outline in pixels, centered on the edge of the shape.
+    pub stroke_width: f32,
+    /// Ends of lines and arcs. Corners are always round.
+    pub cap: LineCap,
+}
+
+impl ShapeStyle {
+    /// A shape filled with a color.
+    pub fn with_fill(color: u8) -> Self {
+        ShapeStyle {
+            fill: Some(color),
+            ..Default::default()
+        }
+    }
+
+    /// An outline or line of a color and width.
+    pub fn with_stroke(color: u8, width: f32) -> Self {
+        ShapeStyle {
+            stroke: Some(color),
+            stroke_width: width,
+            ..Default::default()
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.fill.is_some_and(|color| color > 0x0F)
+            || self.stroke.is_some_and(|color| color > 0x0F)
+        {
+            return Err(Error::InvalidColor);
+        }
+        Ok(())
+    }
+
+    /// Color and half width of the outline, if it's visible.
+    fn outline(&self) -> Option<(u8, f32)> {
+        let half = self.stroke_width / 2.0;
+        self.stroke
+            .filter(|_| half > 0.0)
+            .map(|color| (color, half))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+}
+
+/// Closed contours of line segments, filled with the non-zero winding rule.
+///
+/// Each pixel row is sampled at [SUBSAMPLES] heights, where the spans with a
+/// non-zero winding number are added with their exact horizontal extent.
+/// Strokes are built from overlapping convex pieces of the same orientation,
+/// the winding rule makes their union covered once.
+#[derive(Default)]
+struct Path {
+    edges: Vec<Edge>,
+    points: Vec<PointF>,
+}
+
+impl Path {
+    /// Add a closed contour through the points, keeping its orientation.
+    fn contour(&mut self, points: &[PointF]) {
+        if points.iter().any(|point| !point.is_finite()) {
+            return;
+        }
+        for (i, from) in points.iter().enumerate() {
+            let to = points[(i + 1) % points.len()];
+            if from.y != to.y {
+                self.edges.push(Edge {
+                    x0: from.x,
+                    y0: from.y,
+                    x1: to.x,
+                    y1: to.y,
+                });
+            }
+        }
+    }
+
+    /// Add a closed contour with positive (`reverse == false`) or negative
+    /// orientation, e.g. for holes.
+    fn oriented(&mut self, points: &[PointF], reverse: bool) {
+        let area: f32 = (0..points.len())
+            .map(|i| {
+                let (a, b) = (points[i], points[(i + 1) % points.len()]);
+                a.x * b.y - b.x * a.y
+            })
+            .sum();
+        let start = self.edges.len();
+        self.contour(points);
+        if (area < 0.0) != reverse {
+            for edge in &mut self.edges[start..] {
+                *edge = Edge {
+                    x0: edge.x1,
+                    y0: edge.y1,
+                    x1: edge.x0,
+                    y1: edge.y0,
+                };
+            }
+        }
+    }
+
+    /// Add the points in [Path::points] as a positive contour and clear them.
+    fn piece(&mut self) {
+        let points = core::mem::take(&mut self.points);
+        self.oriented(&points, false);
+        self.points = points;
+        self.points.clear();
+    }
+
+    /// Append the points of an arc around `center` to [Path::points], from
+    /// angle `start` (radians) over `sweep`, including both ends.
+    fn arc(&mut self, center: PointF, radius: f32, start: f32, sweep: f32) {
+        let step = if radius > TOLERANCE {
+            2.0 * acosf(1.0 - TOLERANCE / radius)
+        } else {
+            PI
+        };
+        let segments = (ceilf(sweep.abs() / step) as usize).clamp(1, MAX_ARC_SEGMENTS);
+        for i in 0..=segments {
+            let angle = start + sweep * i as f32 / segments as f32;
+            self.points.push(PointF::new(
+                center.x + radius * cosf(angle),
+                center.y + radius * sinf(angle),
+            ));
+        }
+    }
+
+    /// Add a circular sector as a positive piece.
+    fn sector(&mut self, center: PointF, radius: f32, start: f32, sweep: f32) {
+        self.points.push(center);
+        self.arc(center, radius, start, sweep);
+        self.piece();
+    }
+
+    /// Add a circle with positive or negative orientation.
+    fn circle(&mut self, center: PointF, radius: f32, reverse: bool) {
+        self.arc(center, radius, 0.0, 2.0 * PI);
+        self.points.pop();
+        let points = core::mem::take(&mut self.points);
+        self.oriented(&points, reverse);
+        self.points = points;
+        self.points.clear();
+    }
+
+    /// Add the outline of a line through the points with half width `half`,
+    /// round corners and, if it's open, the given caps.
+    fn stroke(&mut self, points: &[PointF], closed: bool, half: f32, cap: LineCap) {
+        let mut segments: Vec<(PointF, PointF, PointF)> = Vec::with_capacity(points.len());
+        let count = if closed {
+            points.len()
+        } else {
+            points.len().saturating_sub(1)
+        };
+        for i in 0..count {
+            let (from, to) = (points[i], points[(i + 1) % points.len()]);
+            let delta = to.sub(from);
+            let length = sqrtf(delta.x * delta.x + delta.y * delta.y);
+            if length > 0.0 && length.is_finite() {
+                segments.push((from, to, delta.scale(1.0 / length)));
+            }
+        }
+        if segments.is_empty() {
+            // a dot
+            if let (Some(&point), LineCap::Round) = (points.first(), cap) {
+                self.circle(point, half, false);
+            }
+            return;
+        }
+
+        for &(from, to, direction) in &segments {
+            let normal = PointF::new(-direction.y, direction.x).scale(half);
+            self.points.extend([
+                from.add(normal),
+                to.add(normal),
+                to.sub(normal),
+                from.sub(normal),
+            ]);
+            self.piece();
+        }
+
+        // fill the gaps on the outside of the corners
+        let joins = if closed {
+            segments.len()
+        } else {
+            segments.len() - 1
+        };
+        for i in 0..joins {
+            let (_, corner, first) = segments[i];
+            let (_, _, second) = segments[(i + 1) % segments.len()];
+            let cross = first.x * second.y - first.y * second.x;
+            let dot = first.x * second.x + first.y * second.y;
+            let turn = atan2f(cross, dot);
+            if turn == 0.0 {
+                continue;
+            }
+            // the outside is opposite to the turn
+            let outside = if turn > 0.0 {
+                PointF::new(first.y, -first.x)
+            } else {
+                PointF::new(-first.y, first.x)
+            };
+            self.sector(corner, half, atan2f(outside.y, outside.x), turn);
+        }
+
+        if !closed {
+            let (start, _, direction) = segments[0];
+            self.cap(start, direction.scale(-1.0), half, cap);
+            let (_, end, direction) = segments[segments.len() - 1];
+            self.cap(end, direction, half, cap);
+        }
+    }
+
+    /// Add the cap at the end of a line pointing in `direction`.
+    fn cap(&mut self, end: PointF, direction: PointF, half: f32, cap: LineCap) {
+        let normal = PointF::new(-direction.y, direction.x).scale(half);
+        match cap {
+            LineCap::Butt => {}
+            LineCap::Round => self.sector(end, half, atan2f(-direction.x, direction.y), PI),
+            LineCap::Square => {
+                let outer = end.add(direction.scale(half));
+                self.points.extend([
+                    end.add(normal),
+                    outer.add(normal),
+                    outer.sub(normal),
+                    end.sub(normal),
+                ]);
+                self.piece();
+            }
+        }
+    }
+
+    /// The outline of a circle with half width `half`.
+    fn ring(center: PointF, radius: f32, half: f32) -> Path {
+        let mut path = Path::default();
+        path.circle(center, radius + half, false);
+        if radius > half {
+            path.circle(center, radius - half, true);
+        }
+        path
+    }
+
+    /// A circular sector, from angle `start` (radians) over `sweep`.
+    fn pie(center: PointF, radius: f32, start: f32, sweep: f32) -> Path {
+        let mut path = Path::default();
+        path.points.push(center);
+        path.arc(center, radius, start, sweep);
+        let points = core::mem::take(&mut path.points);
+        path.contour(&points);
+        path
+    }
+
+    /// The stroke of an arc with half width `half` and the given caps, a
+    /// ring if it sweeps the full circle.
+    fn arc_stroke(
+        center: PointF,
+        radius: f32,
+        start: f32,
+        sweep: f32,
+        half: f32,
+        cap: LineCap,
+    ) -> Path {
+        if sweep.abs() >= 2.0 * PI {
+            return Path::ring(center, radius, half);
+        }
+        let mut path = Path::default();
+        path.arc(center, radius + half, start, sweep);
+        if radius > half {
+            path.arc(center, radius - half, start + sweep, -sweep);
+        } else {
+            path.points.push(center);
+        }
+        path.piece();
+        // the ends point along the tangents, away from the arc
+        let turn = if sweep < 0.0 { -1.0 } else { 1.0 };
+        for (angle, outward) in [(start, -turn), (start + sweep, turn)] {
+            let (sin, cos) = (sinf(angle), cosf(angle));
+            let end = PointF::new(center.x + radius * cos, center.y + radius * sin);
+            path.cap(end, PointF::new(-sin, cos).scale(outward), half, cap);
+        }
+        path
+    }
+
+    /// A rectangle with corners rounded by `radius`.
+    fn rounded_rect(top_left: PointF, width: f32, height: f32, radius: f32) -> Path {
+        let mut path = Path::default();
+        rounded_rect(&mut path, top_left, width, height, radius);
+        let points = core::mem::take(&mut path.points);
+        path.contour(&points);
+        path
+    }
+
+    /// The outline of a rounded rectangle with half width `half`, centered on
+    /// its edge.
+    fn rounded_rect_stroke(
+        top_left: PointF,
+        width: f32,
+        height: f32,
+        radius: f32,
+        half: f32,
+    ) -> Path {
+        let mut path = Path::default();
+        let outset = PointF::new(half, half);
+        rounded_rect(
+            &mut path,
+            top_left.sub(outset),
+            width + 2.0 * half,
+            height + 2.0 * half,
+            radius + half,
+        );
+        let outside = core::mem::take(&mut path.points);
+        path.oriented(&outside, false);
+        if width > 2.0 * half && height > 2.0 * half {
+            rounded_rect(
+                &mut path,
+                top_left.add(outset),
+                width - 2.0 * half,
+                height - 2.0 * half,
+                radius - half,
+            );
+            let inside = core::mem::take(&mut path.points);
+            path.oriented(&inside, true);
+        }
+        path
+    }
+
+    /// Blend the covered pixels with `color` into the framebuffer.
+    fn fill(&self, display: &mut Display<'_>, color: u8) {
+        self.rows(Display::WIDTH, Display::HEIGHT, |x, y, width, coverage| {
+            display.blend_packed_row(x, y, width, coverage, color)
+        });
+    }
+
+    /// Rasterize the path clipped to `width` and `height`, calling `row` with
+    /// the position, width and nibble packed coverage of each covered row of
+    /// pixels, 0 for none to 15 for full, like [Display::blend_packed_row].
+    fn rows(&self, width: u16, height: u16, mut row: impl FnMut(i32, i32, u16, &[u8])) {
+        if self.edges.is_empty() {
+            return;
+        }
+        let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+        let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+        for edge in &self.edges {
+            min_x = min_x.min(edge.x0).min(edge.x1);
+            max_x = max_x.max(edge.x0).max(edge.x1);
+            min_y = min_y.min(edge.y0).min(edge.y1);
+            max_y = max_y.max(edge.y0).max(edge.y1);
+        }
+        let left = floorf(min_x).max(0.0);
+        let right = (ceilf(max_x) + 1.0).min(width as f32);
+        let top = floorf(min_y).max(0.0);
+        let bottom = ceilf(max_y).min(height as f32);
+        if left >= right || top >= bottom {
+            return;
+        }
+        let width = (right - left) as usize;
+        let edges = {
+            let mut edges = self.edges.clone();
+            edges.sort_unstable_by(|a, b| a.y0.min(a.y1).total_cmp(&b.y0.min(b.y1)));
+            edges
+        };
+
+        let mut deltas = vec![0f32; width + 2];
+        let mut packed = vec![0u8; width.div_ceil(2)];
+        let mut active: Vec<Edge> = Vec::new();
+        let mut crossings: Vec<(f32, i32)> = Vec::new();
+        let mut next = 0;
+        for y in top as i32..bottom as i32 {
+            let (row_top, row_bottom) = (y as f32, y as f32 + 1.0);
+            while next < edges.len() && edges[next].y0.min(edges[next].y1) < row_bottom {
+                active.push(edges[next]);
+                next += 1;
+            }
+            active.retain(|edge| edge.y0.max(edge.y1) > row_top);
+            if active.is_empty() {
+                continue;
+            }
+            deltas.fill(0.0);
+            for sample in 0..SUBSAMPLES {
+                let sample_y = row_top + (sample as f32 + 0.5) / SUBSAMPLES as f32;
+                crossings.clear();
+                for edge in &active {
+                    if (edge.y0 <= sample_y) != (edge.y1 <= sample_y) {
+                        let t = (sample_y - edge.y0) / (edge.y1 - edge.y0);
+                        let x = edge.x0 + (edge.x1 - edge.x0) * t - left;
+                        crossings.push((x, if edge.y1 > edge.y0 { 1 } else { -1 }));
+                    }
+                }
+                crossings.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+                let mut winding = 0;
+                let mut start = 0.0;
+                for &(x, direction) in &crossings {
+                    if winding == 0 {
+                        start = x;
+                    }
+                    winding += direction;
+                    if winding == 0 {
+                        span(&mut deltas, start, x, width as f32);
+                    }
+                }
+            }
+            let mut sum = 0.0;
+            packed.fill(0);
+            for (x, delta) in deltas[..width].iter().enumerate() {
+                sum += delta;
+                let coverage = (sum.clamp(0.0, 1.0) * 15.0 + 0.5) as u8;
+                packed[x / 2] |= coverage << (4 * (x % 2));
+            }
+            row(left as i32, y, width as u16, &packed);
+        }
+    }
+}
+
+/// Add the span from `from` to `to` on one sample row to the coverage
+/// changes of the pixels, so the running sum over a row is the coverage.
+fn span(deltas: &mut [f32], from: f32, to: f32, width: f32) {
+    let (from, to) = (from.clamp(0.0, width), to.clamp(0.0, width));
+    if from >= to {
+        return;
+    }
+    let weight = 1.0 / SUBSAMPLES as f32;
+    for (x, sign) in [(from, weight), (to, -weight)] {
+        let index = floorf(x);
+        let fraction = x - index;
+        deltas[index as usize] += sign * (1.0 - fraction);
+        deltas[index as usize + 1] += sign * fraction;
+    }
+}
+
+/// Points of a rounded rectangle, clockwise from the top right corner.
+fn rounded_rect(path: &mut Path, top_left: PointF, width: f32, height: f32, radius: f32) {
+    let radius = radius.clamp(0.0, width.min(height) / 2.0);
+    let (left, top) = (top_left.x + radius, top_left.y + radius);
+    let (right, bottom) = (top_left.x + width - radius, top_left.y + height - radius);
+    for (x, y, start) in [
+        (right, top, -PI / 2.0),
+        (right, bottom, 0.0),
+        (left, bottom, PI / 2.0),
+        (left, top, PI),
+    ] {
+        path.arc(PointF::new(x, y), radius, start, PI / 2.0);
+    }
+}
+
+impl<'a> Display<'a> {
+    /// Draw an anti-aliased line with the stroke color, width and caps of the
+    /// style, blending the coverage of each pixel with the framebuffer.
+    ///
+    /// Returns [Error::InvalidColor] if a color of the style is greater than
+    /// 0x0F.
+    pub fn draw_line_aa(
+        &mut self,
+        from: impl Into<PointF>,
+        to: impl Into<PointF>,
+        style: &ShapeStyle,
+    ) -> Result<()> {
+        self.draw_polyline_aa(&[from.into(), to.into()], style)
+    }
+
+    /// Draw anti-aliased connected lines through the points with round
+    /// corners, e.g. for a chart, see [Display::draw_line_aa].
+    pub fn draw_polyline_aa(&mut self, points: &[PointF], style: &ShapeStyle) -> Result<()> {
+        style.check()?;
+        if let Some((color, half)) = style.outline() {
+            let mut path = Path::default();
+            path.stroke(points, false, half, style.cap);
+            path.fill(self, color);
+        }
+        Ok(())
+    }
+
+    /// Draw an anti-aliased polygon, filled with the non-zero winding rule
+    /// and outlined with round corners.
+    ///
+    /// Returns [Error::InvalidColor] if a color of the style is greater than
+    /// 0x0F.
+    ///
+    /// ```rust ignore
+    /// let style = ShapeStyle {
+    ///     fill: Some(12),
+    ///     stroke: Some(0),
+    ///     stroke_width: 2.0,
+    ///     ..Default::default()
+    /// };
+    /// let triangle = [PointF::new(100.0, 50.0), PointF::new(150.0, 140.0), PointF::new(50.0, 140.0)];
+    /// display.draw_polygon_aa(&triangle, &style)?;
+    /// ```
+    pub fn draw_polygon_aa(&mut self, points: &[PointF], style: &ShapeStyle) -> Result<()> {
+        style.check()?;
+        if let Some(color) = style.fill {
+            let mut path = Path::default();
+            path.contour(points);
+            path.fill(self, color);
+        }
+        if let Some((color, half)) = style.outline() {
+            let mut path = Path::default();
+            path.stroke(points, true, half, style.cap);
+            path.fill(self, color);
+        }
+        Ok(())
+    }
+
+    /// Draw an anti-aliased circle, see [Display::draw_polygon_aa].
+    pub fn draw_circle_aa(
+        &mut self,
+        center: impl Into<PointF>,
+        radius: f32,
+        style: &ShapeStyle,
+    ) -> Result<()> {
+        style.check()?;
+        let center = center.into();
+        if let Some(color) = style.fill {
+            let mut path = Path::default();
+            path.circle(center, radius, false);
+            path.fill(self, color);
+        }
+        if let Some((color, half)) = style.outline() {
+            Path::ring(center, radius, half).fill(self, color);
+        }
+        Ok(())
+    }
+
+    /// Draw an anti-aliased arc of a circle from the angle `start` over
+    /// `sweep`, in degrees clockwise from 3 o'clock, with the stroke and caps
+    /// of the style. The fill color fills the sector, e.g. for a pie chart.
+    ///
+    /// Returns [Error::InvalidColor] if a color of the style is greater than
+    /// 0x0F.
+    ///
+    /// ```rust ignore
+    /// // gauge from 7 to 5 o'clock, 60% filled
+    /// let gauge = ShapeStyle {
+    ///     cap: LineCap::Round,
+    ///     ..ShapeStyle::with_stroke(12, 16.0)
+    /// };
+    /// display.draw_arc_aa((480.0, 270.0), 200.0, 120.0, 300.0, &gauge)?;
+    /// let value = ShapeStyle { stroke: Some(0), ..gauge };
+    /// display.draw_arc_aa((480.0, 270.0), 200.0, 120.0, 180.0, &value)?;
+    /// ```
+    pub fn draw_arc_aa(
+        &mut self,
+        center: impl Into<PointF>,
+        radius: f32,
+        start: f32,
+        sweep: f32,
+        style: &ShapeStyle,
+    ) -> Result<()> {
+        style.check()?;
+        let center = center.into();
+        let (start, sweep) = (
+            start.to_radians(),
+            sweep.to_radians().clamp(-2.0 * PI, 2.0 * PI),
+        );
+        if let Some(color) = style.fill {
+            Path::pie(center, radius, start, sweep).fill(self, color);
+        }
+        if let Some((color, half)) = style.outline() {
+            Path::arc_stroke(center, radius, start, sweep, half, style.cap).fill(self, color);
+        }
+        Ok(())
+    }
+
+    /// Draw an anti-aliased rectangle with corners rounded by `radius`, see
+    /// [Display::draw_polygon_aa]. The outline is centered on the edge, so
+    /// the corners of the outside are rounded by `radius` plus half the
+    /// stroke width.
+    pub fn draw_rounded_rect_aa(
+        &mut self,
+        top_left: impl Into<PointF>,
+        width: f32,
+        height: f32,
+        radius: f32,
+        style: &ShapeStyle,
+    ) -> Result<()> {
+        style.check()?;
+        let top_left = top_left.into();
+        if width <= 0.0 || height <= 0.0 {
+            return Ok(());
+        }
+        if let Some(color) = style.fill {
+            Path::rounded_rect(top_left, width, height, radius).fill(self, color);
+        }
+        if let Some((color, half)) = style.outline() {
+            Path::rounded_rect_stroke(top_left, width, height, radius, half).fill(self, color);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testdata::assert_golden;
+
+    const SIZE: usize = 48;
+
+    /// Coverage of the pixels of a `SIZE` x `SIZE` image, 0 to 15.
+    fn render(path: &Path) -> Vec<u8> {
+        let mut pixels = vec![0; SIZE * SIZE];
+        path.rows(SIZE as u16, SIZE as u16, |x, y, width, coverage| {
+            let start = y as usize * SIZE + x as usize;
+            let row = &mut pixels[start..start + width as usize];
+            for (i, pixel) in row.iter_mut().enumerate() {
+                *pixel = coverage[i / 2] >> (4 * (i % 2)) & 0x0F;
+            }
+        });
+        pixels
+    }
+
+    /// Compare with the golden image and the covered area with `area`.
+    fn check(name: &str, path: &Path, area: f32) {
+        let pixels = render(path);
+        assert_golden(name, SIZE, 15, &pixels);
+        let covered = pixels.iter().map(|&c| c as f32).sum::<f32>() / 15.0;
+        assert!(
+            (covered - area).abs() <= area * 0.01,
+            "{}: covers {} pixels, expected {}",
+            name,
+            covered,
+            area
+        );
+    }
+
+    #[test]
+    fn line() {
+        let mut path = Path::default();
+        let (from, to) = (PointF::new(4.0, 40.0), PointF::new(44.0, 10.0));
+        path.stroke(&[from, to], false, 1.5, LineCap::Butt);
+        check("shapes/line.pgm", &path, 50.0 * 3.0);
+    }
+
+    #[test]
+    fn circle() {
+        let mut path = Path::default();
+        path.circle(PointF::new(24.0, 24.0), 18.0, false);
+        check("shapes/circle.pgm", &path, PI * 18.0 * 18.0);
+    }
+
+    #[test]
+    fn ring() {
+        let path = Path::ring(PointF::new(24.0, 24.0), 16.0, 2.5);
+        let area = PI * (18.5 * 18.5 - 13.5 * 13.5);
+        check("shapes/ring.pgm", &path, area);
+    }
+
+    #[test]
+    fn arc_with_round_caps() {
+        let (start, sweep) = (120f32.to_radians(), 300f32.to_radians());
+        let path = Path::arc_stroke(
+            PointF::new(24.0, 24.0),
+            16.0,
+            start,
+            sweep,
+            3.0,
+            LineCap::Round,
+        );
+        // the band and two half circles
+        let area = sweep / 2.0 * (19.0 * 19.0 - 13.0 * 13.0) + PI * 3.0 * 3.0;
+        check("shapes/arc.pgm", &path, area);
+    }
+
+    #[test]
+    fn rounded_rect() {
+        let top_left = PointF::new(4.0, 8.0);
+        let path = Path::rounded_rect(top_left, 40.0, 32.0, 8.0);
+        let area = 40.0 * 32.0 - (4.0 - PI) * 8.0 * 8.0;
+        check("shapes/rounded-rect.pgm", &path, area);
+
+        let path = Path::rounded_rect_stroke(top_left, 40.0, 32.0, 8.0, 1.0);
+        let outside = 42.0 * 34.0 - (4.0 - PI) * 9.0 * 9.0;
+        let inside = 38.0 * 30.0 - (4.0 - PI) * 7.0 * 7.0;
+        check("shapes/rounded-rect-stroke.pgm", &path, outside - inside);
+    }
+}
@@ -0,0 +1,71 @@
+//! Golden images of the unit tests, stored as binary PGM files in `testdata/`.
+
+use std::{env, fs, path::PathBuf, vec::Vec};
+
+fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata")
+        .join(name)
+}
+
+/// Contents of a file.
+pub(crate) fn read(name: &str) -> Vec<u8> {
+    fs::read(path(name)).unwrap_or_else(|err| panic!("{}: {}", name, err))
+}
+
+/// Width, height and pixels of a PGM file.
+pub(crate) fn read_pgm(name: &str) -> (usize, usize, Vec<u8>) {
+    let data = read(name);
+    // "P5", width, height and maximum value separated by single spaces or
+    // newlines, followed by the pixels
+    let mut fields = data.splitn(5, |byte| byte.is_ascii_whitespace());
+    assert_eq!(
+        fields.next(),
+        Some(&b"P5"[..]),
+        "{}: not a binary PGM",
+        name
+    );
+    let mut number = || -> usize {
+        let field = fields.next().unwrap();
+        core::str::from_utf8(field).unwrap().parse().unwrap()
+    };
+    let (width, height, _max) = (number(), number(), number());
+    let pixels = fields.next().unwrap().to_vec();
+    assert_eq!(pixels.len(), width * height, "{}: truncated", name);
+    (width, height, pixels)
+}
+
+fn write_pgm(name: &str, width: usize, max: u8, pixels: &[u8]) {
+    let mut data = std::format!("P5\n{} {}\n{}\n", width, pixels.len() / width, max).into_bytes();
+    data.extend_from_slice(pixels);
+    let path = path(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, data).unwrap();
+}
+
+/// Compare pixels with a golden image. With `UPDATE_GOLDEN` set in the
+/// environment the golden image is written instead, review the changes before
+/// committing them.
+pub(crate) fn assert_golden(name: &str, width: usize, max: u8, pixels: &[u8]) {
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        write_pgm(name, width, max, pixels);
+        return;
+    }
+    let (golden_width, golden_height, golden) = read_pgm(name);
+    assert_eq!(
+        (golden_width, golden_height),
+        (width, pixels.len() / width),
+        "{}: size differs",
+        name
+    );
+    if let Some(i) = golden.iter().zip(pixels).position(|(a, b)| a != b) {
+        panic!(
+            "{}: pixel {}x{} is {}, expected {}",
+            name,
+            i % width,
+            i / width,
+            pixels[i],
+            golden[i]
+        );
+    }
+}
@@ -0,0 +1,505 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embedded_hal::i2c::{Error as _, I2c, Operation as I2cOperation};
+
+use crate::{Display, Error, Result};
+
+/// Maximum number of simultaneous touch points reported by the controller.
+pub const MAX_POINTS: usize = 5;
+
+/// I2C addresses of the GT911. The controller selects one of them depending on
+/// the level of its interrupt line during reset.
+const ADDRESSES: [u8; 2] = [0x5D, 0x14];
+
+const REG_COMMAND: u16 = 0x8040;
+const REG_CONFIG: u16 = 0x8047;
+const REG_RESOLUTION: u16 = 0x8048;
+const REG_MODULE_SWITCH: u16 = 0x804D;
+const REG_CONFIG_CHECKSUM: u16 = 0x80FF;
+const REG_PRODUCT_ID: u16 = 0x8140;
+const REG_STATUS: u16 = 0x814E;
+const REG_POINTS: u16 = 0x814F;
+
+/// Size of the configuration block, excluding checksum and fresh flag.
+const CONFIG_SIZE: usize = (REG_CONFIG_CHECKSUM - REG_CONFIG) as usize;
+const POINT_SIZE: usize = 8;
+const STATUS_READY: u8 = 0x80;
+const COMMAND_SLEEP: u8 = 0x05;
+
+/// A single touch point in display coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchPoint {
+    /// Track id, stable while the finger stays on the screen.
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+    /// Size of the contact area as reported by the controller.
+    pub size: u16,
+}
+
+/// The touch points of a single report.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Touches {
+    points: [TouchPoint; MAX_POINTS],
+    len: u8,
+}
+
+impl Touches {
+    /// Number of touch points.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns whether all fingers have been lifted.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The touch points of the report.
+    pub fn points(&self) -> &[TouchPoint] {
+        &self.points[..self.len()]
+    }
+
+    /// Iterate over the touch points of the report.
+    pub fn iter(&self) -> impl Iterator<Item = &TouchPoint> + '_ {
+        self.points().iter()
+    }
+
+    /// Touch point with the given track id.
+    pub fn get(&self, id: u8) -> Option<&TouchPoint> {
+        self.iter().find(|point| point.id == id)
+    }
+}
+
+/// Build a report from recorded points, e.g. to replay a touch trace. Points
+/// beyond [MAX_POINTS] are ignored.
+impl FromIterator<TouchPoint> for Touches {
+    fn from_iter<T: IntoIterator<Item = TouchPoint>>(iter: T) -> Self {
+        let mut touches = Touches::default();
+        for point in iter.into_iter().take(MAX_POINTS) {
+            touches.points[touches.len()] = point;
+            touches.len += 1;
+        }
+        touches
+    }
+}
+
+/// How the controller signals new reports on its interrupt line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InterruptTrigger {
+    RisingEdge,
+    FallingEdge,
+    LowLevel,
+    HighLevel,
+}
+
+/// Orientation of the touch overlay relative to the panel.
+///
+/// The default matches the overlay of the LilyGo T5 4.7 S3, which reports
+/// with swapped axes and the vertical axis mirrored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchTransform {
+    /// Exchange the raw x and y axes.
+    pub swap_xy: bool,
+    /// Mirror horizontally, applied after swapping.
+    pub mirror_x: bool,
+    /// Mirror vertically, applied after swapping.
+    pub mirror_y: bool,
+}
+
+impl Default for TouchTransform {
+    fn default() -> Self {
+        TouchTransform {
+            swap_xy: true,
+            mirror_x: false,
+            mirror_y: true,
+        }
+    }
+}
+
+/// Flag to hand the interrupt of the touch controller to [Touch].
+///
+/// Set it from the GPIO interrupt handler of the interrupt line (GPIO47 on the
+/// T5 4.7 S3) and let [`Touch::read_on_interrupt`] read the controller only
+/// when a report is pending.
+///
+/// ```rust ignore
+/// static TOUCH_INT: Mutex<RefCell<Option<Input>>> = Mutex::new(RefCell::new(None));
+/// static TOUCH_IRQ: TouchInterrupt = TouchInterrupt::new();
+///
+/// #[handler]
+/// fn gpio_handler() {
+///     critical_section::with(|cs| {
+///         TOUCH_INT.borrow_ref_mut(cs).as_mut().unwrap().clear_interrupt()
+///     });
+///     TOUCH_IRQ.signal();
+/// }
+///
+/// if let Some(touches) = touch.read_on_interrupt(&TOUCH_IRQ, &display)? {
+///     for point in touches.iter() {
+///         log::info!("{} at {}x{}", point.id, point.x, point.y);
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TouchInterrupt(AtomicBool);
+
+impl Default for TouchInterrupt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TouchInterrupt {
+    pub const fn new() -> Self {
+        TouchInterrupt(AtomicBool::new(false))
+    }
+
+    /// Mark a report as pending. Safe to call from an interrupt handler.
+    pub fn signal(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Returns and resets the pending flag.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Acquire)
+    }
+}
+
+/// Driver for the GT911 capacitive touch controller of the touch overlay.
+///
+/// Coordinates are mapped into the coordinate space of the [Display] passed to
+/// [`Touch::read_touches`], following its [Rotation](crate::Rotation).
+pub struct Touch<I2C> {
+    i2c: I2C,
+    address: u8,
+    /// Raw resolution as configured in the controller.
+    resolution: (u16, u16),
+    transform: TouchTransform,
+}
+
+impl<I2C: I2c> Touch<I2C> {
+    /// Detect the controller on either of its addresses and read its
+    /// configured resolution.
+    pub fn new(mut i2c: I2C) -> Result<Self> {
+        let mut id = [0u8; 4];
+        let address = ADDRESSES
+            .into_iter()
+            .find(|&address| read_register(&mut i2c, address, REG_PRODUCT_ID, &mut id).is_ok())
+            .ok_or(Error::TouchNotFound)?;
+        let mut touch = Touch {
+            i2c,
+            address,
+            resolution: (Display::HEIGHT, Display::WIDTH),
+            transform: TouchTransform::default(),
+        };
+        let mut resolution = [0u8; 4];
+        touch.read(REG_RESOLUTION, &mut resolution)?;
+        let x = u16::from_le_bytes([resolution[0], resolution[1]]);
+        let y = u16::from_le_bytes([resolution[2], resolution[3]]);
+        if x > 0 && y > 0 {
+            touch.resolution = (x, y);
+        }
+        Ok(touch)
+    }
+
+    /// Release the I2C bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    /// I2C address the controller has been found on.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Product id of the controller, e.g. `*b"911\0"`.
+    pub fn product_id(&mut self) -> Result<[u8; 4]> {
+        let mut id = [0u8; 4];
+        self.read(REG_PRODUCT_ID, &mut id)?;
+        Ok(id)
+    }
+
+    /// Set the orientation of the overlay relative to the panel.
+    pub fn set_transform(&mut self, transform: TouchTransform) {
+        self.transform = transform;
+    }
+
+    /// Read the current touch points in the coordinates of `display`. Returns
+    /// `None` if the controller has no new report, and empty [Touches] once
+    /// all fingers have been lifted.
+    pub fn read_touches(&mut self, display: &Display) -> Result<Option<Touches>> {
+        let mut status = [0u8];
+        self.read(REG_STATUS, &mut status)?;
+        if status[0] & STATUS_READY == 0 {
+            return Ok(None);
+        }
+        let len = ((status[0] & 0x0F) as usize).min(MAX_POINTS);
+        let mut raw = [0u8; MAX_POINTS * POINT_SIZE];
+        if len > 0 {
+            self.read(REG_POINTS, &mut raw[..len * POINT_SIZE])?;
+        }
+        // acknowledge the report so the controller can write the next one
+        self.write(REG_STATUS, &[0])?;
+
+        let mut touches = Touches {
+            len: len as u8,
+            ..Touches::default()
+        };
+        for (point, raw) in touches
+            .points
+            .iter_mut()
+            .zip(raw.chunks_exact(POINT_SIZE))
+            .take(len)
+        {
+            let (x, y) = self.map(
+                u16::from_le_bytes([raw[1], raw[2]]),
+                u16::from_le_bytes([raw[3], raw[4]]),
+            );
+            let (x, y) = display.rotation().from_panel(x, y);
+            *point = TouchPoint {
+                id: raw[0],
+                x,
+                y,
+                size: u16::from_le_bytes([raw[5], raw[6]]),
+            };
+        }
+        Ok(Some(touches))
+    }
+
+    /// Like [`Touch::read_touches`], but only talks to the controller if
+    /// `interrupt` has been signaled since the last call.
+    pub fn read_on_interrupt(
+        &mut self,
+        interrupt: &TouchInterrupt,
+        display: &Display,
+    ) -> Result<Option<Touches>> {
+        if !interrupt.take() {
+            return Ok(None);
+        }
+        self.read_touches(display)
+    }
+
+    /// Configure how the controller drives its interrupt line. The setting is
+    /// written to the volatile configuration and lost on reset.
+    pub fn set_interrupt_trigger(&mut self, trigger: InterruptTrigger) -> Result<()> {
+        let mut config = [0u8; CONFIG_SIZE + 2];
+        self.read(REG_CONFIG, &mut config[..CONFIG_SIZE])?;
+        let switch = (REG_MODULE_SWITCH - REG_CONFIG) as usize;
+        config[switch] = (config[switch] & !0x03) | trigger as u8;
+        // two's complement of the byte sum, followed by the "config fresh" flag
+        let sum = config[..CONFIG_SIZE]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        config[CONFIG_SIZE] = (!sum).wrapping_add(1);
+        config[CONFIG_SIZE + 1] = 1;
+        self.write(REG_CONFIG, &config)
+    }
+
+    /// Put the controller into sleep mode. The interrupt line has to be held
+    /// low while sending the command, it is woken up by driving it high.
+    pub fn sleep(&mut self) -> Result<()> {
+        self.write(REG_COMMAND, &[COMMAND_SLEEP])
+    }
+
+    /// Map raw controller coordinates to panel coordinates.
+    fn map(&self, x: u16, y: u16) -> (u16, u16) {
+        let (mut x, mut y, mut width, mut height) = (x, y, self.resolution.0, self.resolution.1);
+        if self.transform.swap_xy {
+            (x, y, width, height) = (y, x, height, width);
+        }
+        let scale = |value: u16, raw: u16, panel: u16| {
+            ((value as u32 * panel as u32 / raw.max(1) as u32) as u16).min(panel - 1)
+        };
+        let mut x = scale(x, width, Display::WIDTH);
+        let mut y = scale(y, height, Display::HEIGHT);
+        if self.transform.mirror_x {
+            x = Display::WIDTH - 1 - x;
+        }
+        if self.transform.mirror_y {
+            y = Display::HEIGHT - 1 - y;
+        }
+        (x, y)
+    }
+
+    fn read(&mut self, register: u16, buffer: &mut [u8]) -> Result<()> {
+        read_register(&mut self.i2c, self.address, register, buffer)
+    }
+
+    fn write(&mut self, register: u16, data: &[u8]) -> Result<()> {
+        self.i2c
+            .transaction(
+                self.address,
+                &mut [
+                    I2cOperation::Write(&register.to_be_bytes()),
+                    I2cOperation::Write(data),
+                ],
+            )
+            .map_err(|err| Error::I2c(err.kind()))
+    }
+}
+
+fn read_register<I2C: I2c>(
+    i2c: &mut I2C,
+    address: u8,
+    register: u16,
+    buffer: &mut [u8],
+) -> Result<()> {
+    i2c.write_read(address, &register.to_be_bytes(), buffer)
+        .map_err(|err| Error::I2c(err.kind()))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+    use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
+
+    use super::*;
+    use crate::Rotation;
+
+    /// Product id and resolution of the T5 4.7 S3 overlay.
+    fn probe(address: u8) -> Vec<Transaction> {
+        vec![
+            Transaction::write_read(address, vec![0x81, 0x40], b"911\0".to_vec()),
+            Transaction::write_read(address, vec![0x80, 0x48], vec![0x1C, 0x02, 0xC0, 0x03]),
+        ]
+    }
+
+    fn write(address: u8, register: u16, data: &[u8]) -> Vec<Transaction> {
+        vec![
+            Transaction::transaction_start(address),
+            Transaction::write(address, register.to_be_bytes().to_vec()),
+            Transaction::write(address, data.to_vec()),
+            Transaction::transaction_end(address),
+        ]
+    }
+
+    fn not_found(address: u8) -> Transaction {
+        Transaction::write_read(address, vec![0x81, 0x40], vec![0; 4])
+            .with_error(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+    }
+
+    #[test]
+    fn probes_both_addresses() {
+        let touch = Touch::new(Mock::new(&probe(0x5D))).unwrap();
+        assert_eq!(touch.address(), 0x5D);
+        assert_eq!(touch.resolution, (540, 960));
+        touch.release().done();
+
+        let mut expectations = vec![not_found(0x5D)];
+        expectations.extend(probe(0x14));
+        let touch = Touch::new(Mock::new(&expectations)).unwrap();
+        assert_eq!(touch.address(), 0x14);
+        touch.release().done();
+
+        let mut i2c = Mock::new(&[not_found(0x5D), not_found(0x14)]);
+        assert!(matches!(Touch::new(i2c.clone()), Err(Error::TouchNotFound)));
+        i2c.done();
+    }
+
+    #[test]
+    fn reads_points_and_clears_status() {
+        let mut expectations = probe(0x5D);
+        expectations.extend([
+            Transaction::write_read(0x5D, vec![0x81, 0x4E], vec![0x82]),
+            Transaction::write_read(
+                0x5D,
+                vec![0x81, 0x4F],
+                vec![
+                    3, 100, 0, 200, 0, 30, 0, 0, // id 3 at 100x200, size 30
+                    7, 0x0B, 0x02, 0xBF, 0x03, 12, 1, 0, // id 7 at 523x959, size 268
+                ],
+            ),
+        ]);
+        expectations.extend(write(0x5D, 0x814E, &[0]));
+        let mut touch = Touch::new(Mock::new(&expectations)).unwrap();
+        let display = Display::mock();
+
+        let touches = touch.read_touches(&display).unwrap().unwrap();
+        // raw axes are swapped, the vertical one mirrored
+        assert_eq!(
+            touches.points(),
+            [
+                TouchPoint {
+                    id: 3,
+                    x: 200,
+                    y: 439,
+                    size: 30
+                },
+                TouchPoint {
+                    id: 7,
+                    x: 959,
+                    y: 16,
+                    size: 268
+                },
+            ]
+        );
+        assert_eq!(touches.get(7).unwrap().x, 959);
+        touch.release().done();
+    }
+
+    #[test]
+    fn points_follow_the_display_rotation() {
+        let mut expectations = probe(0x5D);
+        for _ in 0..2 {
+            expectations.extend([
+                Transaction::write_read(0x5D, vec![0x81, 0x4E], vec![0x81]),
+                Transaction::write_read(0x5D, vec![0x81, 0x4F], vec![1, 100, 0, 200, 0, 0, 0, 0]),
+            ]);
+            expectations.extend(write(0x5D, 0x814E, &[0]));
+        }
+        let mut touch = Touch::new(Mock::new(&expectations)).unwrap();
+        let mut display = Display::mock();
+
+        display.set_rotation(Rotation::Deg90);
+        let point = touch.read_touches(&display).unwrap().unwrap().points()[0];
+        assert_eq!((point.x, point.y), (100, 200));
+        assert_eq!(Rotation::Deg90.to_panel(point.x, point.y), (200, 439));
+
+        display.set_rotation(Rotation::Deg180);
+        let point = touch.read_touches(&display).unwrap().unwrap().points()[0];
+        assert_eq!((point.x, point.y), (759, 100));
+        touch.release().done();
+    }
+
+    #[test]
+    fn status_without_report_is_not_cleared() {
+        let mut expectations = probe(0x5D);
+        expectations.push(Transaction::write_read(0x5D, vec![0x81, 0x4E], vec![0x00]));
+        // all fingers lifted
+        expectations.push(Transaction::write_read(0x5D, vec![0x81, 0x4E], vec![0x80]));
+        expectations.extend(write(0x5D, 0x814E, &[0]));
+        let mut touch = Touch::new(Mock::new(&expectations)).unwrap();
+        let display = Display::mock();
+
+        assert_eq!(touch.read_touches(&display), Ok(None));
+        assert_eq!(touch.read_touches(&display), Ok(Some(Touches::default())));
+        touch.release().done();
+    }
+
+    #[test]
+    fn reads_only_on_interrupt() {
+        let mut expectations = probe(0x5D);
+        expectations.push(
+            Transaction::write_read(0x5D, vec![0x81, 0x4E], vec![0]).with_error(ErrorKind::Bus),
+        );
+        let mut touch = Touch::new(Mock::new(&expectations)).unwrap();
+        let display = Display::mock();
+        let interrupt = TouchInterrupt::new();
+
+        assert_eq!(touch.read_on_interrupt(&interrupt, &display), Ok(None));
+        interrupt.signal();
+        assert_eq!(
+            touch.read_on_interrupt(&interrupt, &display),
+            Err(Error::I2c(ErrorKind::Bus))
+        );
+        assert_eq!(touch.read_on_interrupt(&interrupt, &display), Ok(None));
+        touch.release().done();
+    }
+}
@@ -0,0 +1,105 @@
+//! Host side counterpart of the driver's `Dither`, so converted images look
+//! the same as images dithered on the device.
+
+use clap::ValueEnum;
+
+/// Perceived lightness of the panel levels, see `PanelLevels` in the driver.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Panel {
+    /// Approximation of the ED047TC1 with the driver's waveform.
+    Ed047tc1,
+    /// Evenly spaced levels.
+    Linear,
+}
+
+impl Panel {
+    pub fn lightness(&self) -> &'static [u8; 16] {
+        match self {
+            Self::Ed047tc1 => &[
+                0, 29, 51, 70, 89, 106, 123, 139, 154, 169, 184, 199, 213, 227, 241, 255,
+            ],
+            Self::Linear => &[
+                0, 17, 34, 51, 68, 85, 102, 119, 136, 153, 170, 187, 204, 221, 238, 255,
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// Nearest level without dithering.
+    None,
+    /// Error diffusion, best for photos.
+    FloydSteinberg,
+    /// Error diffusion keeping more contrast, best for line art and comics.
+    Atkinson,
+    /// Ordered 4x4 dithering, best for UI elements.
+    Bayer,
+}
+
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Dither 8 bit lightness values into panel levels.
+pub fn dither(luma: &[u8], width: usize, method: Method, lightness: &[u8; 16]) -> Vec<u8> {
+    let below = |value: u8| lightness.iter().rposition(|&l| l <= value).unwrap_or(0);
+    let nearest = |value: u8| {
+        let low = below(value);
+        match lightness.get(low + 1) {
+            Some(&high) if high - value < value.saturating_sub(lightness[low]) => low + 1,
+            _ => low,
+        }
+    };
+    let height = luma.len() / width.max(1);
+    // errors in 1/16 of a lightness step, padded by two pixels and rows
+    let stride = width + 4;
+    let mut errors = vec![0i32; stride * (height + 2)];
+    let mut out = vec![0u8; luma.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let e = y * stride + x + 2;
+            let value = match method {
+                Method::None | Method::Bayer => luma[y * width + x],
+                Method::FloydSteinberg | Method::Atkinson => {
+                    let diffused = (errors[e] + 8).div_euclid(16);
+                    (luma[y * width + x] as i32 + diffused).clamp(0, 255) as u8
+                }
+            };
+            let level = match method {
+                Method::Bayer => {
+                    let low = below(value);
+                    if low == 15 {
+                        low
+                    } else {
+                        let gap = (lightness[low + 1] - lightness[low]) as u32;
+                        let threshold = BAYER_4X4[y % 4][x % 4] as u32 * 2 + 1;
+                        if (value - lightness[low]) as u32 * 32 > threshold * gap {
+                            low + 1
+                        } else {
+                            low
+                        }
+                    }
+                }
+                _ => nearest(value),
+            };
+            out[y * width + x] = level as u8;
+
+            let error = (value as i32 - lightness[level] as i32) * 16;
+            match method {
+                Method::FloydSteinberg => {
+                    errors[e + 1] += error * 7 / 16;
+                    errors[e + stride - 1] += error * 3 / 16;
+                    errors[e + stride] += error * 5 / 16;
+                    errors[e + stride + 1] += error / 16;
+                }
+                Method::Atkinson => {
+                    let share = error / 8;
+                    for offset in [1, 2, stride - 1, stride, stride + 1, 2 * stride] {
+                        errors[e + offset] += share;
+                    }
+                }
+                Method::None | Method::Bayer => {}
+            }
+        }
+    }
+    out
+}
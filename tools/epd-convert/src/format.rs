@@ -0,0 +1,244 @@
+//! Encoder for the 4bpp image format, see `Image4bpp` in the driver.
+
+const MAGIC: &[u8; 2] = b"E4";
+const VERSION: u8 = 1;
+const FLAG_TRANSPARENT: u8 = 0x01;
+const FLAG_RLE: u8 = 0x02;
+
+/// Panel levels of an image, one byte per pixel from 0 (black) to 15 (white).
+pub struct Levels {
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<u8>,
+}
+
+impl Levels {
+    fn row(&self, y: usize) -> &[u8] {
+        let width = self.width as usize;
+        &self.pixels[y * width..(y + 1) * width]
+    }
+}
+
+/// Serialize the image, optionally with a transparency key and RLE.
+pub fn encode(levels: &Levels, transparent: Option<u8>, rle: bool) -> Vec<u8> {
+    let mut flags = 0;
+    if transparent.is_some() {
+        flags |= FLAG_TRANSPARENT;
+    }
+    if rle {
+        flags |= FLAG_RLE;
+    }
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.push(flags);
+    out.extend_from_slice(&levels.width.to_le_bytes());
+    out.extend_from_slice(&levels.height.to_le_bytes());
+    out.push(transparent.unwrap_or(0) & 0x0F);
+    out.push(0);
+    for y in 0..levels.height as usize {
+        let packed = pack_row(levels.row(y));
+        if rle {
+            packbits(&packed, &mut out);
+        } else {
+            out.extend_from_slice(&packed);
+        }
+    }
+    out
+}
+
+/// Pack a row of levels into bytes, even pixels in the low nibble.
+fn pack_row(row: &[u8]) -> Vec<u8> {
+    row.chunks(2)
+        .map(|pair| (pair[0] & 0x0F) | (pair.get(1).copied().unwrap_or(0) & 0x0F) << 4)
+        .collect()
+}
+
+/// PackBits: headers below 128 are followed by `n + 1` literal bytes, others
+/// repeat the next byte `n - 126` times.
+fn packbits(data: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    let mut literal_start = 0;
+    let flush_literal = |out: &mut Vec<u8>, literal: &[u8]| {
+        for chunk in literal.chunks(128) {
+            out.push(chunk.len() as u8 - 1);
+            out.extend_from_slice(chunk);
+        }
+    };
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(129)
+            .take_while(|&&byte| byte == data[i])
+            .count();
+        if run >= 3 {
+            flush_literal(out, &data[literal_start..i]);
+            out.push((run + 126) as u8);
+            out.push(data[i]);
+            i += run;
+            literal_start = i;
+        } else {
+            i += run;
+        }
+    }
+    flush_literal(out, &data[literal_start..]);
+}
+
+/// Rust source with the serialized image as a byte array, to be parsed with
+/// `Image4bpp::from_bytes`.
+pub fn to_rust(name: &str, source: &str, bytes: &[u8]) -> String {
+    let mut out = format!(
+        "// Generated by epd-convert from {source}\n\
+         // let image = Image4bpp::from_bytes(&{name}).unwrap();\n\
+         pub const {name}: [u8; {}] = [\n",
+        bytes.len()
+    );
+    for chunk in bytes.chunks(16) {
+        let line: Vec<String> = chunk.iter().map(|byte| format!("0x{byte:02x}")).collect();
+        out.push_str("    ");
+        out.push_str(&line.join(", "));
+        out.push_str(",\n");
+    }
+    out.push_str("];\n");
+    out
+}
+
+/// Binary PGM of the lightness the panel shows for each level.
+pub fn to_pgm(levels: &Levels, lightness: &[u8; 16]) -> Vec<u8> {
+    let mut out = format!("P5\n{} {}\n255\n", levels.width, levels.height).into_bytes();
+    out.extend(
+        levels
+            .pixels
+            .iter()
+            .map(|&level| lightness[level as usize & 0x0F]),
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inverse of [packbits], as in `Image4bpp` of the driver.
+    fn unpackbits(data: &[u8], len: usize) -> (Vec<u8>, usize) {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while out.len() < len {
+            let header = data[i] as usize;
+            if header < 128 {
+                out.extend_from_slice(&data[i + 1..i + 2 + header]);
+                i += header + 2;
+            } else {
+                out.extend(std::iter::repeat_n(data[i + 1], header - 126));
+                i += 2;
+            }
+        }
+        assert_eq!(out.len(), len, "run across rows");
+        (out, i)
+    }
+
+    /// Header fields and the levels of a serialized image.
+    fn decode(bytes: &[u8]) -> (u8, u16, u16, u8, Vec<u8>) {
+        assert_eq!(&bytes[..3], b"E4\x01");
+        let flags = bytes[3];
+        let width = u16::from_le_bytes([bytes[4], bytes[5]]);
+        let height = u16::from_le_bytes([bytes[6], bytes[7]]);
+        let stride = (width as usize).div_ceil(2);
+        let mut data = &bytes[10..];
+        let mut pixels = Vec::new();
+        for _ in 0..height {
+            let row = if flags & FLAG_RLE != 0 {
+                let (row, len) = unpackbits(data, stride);
+                data = &data[len..];
+                row
+            } else {
+                let (row, rest) = data.split_at(stride);
+                data = rest;
+                row.to_vec()
+            };
+            let levels = row.iter().flat_map(|byte| [byte & 0x0F, byte >> 4]);
+            pixels.extend(levels.take(width as usize));
+        }
+        assert!(data.is_empty(), "trailing data");
+        (flags, width, height, bytes[8], pixels)
+    }
+
+    fn levels(width: u16, height: u16) -> Levels {
+        // runs, literals and odd widths
+        let pixels = (0..width as usize * height as usize)
+            .map(|i| match i % 97 {
+                0..=40 => 3,
+                41..=70 => (i * 7 % 16) as u8,
+                _ => 15,
+            })
+            .collect();
+        Levels {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for (width, height) in [(1, 1), (7, 3), (64, 4), (301, 5)] {
+            let levels = levels(width, height);
+            for (transparent, rle) in [(None, false), (Some(15), false), (Some(3), true)] {
+                let (flags, w, h, key, pixels) = decode(&encode(&levels, transparent, rle));
+                assert_eq!((w, h), (width, height));
+                assert_eq!(flags & FLAG_RLE != 0, rle);
+                assert_eq!(flags & FLAG_TRANSPARENT != 0, transparent.is_some());
+                assert_eq!(key, transparent.unwrap_or(0));
+                assert_eq!(pixels, levels.pixels);
+            }
+        }
+    }
+
+    #[test]
+    fn packbits_round_trip() {
+        let long_run = vec![0xAA; 300];
+        let literal: Vec<u8> = (0..300).map(|i| (i * 13 % 251) as u8).collect();
+        let mixed: Vec<u8> = [1, 1, 2, 2, 2, 3, 4, 4, 4, 4, 5, 5]
+            .into_iter()
+            .chain(std::iter::repeat_n(9, 129))
+            .chain([7, 8])
+            .collect();
+        for data in [vec![], vec![5], vec![5, 5], long_run, literal, mixed] {
+            let mut packed = Vec::new();
+            packbits(&data, &mut packed);
+            let (unpacked, len) = unpackbits(&packed, data.len());
+            assert_eq!(unpacked, data);
+            assert_eq!(len, packed.len());
+        }
+        // runs are compressed, literals grow by one header per 128 bytes
+        let mut packed = Vec::new();
+        packbits(&[0xAA; 300], &mut packed);
+        assert_eq!(packed, [0xFF, 0xAA, 0xFF, 0xAA, 0xA8, 0xAA]);
+        packed.clear();
+        packbits(&(0..200).map(|i| i as u8).collect::<Vec<_>>(), &mut packed);
+        assert_eq!(packed.len(), 202);
+    }
+
+    #[test]
+    fn pgm_preview() {
+        let levels = Levels {
+            width: 3,
+            height: 2,
+            pixels: vec![0, 1, 15, 8, 7, 0],
+        };
+        let lightness = [
+            0, 29, 51, 70, 89, 106, 123, 139, 154, 169, 184, 199, 213, 227, 241, 255,
+        ];
+        let pgm = to_pgm(&levels, &lightness);
+        let header = b"P5\n3 2\n255\n";
+        assert_eq!(&pgm[..header.len()], header);
+        assert_eq!(&pgm[header.len()..], [0, 29, 255, 154, 139, 0]);
+    }
+
+    #[test]
+    fn rust_source() {
+        let source = to_rust("ICON", "icon.png", &[0x45, 0x34, 1, 2]);
+        assert!(source.contains("// Generated by epd-convert from icon.png\n"));
+        assert!(source.contains("pub const ICON: [u8; 4] = [\n    0x45, 0x34, 0x01, 0x02,\n];\n"));
+    }
+}
@@ -0,0 +1,272 @@
+//! Converts PNG, JPEG and BMP images into the 4bpp image format of the
+//! `lilygo-epd47` driver, either as a binary file for `include_bytes!` or as
+//! Rust source.
+//!
+//! ```text
+//! epd-convert photo.jpg -o photo.e4 --width 960 --dither floyd-steinberg --preview photo.pgm
+//! epd-convert icon.png -o icon.rs --rle --transparent 15
+//! ```
+
+use std::{error::Error, fs, path::PathBuf};
+
+use clap::{Parser, ValueEnum};
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+
+mod dither;
+mod format;
+
+use crate::{
+    dither::{Method, Panel},
+    format::Levels,
+};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Rotation {
+    #[value(name = "0")]
+    Deg0,
+    #[value(name = "90")]
+    Deg90,
+    #[value(name = "180")]
+    Deg180,
+    #[value(name = "270")]
+    Deg270,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    /// Serialized image for `include_bytes!` and `Image4bpp::from_bytes`.
+    E4,
+    /// Rust source with the serialized image as a `const` byte array.
+    Rust,
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Input image (PNG, JPEG or BMP).
+    input: PathBuf,
+    /// Output file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Output format, guessed from the output extension by default.
+    #[arg(short, long)]
+    format: Option<Format>,
+    /// Resize to this width. Keeps the aspect ratio if no height is given.
+    #[arg(long)]
+    width: Option<u32>,
+    /// Resize to this height. Keeps the aspect ratio if no width is given.
+    #[arg(long)]
+    height: Option<u32>,
+    /// Clockwise rotation, applied before resizing.
+    #[arg(long, value_enum, default_value = "0")]
+    rotate: Rotation,
+    /// Dithering method.
+    #[arg(long, value_enum, default_value = "floyd-steinberg")]
+    dither: Method,
+    /// Gray levels of the panel used for dithering and the preview.
+    #[arg(long, value_enum, default_value = "ed047tc1")]
+    panel: Panel,
+    /// Swap black and white.
+    #[arg(long)]
+    invert: bool,
+    /// Transparent level (0-15). Pixels with less than 50% alpha are mapped to
+    /// it, opaque pixels of this level to the neighboring level.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..16))]
+    transparent: Option<u8>,
+    /// Compress the rows with RLE.
+    #[arg(long)]
+    rle: bool,
+    /// Name of the array in Rust output, derived from the output file name by
+    /// default.
+    #[arg(long)]
+    name: Option<String>,
+    /// Write a PGM preview of what the panel will show.
+    #[arg(long)]
+    preview: Option<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let image = image::open(&args.input)
+        .map_err(|err| format!("failed to read {}: {err}", args.input.display()))?;
+    let image = match args.rotate {
+        Rotation::Deg0 => image,
+        Rotation::Deg90 => image.rotate90(),
+        Rotation::Deg180 => image.rotate180(),
+        Rotation::Deg270 => image.rotate270(),
+    };
+    let image = resize(image, args.width, args.height);
+    let (width, height) = image.dimensions();
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(format!("image too large: {width}x{height}").into());
+    }
+
+    let rgba = image.to_rgba8();
+    let luma: Vec<u8> = rgba
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, _] = pixel.0;
+            let luma = ((r as u32 * 77 + g as u32 * 150 + b as u32 * 29 + 128) >> 8) as u8;
+            if args.invert {
+                255 - luma
+            } else {
+                luma
+            }
+        })
+        .collect();
+    let lightness = args.panel.lightness();
+    let mut pixels = dither::dither(&luma, width as usize, args.dither, lightness);
+    if let Some(key) = args.transparent {
+        let alpha = rgba.pixels().map(|pixel| pixel.0[3]);
+        let remapped = apply_transparency(&mut pixels, alpha, key, lightness);
+        if remapped > 0 {
+            eprintln!(
+                "warning: {remapped} opaque pixels of the transparent level {key} changed to {}",
+                neighbor(key, lightness)
+            );
+        }
+    }
+    let levels = Levels {
+        width: width as u16,
+        height: height as u16,
+        pixels,
+    };
+
+    let bytes = format::encode(&levels, args.transparent, args.rle);
+    let extension = args.output.extension().and_then(|ext| ext.to_str());
+    let output_format = match (args.format, extension) {
+        (Some(format), _) => format,
+        (None, Some("rs")) => Format::Rust,
+        (None, _) => Format::E4,
+    };
+    match output_format {
+        Format::E4 => fs::write(&args.output, &bytes)?,
+        Format::Rust => {
+            let name = args
+                .name
+                .clone()
+                .unwrap_or_else(|| const_name(&args.output));
+            let source = args
+                .input
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+            fs::write(&args.output, format::to_rust(&name, &source, &bytes))?;
+        }
+    }
+    if let Some(preview) = &args.preview {
+        fs::write(preview, format::to_pgm(&levels, lightness))?;
+    }
+    println!(
+        "{} -> {}: {width}x{height}, {} bytes",
+        args.input.display(),
+        args.output.display(),
+        bytes.len()
+    );
+    Ok(())
+}
+
+/// Map pixels with less than 50% alpha to the transparent `key` and opaque
+/// pixels of that level to its neighbor, so they stay visible. Returns the
+/// number of changed opaque pixels.
+fn apply_transparency(
+    pixels: &mut [u8],
+    alpha: impl Iterator<Item = u8>,
+    key: u8,
+    lightness: &[u8; 16],
+) -> usize {
+    let mut remapped = 0;
+    for (level, alpha) in pixels.iter_mut().zip(alpha) {
+        if alpha < 128 {
+            *level = key;
+        } else if *level == key {
+            *level = neighbor(key, lightness);
+            remapped += 1;
+        }
+    }
+    remapped
+}
+
+/// The level with the closest lightness next to `level`.
+fn neighbor(level: u8, lightness: &[u8; 16]) -> u8 {
+    let distance = |other: u8| lightness[level as usize].abs_diff(lightness[other as usize]);
+    match level {
+        0 => 1,
+        15 => 14,
+        _ if distance(level + 1) < distance(level - 1) => level + 1,
+        _ => level - 1,
+    }
+}
+
+fn resize(image: DynamicImage, width: Option<u32>, height: Option<u32>) -> DynamicImage {
+    let (w, h) = image.dimensions();
+    let (width, height) = match (width, height) {
+        (None, None) => return image,
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (width, (h as u64 * width as u64 / w as u64).max(1) as u32),
+        (None, Some(height)) => ((w as u64 * height as u64 / h as u64).max(1) as u32, height),
+    };
+    image.resize_exact(width, height, FilterType::Lanczos3)
+}
+
+/// `SCREAMING_SNAKE_CASE` name from the output file stem.
+fn const_name(path: &std::path::Path) -> String {
+    let stem = path.file_stem().map_or_else(
+        || "IMAGE".into(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+    let mut name: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn transparency_keeps_opaque_pixels_visible() {
+        let lightness = Panel::Ed047tc1.lightness();
+        let mut pixels = vec![15, 15, 3, 0, 15];
+        let alpha = [255, 0, 255, 127, 128];
+        let remapped = apply_transparency(&mut pixels, alpha.into_iter(), 15, lightness);
+        assert_eq!(pixels, [14, 15, 3, 15, 14]);
+        assert_eq!(remapped, 2);
+
+        let mut pixels = vec![0, 5, 5];
+        let remapped = apply_transparency(&mut pixels, [255, 255, 0].into_iter(), 5, lightness);
+        assert_eq!(pixels, [0, 4, 5]);
+        assert_eq!(remapped, 1);
+    }
+
+    #[test]
+    fn neighbors() {
+        let ed047tc1 = Panel::Ed047tc1.lightness();
+        assert_eq!(neighbor(0, ed047tc1), 1);
+        assert_eq!(neighbor(15, ed047tc1), 14);
+        // the steps get smaller towards white
+        assert_eq!(neighbor(1, ed047tc1), 2);
+        assert_eq!(neighbor(2, ed047tc1), 3);
+        // ties go to the darker level
+        assert_eq!(neighbor(8, Panel::Linear.lightness()), 7);
+    }
+
+    #[test]
+    fn const_names() {
+        assert_eq!(const_name(Path::new("assets/rust-logo.rs")), "RUST_LOGO");
+        assert_eq!(const_name(Path::new("8bit.rs")), "_8BIT");
+    }
+}
@@ -0,0 +1,369 @@
+//! Encoder for the 4bpp font format, see `Font4bpp` in the driver.
+
+const MAGIC: &[u8; 2] = b"F4";
+const VERSION: u8 = 1;
+
+/// A rasterized glyph.
+pub struct Glyph {
+    pub width: u8,
+    pub height: u8,
+    /// Offset of the left edge from the pen position.
+    pub left: i16,
+    /// Offset of the top edge above the baseline.
+    pub top: i16,
+    /// Advance in 1/16 pixels.
+    pub advance: u16,
+    /// Coverage from 0 to 15, one byte per pixel.
+    pub coverage: Vec<u8>,
+}
+
+/// A rasterized font.
+pub struct Font {
+    pub line_height: u16,
+    pub ascent: u16,
+    pub descent: u16,
+    /// Glyphs sorted by character.
+    pub glyphs: Vec<(char, Glyph)>,
+    /// Glyph indices and advance adjustment in 1/16 pixels, sorted.
+    pub kerning: Vec<(u16, u16, i16)>,
+    pub default_glyph: Option<u16>,
+}
+
+impl Font {
+    /// Index of the glyph of a character.
+    pub fn index(&self, c: char) -> Option<u16> {
+        self.glyphs
+            .binary_search_by_key(&c, |(c, _)| *c)
+            .ok()
+            .map(|index| index as u16)
+    }
+
+    /// Glyph of a character, or the default glyph.
+    fn glyph(&self, c: char) -> Option<(u16, &Glyph)> {
+        let index = self.index(c).or(self.default_glyph)?;
+        Some((index, &self.glyphs[index as usize].1))
+    }
+
+    fn kerning(&self, left: u16, right: u16) -> i16 {
+        self.kerning
+            .binary_search_by_key(&(left, right), |&(left, right, _)| (left, right))
+            .map_or(0, |index| self.kerning[index].2)
+    }
+
+    /// Ranges of consecutive characters as first character, length and index
+    /// of the first glyph.
+    fn ranges(&self) -> Vec<(u32, u16, u16)> {
+        let mut ranges: Vec<(u32, u16, u16)> = Vec::new();
+        for (index, (c, _)) in self.glyphs.iter().enumerate() {
+            match ranges.last_mut() {
+                Some((first, len, _)) if *first + *len as u32 == *c as u32 && *len < u16::MAX => {
+                    *len += 1
+                }
+                _ => ranges.push((*c as u32, 1, index as u16)),
+            }
+        }
+        ranges
+    }
+}
+
+/// Serialize the font.
+pub fn encode(font: &Font) -> Vec<u8> {
+    let ranges = font.ranges();
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.push(0);
+    for value in [
+        font.line_height,
+        font.ascent,
+        font.descent,
+        ranges.len() as u16,
+        font.glyphs.len() as u16,
+        font.kerning.len() as u16,
+        font.default_glyph.unwrap_or(u16::MAX),
+        0,
+    ] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    for (first, len, glyph) in ranges {
+        out.extend_from_slice(&first.to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&glyph.to_le_bytes());
+    }
+    let mut bitmaps = Vec::new();
+    for (_, glyph) in &font.glyphs {
+        out.extend_from_slice(&(bitmaps.len() as u32).to_le_bytes());
+        out.push(glyph.width);
+        out.push(glyph.height);
+        out.extend_from_slice(&glyph.left.to_le_bytes());
+        out.extend_from_slice(&glyph.top.to_le_bytes());
+        out.extend_from_slice(&glyph.advance.to_le_bytes());
+        if glyph.width > 0 {
+            for row in glyph.coverage.chunks(glyph.width as usize) {
+                bitmaps.extend(row.chunks(2).map(|pair| {
+                    (pair[0] & 0x0F) | (pair.get(1).copied().unwrap_or(0) & 0x0F) << 4
+                }));
+            }
+        }
+    }
+    for (left, right, adjustment) in &font.kerning {
+        out.extend_from_slice(&left.to_le_bytes());
+        out.extend_from_slice(&right.to_le_bytes());
+        out.extend_from_slice(&adjustment.to_le_bytes());
+    }
+    out.extend_from_slice(&bitmaps);
+    out
+}
+
+/// Rust source with the serialized font as a byte array, to be parsed with
+/// `Font4bpp::from_bytes`.
+pub fn to_rust(name: &str, source: &str, bytes: &[u8]) -> String {
+    let mut out = format!(
+        "// Generated by epd-font from {source}\n\
+         // let font = Font4bpp::from_bytes(&{name}).unwrap();\n\
+         pub static {name}: [u8; {}] = [\n",
+        bytes.len()
+    );
+    for chunk in bytes.chunks(16) {
+        let line: Vec<String> = chunk.iter().map(|byte| format!("0x{byte:02x}")).collect();
+        out.push_str("    ");
+        out.push_str(&line.join(", "));
+        out.push_str(",\n");
+    }
+    out.push_str("];\n");
+    out
+}
+
+/// Binary PGM of the sample text drawn in black on white like
+/// `Display::draw_text`, one line per line of the sample.
+pub fn to_pgm(font: &Font, sample: &str) -> Vec<u8> {
+    let margin = font.line_height as i32 / 2;
+    let lines: Vec<&str> = sample.lines().collect();
+    let pen_width = |line: &str| {
+        let mut pen = 0i32;
+        let mut previous = None;
+        for c in line.chars() {
+            let Some((index, glyph)) = font.glyph(c) else {
+                continue;
+            };
+            if let Some(previous) = previous {
+                pen += font.kerning(previous, index) as i32;
+            }
+            pen += glyph.advance as i32;
+            previous = Some(index);
+        }
+        (pen + 8) >> 4
+    };
+    let width = lines.iter().map(|line| pen_width(line)).max().unwrap_or(0) + 2 * margin;
+    let height = lines.len() as i32 * font.line_height as i32 + 2 * margin;
+    let mut levels = vec![15u8; (width * height) as usize];
+    for (number, line) in lines.iter().enumerate() {
+        let baseline = margin + number as i32 * font.line_height as i32 + font.ascent as i32;
+        let mut pen = margin << 4;
+        let mut previous = None;
+        for c in line.chars() {
+            let Some((index, glyph)) = font.glyph(c) else {
+                continue;
+            };
+            if let Some(previous) = previous {
+                pen += font.kerning(previous, index) as i32;
+            }
+            let left = ((pen + 8) >> 4) + glyph.left as i32;
+            let top = baseline - glyph.top as i32;
+            for (i, &coverage) in glyph.coverage.iter().enumerate() {
+                let x = left + (i % glyph.width as usize) as i32;
+                let y = top + (i / glyph.width as usize) as i32;
+                if coverage == 0 || x < 0 || y < 0 || x >= width || y >= height {
+                    continue;
+                }
+                let level = &mut levels[(y * width + x) as usize];
+                *level = ((*level as u16 * (15 - coverage as u16) + 7) / 15) as u8;
+            }
+            pen += glyph.advance as i32;
+            previous = Some(index);
+        }
+    }
+    let mut out = format!("P5\n{width} {height}\n255\n").into_bytes();
+    out.extend(levels.iter().map(|&level| level * 17));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    /// Glyphs of every coverage level with odd and even widths, a negative
+    /// left edge, two kerning pairs and `?` for missing characters.
+    fn font() -> Font {
+        let glyph = |width: u8, height: u8, left: i16, top: i16, advance: u16, seed: usize| Glyph {
+            width,
+            height,
+            left,
+            top,
+            advance,
+            coverage: (0..width as usize * height as usize)
+                .map(|i| ((i * 7 + seed) % 16) as u8)
+                .collect(),
+        };
+        Font {
+            line_height: 14,
+            ascent: 10,
+            descent: 4,
+            glyphs: vec![
+                (' ', glyph(0, 0, 0, 0, 64, 0)),
+                ('?', glyph(5, 8, 1, 8, 112, 3)),
+                ('A', glyph(7, 9, 0, 9, 120, 0)),
+                ('B', glyph(6, 9, 1, 9, 128, 5)),
+                ('C', glyph(6, 9, -1, 9, 104, 9)),
+                ('x', glyph(5, 6, 0, 6, 88, 2)),
+                ('€', glyph(8, 10, 0, 9, 152, 11)),
+            ],
+            kerning: vec![(2, 3, -24), (5, 5, -8)],
+            default_glyph: Some(1),
+        }
+    }
+
+    const SAMPLE: &str = "AB C?x€xZ\nCAxx";
+
+    fn testdata(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../../testdata/font")
+            .join(name)
+    }
+
+    #[test]
+    fn header_and_tables() {
+        let bytes = encode(&font());
+        assert_eq!(&bytes[..4], b"F4\x01\x00");
+        let field = |i: usize| u16::from_le_bytes([bytes[4 + 2 * i], bytes[5 + 2 * i]]);
+        // line height, ascent, descent, ranges, glyphs, pairs, default
+        assert_eq!(
+            (0..7).map(field).collect::<Vec<_>>(),
+            [14, 10, 4, 5, 7, 2, 1]
+        );
+
+        // consecutive characters share a range
+        let ranges: Vec<(u32, u16, u16)> = bytes[20..60]
+            .chunks(8)
+            .map(|range| {
+                (
+                    u32::from_le_bytes(range[..4].try_into().unwrap()),
+                    u16::from_le_bytes([range[4], range[5]]),
+                    u16::from_le_bytes([range[6], range[7]]),
+                )
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (0x20, 1, 0),
+                (0x3F, 1, 1),
+                (0x41, 3, 2),
+                (0x78, 1, 5),
+                (0x20AC, 1, 6)
+            ]
+        );
+
+        // bitmaps follow each other, rows are padded to full bytes
+        let glyphs = &bytes[60..60 + 7 * 12];
+        let offsets: Vec<u32> = glyphs
+            .chunks(12)
+            .map(|glyph| u32::from_le_bytes(glyph[..4].try_into().unwrap()))
+            .collect();
+        assert_eq!(offsets, [0, 0, 24, 60, 87, 114, 132]);
+        assert_eq!(
+            &glyphs[4 * 12 + 4..4 * 12 + 12],
+            [6, 9, 0xFF, 0xFF, 9, 0, 104, 0]
+        );
+
+        let pairs = &bytes[144..156];
+        assert_eq!(pairs, [2, 0, 3, 0, 0xE8, 0xFF, 5, 0, 5, 0, 0xF8, 0xFF]);
+        assert_eq!(bytes.len(), 156 + 172);
+    }
+
+    #[test]
+    fn nibble_packing() {
+        let font = Font {
+            line_height: 4,
+            ascent: 3,
+            descent: 1,
+            glyphs: vec![(
+                'a',
+                Glyph {
+                    width: 3,
+                    height: 2,
+                    left: 0,
+                    top: 2,
+                    advance: 64,
+                    coverage: vec![1, 2, 3, 4, 5, 0x1F],
+                },
+            )],
+            kerning: Vec::new(),
+            default_glyph: None,
+        };
+        let bytes = encode(&font);
+        assert_eq!(u16::from_le_bytes([bytes[16], bytes[17]]), u16::MAX);
+        // first pixel in the low nibble, coverage above 15 is masked
+        assert_eq!(&bytes[bytes.len() - 4..], [0x21, 0x03, 0x54, 0x0F]);
+    }
+
+    #[test]
+    fn preview() {
+        let font = Font {
+            line_height: 4,
+            ascent: 3,
+            descent: 1,
+            glyphs: vec![(
+                'a',
+                Glyph {
+                    width: 2,
+                    height: 2,
+                    left: 0,
+                    top: 2,
+                    advance: 40,
+                    coverage: vec![15, 5, 0, 10],
+                },
+            )],
+            kerning: vec![(0, 0, -8)],
+            default_glyph: None,
+        };
+        // margin of 2 pixels, the second glyph at (32 + 40 - 8 + 8) / 16 = 4
+        let pgm = to_pgm(&font, "aa");
+        let header = b"P5\n9 8\n255\n";
+        assert_eq!(&pgm[..header.len()], header);
+        let levels: Vec<u8> = pgm[header.len()..].iter().map(|level| level / 17).collect();
+        let row = |y: usize| &levels[y * 9..(y + 1) * 9];
+        assert_eq!(row(3), [15, 15, 0, 10, 0, 10, 15, 15, 15]);
+        assert_eq!(row(4), [15, 15, 15, 5, 15, 5, 15, 15, 15]);
+        assert!(levels[..27]
+            .iter()
+            .chain(&levels[45..])
+            .all(|&level| level == 15));
+    }
+
+    /// The driver draws the fixture with `Display::draw_text` and compares it
+    /// with the preview. With `UPDATE_GOLDEN` set in the environment the
+    /// fixtures are written instead, review the changes before committing
+    /// them.
+    #[test]
+    fn fixture() {
+        let font = font();
+        let (bytes, pgm) = (encode(&font), to_pgm(&font, SAMPLE));
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::create_dir_all(testdata("")).unwrap();
+            fs::write(testdata("sample.f4"), &bytes).unwrap();
+            fs::write(testdata("sample.pgm"), &pgm).unwrap();
+            return;
+        }
+        assert!(
+            fs::read(testdata("sample.f4")).unwrap() == bytes,
+            "sample.f4 differs"
+        );
+        assert!(
+            fs::read(testdata("sample.pgm")).unwrap() == pgm,
+            "sample.pgm differs"
+        );
+    }
+}
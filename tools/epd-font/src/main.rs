@@ -0,0 +1,391 @@
+//! Converts TTF and OTF fonts into the anti-aliased 4bpp font format of the
+//! `lilygo-epd47` driver, either as a binary file for `include_bytes!` or as
+//! Rust source.
+//!
+//! ```text
+//! epd-font DejaVuSerif.ttf -o serif-24.f4 --size 24 --preview serif-24.pgm
+//! epd-font Inter.otf -o inter.rs --size 32 --ranges 0x20-0x7e,0x400-0x4ff --chars "€…"
+//! epd-font NotoSansSC-Regular.otf -o labels.f4 --size 24 --ranges 0x20-0x7e --chars-file labels.txt
+//! ```
+
+use std::{collections::HashSet, error::Error, fs, path::PathBuf};
+
+use ab_glyph::{Font as _, FontRef, PxScale, ScaleFont};
+use clap::{Parser, ValueEnum};
+use ttf_parser::{
+    gpos::{PairAdjustment, PositioningSubtable},
+    kern,
+    opentype_layout::Coverage,
+    Face,
+    GlyphId,
+    Tag,
+};
+
+mod format;
+
+use crate::format::{Font, Glyph};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    /// Serialized font for `include_bytes!` and `Font4bpp::from_bytes`.
+    F4,
+    /// Rust source with the serialized font as a `static` byte array.
+    Rust,
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Input font (TTF or OTF).
+    input: PathBuf,
+    /// Output file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Output format, guessed from the output extension by default.
+    #[arg(short, long)]
+    format: Option<Format>,
+    /// Font size in pixels per em.
+    #[arg(short, long)]
+    size: f32,
+    /// Unicode ranges to include, e.g. `0x20-0x7e,0xa0-0xff`.
+    #[arg(
+        long,
+        default_value = "0x20-0x7e,0xa0-0xff",
+        value_delimiter = ',',
+        value_parser = parse_ranges
+    )]
+    ranges: Vec<(u32, u32)>,
+    /// Additional characters to include.
+    #[arg(long, default_value = "")]
+    chars: String,
+    /// Include all characters of a UTF-8 text file, e.g. the labels of an
+    /// application, to subset large CJK fonts.
+    #[arg(long)]
+    chars_file: Option<PathBuf>,
+    /// Line height in pixels, from the font metrics by default.
+    #[arg(long)]
+    line_height: Option<u16>,
+    /// Gamma applied to the coverage. Values above 1 make thin strokes darker.
+    #[arg(long, default_value = "1.0")]
+    gamma: f32,
+    /// Leave out the kerning pairs.
+    #[arg(long)]
+    no_kerning: bool,
+    /// Name of the array in Rust output, derived from the output file name by
+    /// default.
+    #[arg(long)]
+    name: Option<String>,
+    /// Write a PGM preview of the sample text.
+    #[arg(long)]
+    preview: Option<PathBuf>,
+    /// Sample text of the preview, `\n` starts a new line.
+    #[arg(
+        long,
+        default_value = "The quick brown fox jumps over the lazy dog.\\nAVATAR Toyota 0123456789"
+    )]
+    sample: String,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let data = fs::read(&args.input)
+        .map_err(|err| format!("failed to read {}: {err}", args.input.display()))?;
+    let font = FontRef::try_from_slice(&data)
+        .map_err(|err| format!("failed to parse {}: {err}", args.input.display()))?;
+    let face = Face::parse(&data, 0)?;
+
+    // ab_glyph scales the height from ascent to descent to the given size
+    let units_per_em = font.units_per_em().ok_or("font without units per em")?;
+    let scale = PxScale::from(args.size * font.height_unscaled() / units_per_em);
+    let scaled = font.as_scaled(scale);
+
+    let chars_file = match &args.chars_file {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))?,
+        None => String::new(),
+    };
+    let mut chars: Vec<char> = args
+        .ranges
+        .iter()
+        .flat_map(|&(first, last)| (first..=last).filter_map(char::from_u32))
+        .chain(args.chars.chars())
+        .chain(chars_file.chars().filter(|c| !c.is_control()))
+        .filter(|&c| font.glyph_id(c).0 != 0)
+        .collect();
+    chars.sort_unstable();
+    chars.dedup();
+    if chars.len() >= u16::MAX as usize {
+        return Err(format!("too many glyphs: {}", chars.len()).into());
+    }
+
+    let mut glyphs = Vec::with_capacity(chars.len());
+    for &c in &chars {
+        glyphs.push((c, rasterize(&font, scale, c, args.gamma)?));
+    }
+    let ids: Vec<GlyphId> = chars.iter().map(|&c| GlyphId(font.glyph_id(c).0)).collect();
+    let mut kerning = Vec::new();
+    if !args.no_kerning {
+        let lookups = kerning_lookups(&face);
+        let firsts = kerning_firsts(&face, &lookups);
+        for (left, &first) in ids.iter().enumerate() {
+            if firsts
+                .as_ref()
+                .is_some_and(|firsts| !firsts.contains(&first.0))
+            {
+                continue;
+            }
+            for (right, &second) in ids.iter().enumerate() {
+                let unscaled = match font
+                    .kern_unscaled(ab_glyph::GlyphId(first.0), ab_glyph::GlyphId(second.0))
+                {
+                    0.0 => gpos_kerning(&face, &lookups, first, second) as f32,
+                    kern => kern,
+                };
+                let adjustment = (scaled.h_scale_factor() * unscaled * 16.0).round() as i16;
+                if adjustment != 0 {
+                    kerning.push((left as u16, right as u16, adjustment));
+                }
+            }
+        }
+    }
+    if kerning.len() > u16::MAX as usize {
+        return Err(format!("too many kerning pairs: {}", kerning.len()).into());
+    }
+
+    let ascent = scaled.ascent().ceil();
+    let descent = (-scaled.descent()).ceil();
+    let line_height = args
+        .line_height
+        .unwrap_or((scaled.ascent() - scaled.descent() + scaled.line_gap()).ceil() as u16);
+    let index = |c: char| chars.binary_search(&c).ok().map(|index| index as u16);
+    let font = Font {
+        line_height,
+        ascent: ascent as u16,
+        descent: descent as u16,
+        default_glyph: index('\u{FFFD}').or_else(|| index('?')),
+        glyphs,
+        kerning,
+    };
+
+    let bytes = format::encode(&font);
+    let extension = args.output.extension().and_then(|ext| ext.to_str());
+    let output_format = match (args.format, extension) {
+        (Some(format), _) => format,
+        (None, Some("rs")) => Format::Rust,
+        (None, _) => Format::F4,
+    };
+    match output_format {
+        Format::F4 => fs::write(&args.output, &bytes)?,
+        Format::Rust => {
+            let name = args
+                .name
+                .clone()
+                .unwrap_or_else(|| const_name(&args.output));
+            let source = args
+                .input
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+            fs::write(&args.output, format::to_rust(&name, &source, &bytes))?;
+        }
+    }
+    if let Some(preview) = &args.preview {
+        let sample = args.sample.replace("\\n", "\n");
+        fs::write(preview, format::to_pgm(&font, &sample))?;
+    }
+    println!(
+        "{} -> {}: {} glyphs, {} kerning pairs, {} bytes",
+        args.input.display(),
+        args.output.display(),
+        font.glyphs.len(),
+        font.kerning.len(),
+        bytes.len()
+    );
+    Ok(())
+}
+
+/// Rasterize a glyph with the pen at the origin.
+fn rasterize(font: &FontRef, scale: PxScale, c: char, gamma: f32) -> Result<Glyph, String> {
+    let scaled = font.as_scaled(scale);
+    let id = font.glyph_id(c);
+    let advance = (scaled.h_advance(id) * 16.0)
+        .round()
+        .clamp(0.0, u16::MAX as f32) as u16;
+    let Some(outline) = font.outline_glyph(id.with_scale(scale)) else {
+        // blank glyph like the space
+        return Ok(Glyph {
+            width: 0,
+            height: 0,
+            left: 0,
+            top: 0,
+            advance,
+            coverage: Vec::new(),
+        });
+    };
+    let bounds = outline.px_bounds();
+    let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+    if width > u8::MAX as u32 || height > u8::MAX as u32 {
+        return Err(format!("glyph {c:?} too large: {width}x{height}"));
+    }
+    let mut coverage = vec![0u8; (width * height) as usize];
+    outline.draw(|x, y, value| {
+        let value = value.clamp(0.0, 1.0).powf(1.0 / gamma);
+        coverage[(y * width + x) as usize] = (value * 15.0).round() as u8;
+    });
+    Ok(Glyph {
+        width: width as u8,
+        height: height as u8,
+        left: bounds.min.x as i16,
+        top: -bounds.min.y as i16,
+        advance,
+        coverage,
+    })
+}
+
+/// Pair adjustment lookups of the `kern` features in the GPOS table.
+fn kerning_lookups(face: &Face) -> Vec<u16> {
+    let Some(gpos) = face.tables().gpos else {
+        return Vec::new();
+    };
+    let mut lookups: Vec<u16> = gpos
+        .features
+        .into_iter()
+        .filter(|feature| feature.tag == Tag::from_bytes(b"kern"))
+        .flat_map(|feature| feature.lookup_indices)
+        .collect();
+    lookups.sort_unstable();
+    lookups.dedup();
+    lookups
+}
+
+/// Glyphs starting a pair in the `kern` table or the GPOS pair adjustments,
+/// so the pair search skips all other glyphs. `None` if the `kern` table
+/// can't be enumerated.
+fn kerning_firsts(face: &Face, lookups: &[u16]) -> Option<HashSet<u16>> {
+    let mut firsts = HashSet::new();
+    if let Some(table) = face.tables().kern {
+        for subtable in table.subtables {
+            match subtable.format {
+                kern::Format::Format0(subtable) => {
+                    firsts.extend(subtable.pairs.into_iter().map(|pair| pair.left().0))
+                }
+                _ => return None,
+            }
+        }
+    }
+    if let Some(gpos) = face.tables().gpos {
+        for lookup in lookups.iter().filter_map(|&index| gpos.lookups.get(index)) {
+            for subtable in lookup.subtables.into_iter::<PositioningSubtable>() {
+                let PositioningSubtable::Pair(pair) = subtable else {
+                    continue;
+                };
+                match pair.coverage() {
+                    Coverage::Format1 { glyphs } => {
+                        firsts.extend(glyphs.into_iter().map(|glyph| glyph.0))
+                    }
+                    Coverage::Format2 { records } => {
+                        for record in records {
+                            firsts.extend(record.start.0..=record.end.0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Some(firsts)
+}
+
+/// Advance adjustment of the first glyph of a pair in font units, from the
+/// first matching pair adjustment subtable.
+fn gpos_kerning(face: &Face, lookups: &[u16], first: GlyphId, second: GlyphId) -> i16 {
+    let Some(gpos) = face.tables().gpos else {
+        return 0;
+    };
+    for lookup in lookups.iter().filter_map(|&index| gpos.lookups.get(index)) {
+        for subtable in lookup.subtables.into_iter::<PositioningSubtable>() {
+            let PositioningSubtable::Pair(pair) = subtable else {
+                continue;
+            };
+            let Some(coverage) = pair.coverage().get(first) else {
+                continue;
+            };
+            let values = match pair {
+                PairAdjustment::Format1 { sets, .. } => {
+                    sets.get(coverage).and_then(|set| set.get(second))
+                }
+                PairAdjustment::Format2 {
+                    classes, matrix, ..
+                } => matrix.get((classes.0.get(first), classes.1.get(second))),
+            };
+            if let Some((values, _)) = values {
+                return values.x_advance;
+            }
+        }
+    }
+    0
+}
+
+/// Parse comma separated ranges like `0x20-0x7e,0xa0`.
+fn parse_ranges(ranges: &str) -> Result<(u32, u32), String> {
+    let parse = |value: &str| {
+        let value = value.trim();
+        match value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix("U+"))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .map_err(|err| format!("invalid code point {value:?}: {err}"))
+    };
+    match ranges.split_once('-') {
+        Some((first, last)) => Ok((parse(first)?, parse(last)?)),
+        None => parse(ranges).map(|value| (value, value)),
+    }
+}
+
+/// `SCREAMING_SNAKE_CASE` name from the output file stem.
+fn const_name(path: &std::path::Path) -> String {
+    let stem = path
+        .file_stem()
+        .map_or_else(|| "FONT".into(), |stem| stem.to_string_lossy().into_owned());
+    let mut name: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_ranges("0x20-0x7e"), Ok((0x20, 0x7E)));
+        assert_eq!(parse_ranges("U+20AC"), Ok((0x20AC, 0x20AC)));
+        assert_eq!(parse_ranges(" 65 - 0x5A "), Ok((65, 0x5A)));
+        assert!(parse_ranges("0x20-").is_err());
+        assert!(parse_ranges("0xZZ").is_err());
+        assert!(parse_ranges("a-z").is_err());
+    }
+
+    #[test]
+    fn const_names() {
+        assert_eq!(const_name(Path::new("fonts/serif-24.rs")), "SERIF_24");
+        assert_eq!(const_name(Path::new("Noto Sans.rs")), "NOTO_SANS");
+        assert_eq!(const_name(Path::new("24px.rs")), "_24PX");
+        assert_eq!(const_name(Path::new("/")), "FONT");
+    }
+}